@@ -0,0 +1,1180 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Plays matches between two GTP engines, like the `twogtp` tool of GoGui.
+
+The arbiter asks the engines for moves with `genmove`, checks the moves
+for legality on its own [`Board`](../board/struct.Board.html) and relays
+them to the opponent with `play`. A game ends by resignation, two
+consecutive passes, a timeout or a forfeit (illegal move, error response or
+a crashed engine). After two passes the game is scored with `final_score`
+by the referee engine, if one is configured, or else by the players
+themselves.
+
+Games can be adjudicated with the settings of
+[`GameConfig`](struct.GameConfig.html): by a `ResignRule`, when the referee
+sees one side ahead for a number of moves, and by a move limit. The reason
+of an adjudication or forfeit is stored in the comment of the SGF file.
+
+Matches between deterministic engines should use openings, see
+[`Openings`](enum.Openings.html).
+
+```no_run
+use gtp::arbiter::{EngineSpec, Match, MatchConfig};
+
+let cfg = MatchConfig::new(
+    EngineSpec::parse("gnugo --mode gtp --level 1").unwrap(),
+    EngineSpec::parse("gnugo --mode gtp --level 10").unwrap());
+
+let mut m = Match::new(cfg).unwrap();
+let result = m.run(|game| println!("{}", game)).unwrap();
+print!("{}", result);
+```
+
+The `gtp-arbiter` binary provides this on the command line.
+*/
+
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::board::Board;
+use super::controller;
+use super::controller::Engine;
+use super::opening;
+use super::opening::Opening;
+use super::sgf::GameRecord;
+use super::sprt::Sprt;
+use super::sprt::Status;
+use super::Color;
+use super::Command;
+use super::EntityParser;
+use super::Entity;
+use super::Response;
+
+/// The command line of an engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineSpec {
+    pub cmd:    String,
+    pub args:   Vec<String>,
+}
+
+impl EngineSpec {
+    /// Splits a command line at whitespace. Arguments containing
+    /// whitespace can be quoted with `"` or `'`.
+    ///
+    /// ```
+    /// let spec = gtp::arbiter::EngineSpec::parse("katago gtp -config 'my cfg.cfg'").unwrap();
+    /// assert_eq!(spec.cmd, "katago");
+    /// assert_eq!(spec.args, vec!["gtp", "-config", "my cfg.cfg"]);
+    /// ```
+    pub fn parse(cmdline: &str) -> Result<EngineSpec, String> {
+        let mut words = vec![];
+        let mut word  = None;
+        let mut quote = None;
+
+        for c in cmdline.chars() {
+            match (quote, c) {
+                (Some(q), c) if c == q => { quote = None; },
+                (Some(_), c)           => word.get_or_insert_with(String::new).push(c),
+                (None, '"') | (None, '\'') => {
+                    quote = Some(c);
+                    word.get_or_insert_with(String::new);
+                },
+                (None, c) if c.is_whitespace() => {
+                    if let Some(w) = word.take() { words.push(w); }
+                },
+                (None, c) => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        if quote.is_some() {
+            return Err(format!("unterminated quote in '{}'", cmdline));
+        }
+        if let Some(w) = word.take() { words.push(w); }
+        if words.is_empty() {
+            return Err(String::from("empty engine command line"));
+        }
+
+        let cmd = words.remove(0);
+        Ok(EngineSpec { cmd, args: words })
+    }
+
+    /// Creates and starts the engine.
+    pub fn start(&self) -> Result<Engine, Error> {
+        let args : Vec<&str> = self.args.iter().map(|s| &s[..]).collect();
+        let mut engine = Engine::new(&self.cmd, &args);
+        engine.start().map_err(|e| Error::Engine(self.cmd.clone(), e))?;
+        Ok(engine)
+    }
+}
+
+impl std::fmt::Display for EngineSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.cmd)?;
+        for a in self.args.iter() { write!(f, " {}", a)?; }
+        Ok(())
+    }
+}
+
+/// Errors that prevent a game from being played.
+#[derive(Debug)]
+pub enum Error {
+    /// Starting or talking to the named engine failed.
+    Engine(String, controller::Error),
+    /// The named engine rejected a command needed to set up
+    /// or score the game.
+    Rejected(String, String),
+    /// Writing the SGF file failed.
+    Io(std::io::Error),
+    /// An opening contains an illegal move, or no balanced random
+    /// opening was found.
+    Opening(String),
+    /// The settings can't be used together, like adjudication
+    /// without a referee.
+    Config(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Engine(name, e)     => write!(f, "engine '{}': {:?}", name, e),
+            Error::Rejected(name, msg) => write!(f, "engine '{}' rejected {}", name, msg),
+            Error::Io(e)               => write!(f, "I/O error: {}", e),
+            Error::Opening(msg)        => write!(f, "bad opening {}", msg),
+            Error::Config(msg)         => write!(f, "bad settings: {}", msg),
+        }
+    }
+}
+
+/// How a game was won.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WinReason {
+    Resign,
+    /// Won by the given number of points.
+    Score(f32),
+    /// The opponent did not answer in time.
+    Time,
+    /// The opponent played an illegal move, answered with an error,
+    /// sent garbage or crashed.
+    Forfeit,
+}
+
+/// The outcome of a game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameResult {
+    Win(Color, WinReason),
+    /// Jigo, a tie.
+    Draw,
+    /// The result could not be determined,
+    /// for instance because the engines disagree on the score.
+    Unknown,
+}
+
+impl GameResult {
+    /// Parses a result in SGF notation, as returned by `final_score`.
+    ///
+    /// ```
+    /// use gtp::Color;
+    /// use gtp::arbiter::{GameResult, WinReason};
+    /// assert_eq!(GameResult::parse("W+3.5"), Some(GameResult::Win(Color::W, WinReason::Score(3.5))));
+    /// assert_eq!(GameResult::parse("B+Resign"), Some(GameResult::Win(Color::B, WinReason::Resign)));
+    /// assert_eq!(GameResult::parse("0"), Some(GameResult::Draw));
+    /// ```
+    pub fn parse(s: &str) -> Option<GameResult> {
+        let s = s.trim();
+        if s == "0" || s.eq_ignore_ascii_case("draw") { return Some(GameResult::Draw); }
+
+        let mut parts = s.splitn(2, '+');
+        let color =
+            match &parts.next()?.to_uppercase()[..] {
+                "B" => Color::B,
+                "W" => Color::W,
+                _   => return None,
+            };
+        let reason =
+            match &parts.next()?.to_uppercase()[..] {
+                "R" | "RESIGN"  => WinReason::Resign,
+                "T" | "TIME"    => WinReason::Time,
+                "F" | "FORFEIT" => WinReason::Forfeit,
+                score => {
+                    let score = score.parse::<f32>().ok()?;
+                    if score == 0.0 { return Some(GameResult::Draw); }
+                    WinReason::Score(score)
+                },
+            };
+        Some(GameResult::Win(color, reason))
+    }
+
+    /// Returns the winner, if any.
+    pub fn winner(&self) -> Option<Color> {
+        match self {
+            GameResult::Win(c, _) => Some(*c),
+            _                     => None,
+        }
+    }
+}
+
+/// Formats the result in SGF notation.
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GameResult::Win(c, reason) => {
+                let c = color_letter(*c);
+                match reason {
+                    WinReason::Resign   => write!(f, "{}+R", c),
+                    WinReason::Score(s) => write!(f, "{}+{}", c, s),
+                    WinReason::Time     => write!(f, "{}+T", c),
+                    WinReason::Forfeit  => write!(f, "{}+F", c),
+                }
+            },
+            GameResult::Draw    => write!(f, "0"),
+            GameResult::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+/// How the referee estimates who is ahead during a game.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Estimate {
+    /// A command answering with a score like `W+12.5`, for instance
+    /// `estimate_score` of GNU Go or `final_score`.
+    Score(String),
+    /// A command answering with the winning probability of black,
+    /// either between 0 and 1, or in percent with a `%` suffix.
+    /// The probability must be from black's point of view, winrates
+    /// of the side to move, like `lz-analyze` reports, aren't supported.
+    Winrate(String),
+}
+
+/// Adjudicates a game as won by resignation, when the referee
+/// sees one side ahead for a number of consecutive moves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResignRule {
+    pub estimate:   Estimate,
+    /// The lead in points, or the winning probability between 0 and 1,
+    /// the leading side needs.
+    pub threshold:  f32,
+    /// The number of consecutive moves the lead must be held.
+    pub moves:      usize,
+}
+
+impl ResignRule {
+    /// Parses `score,<points>,<moves>[,<command>]` or
+    /// `winrate,<probability>,<moves>[,<command>]`. The commands default
+    /// to `estimate_score` and `winrate`.
+    ///
+    /// ```
+    /// use gtp::arbiter::{Estimate, ResignRule};
+    /// let rule = ResignRule::parse("score,30,4").unwrap();
+    /// assert_eq!(rule.estimate, Estimate::Score(String::from("estimate_score")));
+    /// assert_eq!((rule.threshold, rule.moves), (30.0, 4));
+    /// ```
+    pub fn parse(s: &str) -> Result<ResignRule, String> {
+        let f : Vec<&str> = s.splitn(4, ',').map(|f| f.trim()).collect();
+        if f.len() < 3 {
+            return Err(format!("expected <score|winrate>,<threshold>,<moves>[,<command>] in '{}'", s));
+        }
+
+        let estimate =
+            match (f[0], f.get(3)) {
+                ("score",   cmd) => Estimate::Score(cmd.unwrap_or(&"estimate_score").to_string()),
+                ("winrate", cmd) => Estimate::Winrate(cmd.unwrap_or(&"winrate").to_string()),
+                _ => return Err(format!("unknown estimate '{}', expected score or winrate", f[0])),
+            };
+        let threshold = f[1].parse::<f32>().map_err(|_| format!("bad threshold '{}'", f[1]))?;
+        let moves     = f[2].parse::<usize>().map_err(|_| format!("bad move count '{}'", f[2]))?;
+
+        Ok(ResignRule { estimate, threshold, moves })
+    }
+}
+
+/// Settings for a single game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameConfig {
+    pub size:               usize,
+    pub komi:               f32,
+    /// Maximum time an engine may think about a move.
+    pub move_timeout:       Duration,
+    /// Maximum time for the responses to other commands.
+    pub command_timeout:    Duration,
+    /// Adjudicates decided games, needs a referee.
+    pub resign:             Option<ResignRule>,
+    /// Stops the game after this many moves. It is scored by the
+    /// referee, or a draw without referee.
+    pub max_moves:          Option<usize>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            size:               19,
+            komi:               7.5,
+            move_timeout:       Duration::from_secs(60),
+            command_timeout:    Duration::from_secs(10),
+            resign:             None,
+            max_moves:          None,
+        }
+    }
+}
+
+/// A finished game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    pub result:     GameResult,
+    /// Explains forfeits and unknown results, empty otherwise.
+    pub note:       String,
+    pub record:     GameRecord,
+    pub elapsed:    Duration,
+}
+
+/// The engines taking part in a game, with their names for error messages.
+pub struct Players<'a> {
+    pub black:      (&'a str, &'a mut Engine),
+    pub white:      (&'a str, &'a mut Engine),
+    pub referee:    Option<(&'a str, &'a mut Engine)>,
+}
+
+fn cmd_text(cmd: &Command) -> String { cmd.to_string().trim().to_string() }
+
+/// Sends a command which must succeed.
+fn request(name: &str, engine: &mut Engine, cmd: Command, timeout: Duration)
+    -> Result<String, Error>
+{
+    let text = cmd_text(&cmd);
+    match engine.send_wait(cmd, timeout) {
+        Ok(Response::Result((_, t))) => Ok(t),
+        Ok(Response::Error((_, msg))) =>
+            Err(Error::Rejected(name.to_string(), format!("'{}': {}", text, msg))),
+        Err(e) => Err(Error::Engine(name.to_string(), e)),
+    }
+}
+
+fn setup(name: &str, engine: &mut Engine, cfg: &GameConfig) -> Result<(), Error> {
+    let t = cfg.command_timeout;
+    request(name, engine, Command::cmd("boardsize", |e| e.i(cfg.size as u32)), t)?;
+    request(name, engine, Command::new("clear_board"), t)?;
+    request(name, engine, Command::cmd("komi", |e| e.f(cfg.komi)), t)?;
+    Ok(())
+}
+
+/// Asks the engine for its name and version, used for the SGF files.
+pub fn engine_name(engine: &mut Engine, timeout: Duration) -> Option<String> {
+    let name = match engine.send_wait(Command::new("name"), timeout) {
+        Ok(Response::Result((_, n))) => n.trim().to_string(),
+        _ => return None,
+    };
+    match engine.send_wait(Command::new("version"), timeout) {
+        Ok(Response::Result((_, v))) if !v.trim().is_empty() =>
+            Some(format!("{} {}", name, v.trim())),
+        _ => Some(name),
+    }
+}
+
+/// Asks for the score with `final_score`, `None` if the engine
+/// doesn't know or answers garbage.
+fn final_score(engine: &mut Engine, timeout: Duration) -> Option<GameResult> {
+    match engine.send_wait(Command::new("final_score"), timeout) {
+        Ok(Response::Result((_, s))) => GameResult::parse(&s),
+        _                            => None,
+    }
+}
+
+/// Parses a winning probability between 0 and 1, or in percent
+/// with a `%` suffix.
+fn parse_winrate(text: &str) -> Option<f32> {
+    let wr =
+        match text.strip_suffix('%') {
+            Some(pct) => pct.parse::<f32>().ok()? / 100.0,
+            None      => text.parse::<f32>().ok()?,
+        };
+    if (0.0..=1.0).contains(&wr) { Some(wr) } else { None }
+}
+
+/// Asks the referee who is ahead, returns the leading color and its lead.
+fn estimate(referee: &mut Engine, estimate: &Estimate, timeout: Duration) -> Option<(Color, f32)> {
+    let cmd =
+        match estimate { Estimate::Score(cmd) | Estimate::Winrate(cmd) => cmd };
+    let text = match referee.send_wait(Command::new(cmd), timeout) {
+        Ok(Response::Result((_, t))) => t,
+        _                            => return None,
+    };
+    let first = text.split_whitespace().next()?;
+
+    match estimate {
+        Estimate::Score(_) => {
+            match GameResult::parse(first)? {
+                GameResult::Win(c, WinReason::Score(s)) => Some((c, s)),
+                _                                       => None,
+            }
+        },
+        Estimate::Winrate(_) => {
+            let wr = parse_winrate(first)?;
+            if wr >= 0.5 { Some((Color::B, wr)) } else { Some((Color::W, 1.0 - wr)) }
+        },
+    }
+}
+
+fn color_letter(c: Color) -> &'static str { if c == Color::B { "B" } else { "W" } }
+
+impl<'a> Players<'a> {
+    fn player(&mut self, color: Color) -> (&str, &mut Engine) {
+        match color {
+            Color::B => (self.black.0, &mut *self.black.1),
+            Color::W => (self.white.0, &mut *self.white.1),
+        }
+    }
+
+    /// Determines the result when the move limit is reached.
+    fn score_limit(&mut self, cfg: &GameConfig, limit: usize) -> (GameResult, String) {
+        let note = format!("adjudicated: move limit of {} reached", limit);
+        match self.referee.as_mut() {
+            Some((_, referee)) => {
+                match final_score(referee, cfg.command_timeout) {
+                    Some(res) => (res, note),
+                    None      => (GameResult::Unknown, note + ", referee could not score"),
+                }
+            },
+            None => (GameResult::Draw, note),
+        }
+    }
+
+    /// Determines the result after two passes.
+    fn score(&mut self, cfg: &GameConfig) -> (GameResult, String) {
+        let t = cfg.command_timeout;
+        if let Some((_, referee)) = self.referee.as_mut() {
+            return match final_score(referee, t) {
+                Some(res) => (res, String::new()),
+                None      => (GameResult::Unknown, String::from("referee could not score")),
+            };
+        }
+
+        let b = final_score(self.black.1, t);
+        let w = final_score(self.white.1, t);
+        match (b, w) {
+            (Some(b), Some(w)) if b == w => (b, String::new()),
+            (Some(b), Some(w)) =>
+                (GameResult::Unknown, format!("engines disagree on score: {} / {}", b, w)),
+            (Some(r), None) | (None, Some(r)) => (r, String::new()),
+            (None, None) =>
+                (GameResult::Unknown, String::from("no engine could score")),
+        }
+    }
+}
+
+const NO_REFEREE : &str = "adjudication needs a referee";
+
+/// Plays a game between two started engines. Fails with `Error::Config`
+/// if `cfg.resign` is set without a referee.
+pub fn play_game(players: &mut Players, cfg: &GameConfig) -> Result<Game, Error> {
+    play_game_from(players, cfg, None)
+}
+
+/// Plays a game between two started engines, starting with the moves of
+/// the opening. The name of the opening is stored in the SGF file.
+pub fn play_game_from(players: &mut Players, cfg: &GameConfig, opening: Option<&Opening>)
+    -> Result<Game, Error>
+{
+    if cfg.resign.is_some() && players.referee.is_none() {
+        return Err(Error::Config(String::from(NO_REFEREE)));
+    }
+    let start = Instant::now();
+
+    setup(players.black.0, players.black.1, cfg)?;
+    setup(players.white.0, players.white.1, cfg)?;
+    if let Some((name, referee)) = players.referee.as_mut() {
+        setup(name, referee, cfg)?;
+    }
+
+    let mut record = GameRecord::new(cfg.size, cfg.komi);
+    let mut board  = Board::new(cfg.size);
+    let mut color  = Color::B;
+
+    if let Some(opening) = opening {
+        record.opening = opening.name.clone();
+        for (c, v) in opening.moves.iter() {
+            board.play(*c, *v).map_err(|e| {
+                Error::Opening(format!("{}: move {}: {}", opening.name, Entity::Vertex(*v), e))
+            })?;
+            record.moves.push((*c, *v));
+
+            let play = Command::cmd("play", |e| e.mv(*c == Color::W, *v));
+            request(players.black.0, players.black.1, play.clone(), cfg.command_timeout)?;
+            request(players.white.0, players.white.1, play.clone(), cfg.command_timeout)?;
+            if let Some((name, referee)) = players.referee.as_mut() {
+                request(name, referee, play, cfg.command_timeout)?;
+            }
+            color = c.opponent();
+        }
+    }
+
+    let mut passes = 0;
+    // The leading color and for how many moves it has been leading.
+    let mut lead   = (Color::B, 0);
+
+    let (result, note) = loop {
+        let opp = color.opponent();
+        let forfeit = |note: String| (GameResult::Win(opp, WinReason::Forfeit), note);
+
+        let (name, mover) = players.player(color);
+        let name = name.to_string();
+        let resp = mover.send_wait(Command::cmd("genmove", |e| e.color(color == Color::W)),
+                                   cfg.move_timeout);
+        let text = match resp {
+            Ok(Response::Result((_, t)))  => t,
+            Ok(Response::Error((_, msg))) =>
+                break forfeit(format!("{}: genmove failed: {}", name, msg)),
+            Err(controller::Error::Timeout { .. }) =>
+                break (GameResult::Win(opp, WinReason::Time),
+                       format!("{}: no move in time", name)),
+            Err(e) =>
+                break forfeit(format!("{}: {:?}", name, e)),
+        };
+
+        if text.trim().eq_ignore_ascii_case("resign") {
+            break (GameResult::Win(opp, WinReason::Resign), String::new());
+        }
+
+        let mut ep = EntityParser::new(&text);
+        let vertex = match ep.vertex().result().as_ref().map(|v| &v[..]) {
+            Some([Entity::Vertex(v)]) => *v,
+            _ => break forfeit(format!("{}: bad move '{}'", name, text.trim())),
+        };
+
+        if let Err(illegal) = board.play(color, vertex) {
+            break forfeit(format!("{}: illegal move {}: {}",
+                                  name, Entity::Vertex(vertex), illegal));
+        }
+        record.moves.push((color, vertex));
+
+        let play = Command::cmd("play", |e| e.mv(color == Color::W, vertex));
+        let (opp_name, other) = players.player(opp);
+        let opp_name = opp_name.to_string();
+        if let Err(e) = request(&opp_name, other, play.clone(), cfg.command_timeout) {
+            break (GameResult::Win(color, WinReason::Forfeit), e.to_string());
+        }
+        if let Some((name, referee)) = players.referee.as_mut() {
+            request(name, referee, play, cfg.command_timeout)?;
+        }
+
+        if vertex == (0, 0) {
+            passes += 1;
+            if passes == 2 { break players.score(cfg); }
+        } else {
+            passes = 0;
+        }
+
+        if let Some(limit) = cfg.max_moves {
+            if record.moves.len() >= limit { break players.score_limit(cfg, limit); }
+        }
+
+        if let (Some(rule), Some((_, referee))) = (&cfg.resign, players.referee.as_mut()) {
+            lead =
+                match estimate(referee, &rule.estimate, cfg.command_timeout) {
+                    Some((c, v)) if v >= rule.threshold =>
+                        if c == lead.0 { (c, lead.1 + 1) } else { (c, 1) },
+                    _ => (lead.0, 0),
+                };
+            if lead.1 >= rule.moves.max(1) {
+                break (GameResult::Win(lead.0, WinReason::Resign),
+                       format!("adjudicated: {} ahead for {} moves",
+                               color_letter(lead.0), lead.1));
+            }
+        }
+
+        color = opp;
+    };
+
+    record.result = result.to_string();
+    record.date   = super::sgf::today();
+    if !note.is_empty() { record.comment = note.clone(); }
+
+    Ok(Game { result, note, record, elapsed: start.elapsed() })
+}
+
+/// Plays the opening on the referee and returns its estimate of
+/// who is ahead.
+fn opening_lead(referee: &mut Engine, opening: &Opening, cfg: &GameConfig)
+    -> Result<Option<(Color, f32)>, Error>
+{
+    let name = "referee";
+    setup(name, referee, cfg)?;
+    for (c, v) in opening.moves.iter() {
+        request(name, referee, Command::cmd("play", |e| e.mv(*c == Color::W, *v)),
+                cfg.command_timeout)?;
+    }
+
+    let est =
+        match &cfg.resign {
+            Some(rule) => rule.estimate.clone(),
+            None       => Estimate::Score(String::from("estimate_score")),
+        };
+    Ok(estimate(referee, &est, cfg.command_timeout))
+}
+
+/// How many random openings are tried to find a balanced one.
+const BALANCE_TRIES : usize = 20;
+
+/// Where the openings of a match come from. Each opening is played in two
+/// consecutive games with swapped colors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Openings {
+    /// The games start on the empty board.
+    None,
+    /// The openings are used in order, and repeated if there are
+    /// more games.
+    List(Vec<Opening>),
+    /// Random openings with the given number of moves, see
+    /// [`opening::random()`](../opening/fn.random.html). With a referee
+    /// and `max_lead` an opening is only used if the referee's estimated
+    /// lead is at most `max_lead`, in points or as winning probability
+    /// like for `ResignRule`. Up to 20 openings are tried, if none of
+    /// them is balanced the match fails with `Error::Opening`.
+    Random { moves: usize, seed: u64, max_lead: Option<f32> },
+}
+
+/// Settings for a match between two engines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchConfig {
+    /// The engines, the first one plays black in the first game.
+    pub engines:    [EngineSpec; 2],
+    /// Engine used for scoring the games.
+    pub referee:    Option<EngineSpec>,
+    pub games:      usize,
+    /// Swap the colors after each game. Always done with openings.
+    pub alternate:  bool,
+    pub game:       GameConfig,
+    /// Directory for the SGF files, none are written if not set.
+    pub sgf_dir:    Option<PathBuf>,
+    /// The SGF files are named `<prefix>-<game number>.sgf`.
+    pub sgf_prefix: String,
+    /// Stops the match as soon as this test of the first engine
+    /// against the second is decided.
+    pub sprt:       Option<Sprt>,
+    pub openings:   Openings,
+}
+
+impl MatchConfig {
+    /// Creates the config for a match of 10 games, alternating colors.
+    pub fn new(first: EngineSpec, second: EngineSpec) -> MatchConfig {
+        MatchConfig {
+            engines:    [first, second],
+            referee:    None,
+            games:      10,
+            alternate:  true,
+            game:       GameConfig::default(),
+            sgf_dir:    None,
+            sgf_prefix: String::from("game"),
+            sprt:       None,
+            openings:   Openings::None,
+        }
+    }
+}
+
+/// Summary of a game of a match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSummary {
+    /// Counted from 1.
+    pub number:     usize,
+    /// Names of the black and white player.
+    pub black:      String,
+    pub white:      String,
+    /// Index into `MatchConfig::engines` of the black player.
+    pub black_idx:  usize,
+    pub result:     GameResult,
+    pub note:       String,
+    pub moves:      usize,
+    pub elapsed:    Duration,
+    /// The log-likelihood ratio of the SPRT after this game.
+    pub llr:        Option<f64>,
+    /// The name of the opening, empty without opening.
+    pub opening:    String,
+}
+
+impl GameSummary {
+    /// Returns the index of the winning engine, if any.
+    pub fn winner_idx(&self) -> Option<usize> {
+        match self.result.winner()? {
+            Color::B => Some(self.black_idx),
+            Color::W => Some(1 - self.black_idx),
+        }
+    }
+}
+
+impl std::fmt::Display for GameSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:>4} {:<20} {:<20} {:<7} {:>5} {:>8.1}s",
+               self.number, self.black, self.white,
+               self.result.to_string(), self.moves,
+               self.elapsed.as_secs_f64())?;
+        if let Some(llr) = self.llr { write!(f, " LLR {:.2}", llr)?; }
+        if !self.opening.is_empty() { write!(f, " opening {}", self.opening)?; }
+        if !self.note.is_empty() { write!(f, " {}", self.note)?; }
+        Ok(())
+    }
+}
+
+/// The games of a match.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MatchResult {
+    pub names:  [String; 2],
+    pub games:  Vec<GameSummary>,
+    /// The SPRT with the results of the games.
+    pub sprt:   Option<Sprt>,
+}
+
+impl MatchResult {
+    /// Number of wins of the engine with the given index,
+    /// optionally only with the given color.
+    pub fn wins(&self, idx: usize, color: Option<Color>) -> usize {
+        self.games.iter().filter(|g| {
+            g.winner_idx() == Some(idx)
+            && color.map(|c| (c == Color::B) == (g.black_idx == idx)).unwrap_or(true)
+        }).count()
+    }
+
+    /// Number of games played by the engine with the given color.
+    pub fn games_as(&self, idx: usize, color: Color) -> usize {
+        self.games.iter()
+            .filter(|g| (color == Color::B) == (g.black_idx == idx))
+            .count()
+    }
+}
+
+impl std::fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{:>4} {:<20} {:<20} {:<7} {:>5} {:>9}",
+                 "Game", "Black", "White", "Result", "Moves", "Time")?;
+        for g in self.games.iter() { writeln!(f, "{}", g)?; }
+
+        for (i, name) in self.names.iter().enumerate() {
+            writeln!(f, "{}: {}/{} wins (black {}/{}, white {}/{})",
+                     name, self.wins(i, None), self.games.len(),
+                     self.wins(i, Some(Color::B)), self.games_as(i, Color::B),
+                     self.wins(i, Some(Color::W)), self.games_as(i, Color::W))?;
+        }
+        if let Some(sprt) = &self.sprt { writeln!(f, "{}", sprt)?; }
+        Ok(())
+    }
+}
+
+type Starter = dyn Fn(&EngineSpec) -> Result<Engine, Error> + Send;
+
+/// A running match between two engines.
+pub struct Match {
+    cfg:        MatchConfig,
+    engines:    Vec<Engine>,
+    referee:    Option<Engine>,
+    result:     MatchResult,
+    rng:        opening::Rng,
+    /// The opening of the current pair of games.
+    opening:    Option<Opening>,
+    /// Starts an engine again after a forfeit or a loss on time.
+    start:      Box<Starter>,
+    /// Whether the engines are restarted before the next game.
+    restart:    bool,
+}
+
+impl Match {
+    /// Starts the engines of the match.
+    pub fn new(cfg: MatchConfig) -> Result<Match, Error> {
+        if cfg.game.resign.is_some() && cfg.referee.is_none() {
+            return Err(Error::Config(String::from(NO_REFEREE)));
+        }
+        let engines = vec![cfg.engines[0].start()?, cfg.engines[1].start()?];
+        let referee = match &cfg.referee {
+            Some(spec) => Some(spec.start()?),
+            None       => None,
+        };
+        Ok(Match::with_engines(cfg, engines, referee))
+    }
+
+    /// Creates a match with already started engines, which must be
+    /// in the order of `cfg.engines`.
+    pub fn with_engines(cfg: MatchConfig, mut engines: Vec<Engine>, referee: Option<Engine>) -> Match {
+        let t = cfg.game.command_timeout;
+        let names = [
+            engine_name(&mut engines[0], t).unwrap_or_else(|| cfg.engines[0].cmd.clone()),
+            engine_name(&mut engines[1], t).unwrap_or_else(|| cfg.engines[1].cmd.clone()),
+        ];
+
+        Match {
+            engines,
+            referee,
+            result: MatchResult { names, games: Vec::new(), sprt: cfg.sprt.clone() },
+            rng:    opening::Rng::new(match cfg.openings {
+                        Openings::Random { seed, .. } => seed,
+                        _                             => 0,
+                    }),
+            opening: None,
+            start:   Box::new(|spec| spec.start()),
+            restart: false,
+            cfg,
+        }
+    }
+
+    /// After a forfeit or a loss on time the engines may be in any
+    /// state, or still thinking, so both are stopped and started again
+    /// before the next game. This starts them with `start` instead of
+    /// `EngineSpec::start()`, for engines that aren't processes.
+    pub fn restart_with<S>(mut self, start: S) -> Self
+        where S: Fn(&EngineSpec) -> Result<Engine, Error> + Send + 'static
+    {
+        self.start = Box::new(start);
+        self
+    }
+
+    /// Returns true if all games were played or the SPRT is decided.
+    pub fn is_finished(&self) -> bool {
+        self.result.games.len() >= self.cfg.games
+        || self.result.sprt.as_ref().map(|s| s.status() != Status::Continue).unwrap_or(false)
+    }
+
+    /// Returns the games played so far.
+    pub fn result(&self) -> &MatchResult { &self.result }
+
+    /// Returns the opening of the game with the given number.
+    fn next_opening(&mut self, number: usize) -> Result<Option<Opening>, Error> {
+        // The second game of a pair uses the same opening.
+        if (number - 1) % 2 == 1 { return Ok(self.opening.clone()); }
+
+        let pair = (number - 1) / 2;
+        let next =
+            match &self.cfg.openings {
+                Openings::None                    => None,
+                Openings::List(l) if l.is_empty() => None,
+                Openings::List(l)                 => Some(l[pair % l.len()].clone()),
+                Openings::Random { moves, max_lead, .. } => {
+                    let name = format!("random-{}", pair + 1);
+                    let size = self.cfg.game.size;
+                    let mut o = opening::random(&name, &mut self.rng, size, *moves);
+
+                    if let (Some(max), Some(referee)) = (max_lead, self.referee.as_mut()) {
+                        for tries in 1..=BALANCE_TRIES {
+                            match opening_lead(referee, &o, &self.cfg.game)? {
+                                Some((_, lead)) if lead > *max => (),
+                                _ => break,
+                            }
+                            if tries == BALANCE_TRIES {
+                                return Err(Error::Opening(format!(
+                                    "{}: none of {} random openings has a lead of at most {}",
+                                    name, BALANCE_TRIES, max)));
+                            }
+                            o = opening::random(&name, &mut self.rng, size, *moves);
+                        }
+                    }
+                    Some(o)
+                },
+            };
+
+        self.opening = next.clone();
+        Ok(next)
+    }
+
+    /// Plays the next game, writes its SGF file and returns its summary.
+    /// After a forfeit or a loss on time both engines are restarted
+    /// first, see [`restart_with()`](#method.restart_with).
+    pub fn play_next(&mut self) -> Result<GameSummary, Error> {
+        if self.restart {
+            let grace = self.cfg.game.command_timeout;
+            for (engine, spec) in self.engines.iter_mut().zip(self.cfg.engines.iter()) {
+                let _ = engine.stop(grace);
+                *engine = (self.start)(spec)?;
+            }
+            self.restart = false;
+        }
+
+        let number    = self.result.games.len() + 1;
+        let opening   = self.next_opening(number)?;
+        let swap      = self.cfg.alternate || opening.is_some();
+        let black_idx = if swap { (number - 1) % 2 } else { 0 };
+        let names     = self.result.names.clone();
+
+        let (first, second) = self.engines.split_at_mut(1);
+        let (black, white) =
+            if black_idx == 0 { (&mut first[0], &mut second[0]) }
+            else              { (&mut second[0], &mut first[0]) };
+
+        let mut players = Players {
+            black:   (&names[black_idx], black),
+            white:   (&names[1 - black_idx], white),
+            referee: self.referee.as_mut().map(|r| ("referee", r)),
+        };
+        let mut game = play_game_from(&mut players, &self.cfg.game, opening.as_ref())?;
+        if let GameResult::Win(_, WinReason::Forfeit) | GameResult::Win(_, WinReason::Time) = game.result {
+            self.restart = true;
+        }
+
+        game.record.black = names[black_idx].clone();
+        game.record.white = names[1 - black_idx].clone();
+        if let Some(dir) = &self.cfg.sgf_dir {
+            let path = dir.join(format!("{}-{}.sgf", self.cfg.sgf_prefix, number));
+            game.record.save(&path).map_err(Error::Io)?;
+        }
+
+        let tested = if black_idx == 0 { Color::B } else { Color::W };
+        let llr =
+            self.result.sprt.as_mut().map(|sprt| {
+                sprt.add_result(&game.result, tested);
+                sprt.llr()
+            });
+
+        let summary = GameSummary {
+            number,
+            black:      game.record.black.clone(),
+            white:      game.record.white.clone(),
+            black_idx,
+            result:     game.result,
+            note:       game.note,
+            moves:      game.record.moves.len(),
+            elapsed:    game.elapsed,
+            llr,
+            opening:    game.record.opening.clone(),
+        };
+        self.result.games.push(summary.clone());
+        Ok(summary)
+    }
+
+    /// Plays all remaining games and calls `on_game` after each game.
+    pub fn run<F>(&mut self, mut on_game: F) -> Result<MatchResult, Error>
+        where F: FnMut(&GameSummary)
+    {
+        while !self.is_finished() {
+            let summary = self.play_next()?;
+            on_game(&summary);
+        }
+        Ok(self.result.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    fn setup_script() -> Script {
+        Script::new()
+            .expect("boardsize 9").respond("")
+            .expect("clear_board").respond("")
+            .expect("komi 7").respond("")
+    }
+
+    fn game_cfg() -> GameConfig {
+        GameConfig {
+            size:               9,
+            komi:               7.0,
+            move_timeout:       Duration::from_millis(200),
+            command_timeout:    Duration::from_millis(200),
+            ..GameConfig::default()
+        }
+    }
+
+    #[test]
+    fn check_double_pass() {
+        let b = MockEngine::new(setup_script()
+            .expect("genmove b").respond("E5")
+            .expect("play w pass").respond("")
+            .expect("genmove b").respond("PASS")
+            .expect("final_score").respond("B+74"));
+        let w = MockEngine::new(setup_script()
+            .expect("play b E5").respond("")
+            .expect("genmove w").respond("pass")
+            .expect("play b pass").respond("")
+            .expect("final_score").respond("B+74"));
+        let (mut be, mut we) = (b.engine(), w.engine());
+
+        let mut players = Players { black: ("b", &mut be), white: ("w", &mut we), referee: None };
+        let game = play_game(&mut players, &game_cfg()).unwrap();
+        assert_eq!(game.result, GameResult::Win(Color::B, WinReason::Score(74.0)));
+        assert_eq!(game.record.moves, vec![(Color::B, (5, 5)), (Color::W, (0, 0)), (Color::B, (0, 0))]);
+        b.assert_done();
+        w.assert_done();
+    }
+
+    #[test]
+    fn check_forfeit_and_resign() {
+        let b = MockEngine::new(setup_script()
+            .expect("genmove b").respond("E5")
+            .expect("play w E5").respond(""));
+        let w = MockEngine::new(setup_script()
+            .expect("play b E5").respond("")
+            .expect("genmove w").respond("E5"));
+        let (mut be, mut we) = (b.engine(), w.engine());
+        let mut players = Players { black: ("b", &mut be), white: ("w", &mut we), referee: None };
+        let game = play_game(&mut players, &game_cfg()).unwrap();
+        assert_eq!(game.result, GameResult::Win(Color::B, WinReason::Forfeit));
+        assert_eq!(game.note, "w: illegal move E5: vertex occupied");
+
+        let b = MockEngine::new(setup_script().expect("genmove b").respond("resign"));
+        let w = MockEngine::new(setup_script());
+        let (mut be, mut we) = (b.engine(), w.engine());
+        let mut players = Players { black: ("b", &mut be), white: ("w", &mut we), referee: None };
+        let game = play_game(&mut players, &game_cfg()).unwrap();
+        assert_eq!(game.result.to_string(), "W+R");
+    }
+
+    #[test]
+    fn check_adjudication() {
+        let b = MockEngine::new(setup_script()
+            .expect("genmove b").respond("E5")
+            .expect("play w D4").respond(""));
+        let w = MockEngine::new(setup_script()
+            .expect("play b E5").respond("")
+            .expect("genmove w").respond("D4"));
+        let r = MockEngine::new(setup_script()
+            .expect("play b E5").respond("")
+            .expect("estimate_score").respond("B+12.5 (upper bound: 14.5, lower: 11.5)")
+            .expect("play w D4").respond("")
+            .expect("estimate_score").respond("B+15"));
+        let (mut be, mut we, mut re) = (b.engine(), w.engine(), r.engine());
+
+        let mut cfg = game_cfg();
+        cfg.resign = Some(ResignRule::parse("score,10,2").unwrap());
+        let mut players = Players {
+            black: ("b", &mut be), white: ("w", &mut we), referee: Some(("r", &mut re)) };
+        let game = play_game(&mut players, &cfg).unwrap();
+        assert_eq!(game.result.to_string(), "B+R");
+        assert_eq!(game.record.comment, "adjudicated: B ahead for 2 moves");
+        r.assert_done();
+
+        let b = MockEngine::new(setup_script().expect("genmove b").respond("E5"));
+        let w = MockEngine::new(setup_script().expect("play b E5").respond(""));
+        let (mut be, mut we) = (b.engine(), w.engine());
+        let mut cfg = game_cfg();
+        cfg.max_moves = Some(1);
+        let mut players = Players { black: ("b", &mut be), white: ("w", &mut we), referee: None };
+        let game = play_game(&mut players, &cfg).unwrap();
+        assert_eq!(game.result, GameResult::Draw);
+
+        cfg.resign = Some(ResignRule::parse("score,10,2").unwrap());
+        assert!(matches!(play_game(&mut players, &cfg), Err(Error::Config(_))));
+        let mut mcfg = MatchConfig::new(EngineSpec::parse("b").unwrap(), EngineSpec::parse("w").unwrap());
+        mcfg.game = cfg;
+        assert!(matches!(Match::new(mcfg), Err(Error::Config(_))));
+        let sgf = game.record.to_sgf();
+        assert!(sgf.contains("RE[0]"));
+        assert!(sgf.contains("C[adjudicated: move limit of 1 reached]"));
+    }
+
+    #[test]
+    fn check_openings() {
+        let a = MockEngine::new(
+            Script::new().expect("name").respond("a").expect("version").respond("1")
+                .expect("boardsize 9").respond("").expect("clear_board").respond("")
+                .expect("komi 7").respond("").expect("play b E5").respond("")
+                .expect("boardsize 9").respond("").expect("clear_board").respond("")
+                .expect("komi 7").respond("").expect("play b E5").respond("")
+                .expect("genmove w").respond("resign"));
+        let b = MockEngine::new(
+            Script::new().expect("name").respond("b").expect("version").respond("")
+                .expect("boardsize 9").respond("").expect("clear_board").respond("")
+                .expect("komi 7").respond("").expect("play b E5").respond("")
+                .expect("genmove w").respond("resign")
+                .expect("boardsize 9").respond("").expect("clear_board").respond("")
+                .expect("komi 7").respond("").expect("play b E5").respond(""));
+
+        let mut cfg = MatchConfig::new(EngineSpec::parse("a").unwrap(),
+                                       EngineSpec::parse("b").unwrap());
+        cfg.games     = 2;
+        cfg.alternate = false;
+        cfg.game      = game_cfg();
+        cfg.openings  = Openings::List(vec![Opening::parse_line("o1", "E5").unwrap()]);
+
+        let mut m = Match::with_engines(cfg, vec![a.engine(), b.engine()], None);
+        let result = m.run(|_| ()).unwrap();
+        assert_eq!(result.names, [String::from("a 1"), String::from("b")]);
+        assert_eq!(result.games[0].opening, "o1");
+        assert_eq!(result.games[1].opening, "o1");
+        assert_eq!(result.games[1].black, "b");
+        assert_eq!(result.wins(0, None), 1);
+        assert_eq!(result.wins(1, None), 1);
+        a.assert_done();
+        b.assert_done();
+    }
+
+    #[test]
+    fn check_restart_after_time_loss() {
+        // "a" loses the first game on time, both engines are restarted
+        // and "b" resigns the second one.
+        let setup = |s: Script| {
+            s.expect("boardsize 9").respond("")
+             .expect("clear_board").respond("")
+             .expect("komi 7").respond("")
+        };
+        let a = Script::new().expect("name").respond("a").expect("version").respond("");
+        let a = setup(a).expect("genmove b").delay(Duration::from_millis(300)).respond("E5")
+                        .expect("quit").respond("");
+        let a = MockEngine::new(setup(a));
+        let b = Script::new().expect("name").respond("b").expect("version").respond("");
+        let b = setup(b).expect("quit").respond("");
+        let b = MockEngine::new(setup(b).expect("genmove b").respond("resign"));
+
+        let mut cfg = MatchConfig::new(EngineSpec::parse("a").unwrap(),
+                                       EngineSpec::parse("b").unwrap());
+        cfg.games = 2;
+        cfg.game  = game_cfg();
+
+        let (ra, rb) = (a.clone(), b.clone());
+        let mut m =
+            Match::with_engines(cfg, vec![a.engine(), b.engine()], None)
+                .restart_with(move |spec| Ok(if spec.cmd == "a" { ra.engine() } else { rb.engine() }));
+        let result = m.run(|_| ()).unwrap();
+        assert_eq!(result.games[0].result.to_string(), "W+T");
+        assert_eq!(result.games[1].result.to_string(), "W+R");
+        a.assert_done();
+        b.assert_done();
+    }
+
+    #[test]
+    fn check_balanced_openings() {
+        // The last try is checked too, then the match gives up.
+        let mut leads = vec!["B+30"; BALANCE_TRIES - 1];
+        leads.push("W+1.5");
+        leads.extend(vec!["B+30"; BALANCE_TRIES]);
+        let mut script = Script::new();
+        for lead in leads {
+            script = script.expect("boardsize 9").respond("")
+                           .expect("clear_board").respond("")
+                           .expect("komi 7").respond("")
+                           .expect("estimate_score").respond(lead);
+        }
+        let referee = MockEngine::new(script);
+        let a = MockEngine::new(Script::new());
+
+        let mut cfg = MatchConfig::new(EngineSpec::parse("a").unwrap(),
+                                       EngineSpec::parse("b").unwrap());
+        cfg.game     = game_cfg();
+        cfg.openings = Openings::Random { moves: 0, seed: 1, max_lead: Some(2.0) };
+        let mut m = Match::with_engines(cfg, vec![a.engine(), a.engine()], Some(referee.engine()));
+
+        assert_eq!(m.next_opening(1).unwrap().unwrap().name, "random-1");
+        assert!(matches!(m.next_opening(3), Err(Error::Opening(_))));
+        referee.assert_done();
+    }
+
+    #[test]
+    fn check_winrate_parse() {
+        let wr = |t: &str, expected: f32| {
+            let wr = parse_winrate(t).unwrap();
+            assert!((wr - expected).abs() < 1e-6, "{}: {}", t, wr);
+        };
+        wr("1%",    0.01);
+        wr("0.7%",  0.007);
+        wr("1",     1.0);
+        wr("0.35",  0.35);
+        wr("99.5%", 0.995);
+        // Without the suffix, the value must be a probability:
+        assert_eq!(parse_winrate("99.5"), None);
+        assert_eq!(parse_winrate("101%"), None);
+        assert_eq!(parse_winrate("-0.1"), None);
+    }
+
+    #[test]
+    fn check_result_parse() {
+        assert_eq!(GameResult::parse("B+T"), Some(GameResult::Win(Color::B, WinReason::Time)));
+        assert_eq!(GameResult::parse("W+0"), Some(GameResult::Draw));
+        assert_eq!(GameResult::parse("X+1"), None);
+        assert_eq!(GameResult::parse("B+"), None);
+        assert_eq!(GameResult::Win(Color::W, WinReason::Score(0.5)).to_string(), "W+0.5");
+    }
+}
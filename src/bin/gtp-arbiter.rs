@@ -0,0 +1,169 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! Plays a match between two GTP engines, like `twogtp`:
+//!
+//! ```text
+//! gtp-arbiter --black <cmd> --white <cmd> [--referee <cmd>] [--games <n>]
+//!             [--size <n>] [--komi <komi>] [--alternate] [--sgf-dir <dir>]
+//!             [--timeout <secs>] [--sprt <elo0>,<elo1>[,<alpha>,<beta>]]
+//!             [--max-moves <n>] [--adjudicate <score|winrate>,<threshold>,<moves>[,<cmd>]]
+//!             [--openings <dir|file>] [--opening-moves <n>]
+//!             [--random-openings <moves>] [--seed <n>] [--balance <max lead>]
+//! ```
+//!
+//! The engine command lines are single arguments, quote them in the shell.
+//! Prints a line for each game and the results table at the end.
+//! With `--sprt` the first engine (`--black`) is tested against the second
+//! and the match stops as soon as the test is decided.
+//! `--adjudicate` needs a referee, see `gtp::arbiter::ResignRule`, as
+//! does `--balance`.
+//!
+//! `--openings` reads the openings from a directory of SGF files or a text
+//! file, see `gtp::opening`, `--opening-moves` shortens them.
+//! `--random-openings` generates random openings instead, with `--balance`
+//! and a referee only those the referee sees as balanced.
+//! Each opening is played twice with swapped colors.
+
+use std::path::PathBuf;
+use std::time::Duration;
+use gtp::arbiter::{EngineSpec, Match, MatchConfig, Openings, ResignRule};
+use gtp::sprt::Sprt;
+
+fn usage() -> ! {
+    eprintln!("usage: gtp-arbiter --black <cmd> --white <cmd> [--referee <cmd>] [--games <n>]");
+    eprintln!("                   [--size <n>] [--komi <komi>] [--alternate] [--sgf-dir <dir>]");
+    eprintln!("                   [--timeout <secs>] [--sprt <elo0>,<elo1>[,<alpha>,<beta>]]");
+    eprintln!("                   [--max-moves <n>] [--adjudicate <score|winrate>,<threshold>,<moves>[,<cmd>]]");
+    eprintln!("                   [--openings <dir|file>] [--opening-moves <n>]");
+    eprintln!("                   [--random-openings <moves>] [--seed <n>] [--balance <max lead>]");
+    std::process::exit(2);
+}
+
+fn spec(s: &str) -> EngineSpec {
+    match EngineSpec::parse(s) {
+        Ok(spec) => spec,
+        Err(e)   => { eprintln!("{}", e); usage(); },
+    }
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> T {
+    match s.parse::<T>() {
+        Ok(n)  => n,
+        Err(_) => { eprintln!("bad number '{}'", s); usage(); },
+    }
+}
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let mut black     = None;
+    let mut white     = None;
+    let mut referee   = None;
+    let mut games     = 1;
+    let mut alternate = false;
+    let mut sgf_dir   = None;
+    let mut size      = 19;
+    let mut komi      = 7.5;
+    let mut timeout   = None;
+    let mut sprt      = None;
+    let mut max_moves = None;
+    let mut resign    = None;
+    let mut openings  = None;
+    let mut op_moves  = None;
+    let mut random    = None;
+    let mut seed      = 0;
+    let mut balance   = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--alternate" {
+            alternate = true;
+            i += 1;
+            continue;
+        }
+        if i + 1 >= args.len() { usage(); }
+
+        let value = &args[i + 1];
+        match &args[i][..] {
+            "--black"   => black     = Some(spec(value)),
+            "--white"   => white     = Some(spec(value)),
+            "--referee" => referee   = Some(spec(value)),
+            "--games"   => games     = number(value),
+            "--size"    => size      = number(value),
+            "--komi"    => komi      = number(value),
+            "--sgf-dir" => sgf_dir   = Some(PathBuf::from(value)),
+            "--timeout" => timeout   = Some(Duration::from_secs(number(value))),
+            "--max-moves" => max_moves = Some(number(value)),
+            "--adjudicate" => {
+                match ResignRule::parse(value) {
+                    Ok(r)  => resign = Some(r),
+                    Err(e) => { eprintln!("{}", e); usage(); },
+                }
+            },
+            "--sprt"    => {
+                match Sprt::parse(value) {
+                    Ok(s)  => sprt = Some(s),
+                    Err(e) => { eprintln!("{}", e); usage(); },
+                }
+            },
+            "--openings"        => openings = Some(PathBuf::from(value)),
+            "--opening-moves"   => op_moves = Some(number(value)),
+            "--random-openings" => random   = Some(number(value)),
+            "--seed"            => seed     = number(value),
+            "--balance"         => balance  = Some(number(value)),
+            _           => usage(),
+        }
+        i += 2;
+    }
+
+    let (black, white) = match (black, white) {
+        (Some(b), Some(w)) => (b, w),
+        _                  => usage(),
+    };
+    if referee.is_none() && (resign.is_some() || balance.is_some()) {
+        eprintln!("--adjudicate and --balance need a referee");
+        usage();
+    }
+
+    let mut cfg = MatchConfig::new(black, white);
+    cfg.referee   = referee;
+    cfg.games     = games;
+    cfg.alternate = alternate;
+    cfg.sgf_dir   = sgf_dir;
+    cfg.sprt      = sprt;
+    cfg.game.size = size;
+    cfg.game.komi = komi;
+    cfg.game.max_moves = max_moves;
+    cfg.game.resign    = resign;
+    if let Some(t) = timeout { cfg.game.move_timeout = t; }
+
+    if let Some(path) = openings {
+        let mut list = match gtp::opening::load(&path, size) {
+            Ok(list) => list,
+            Err(e)   => { eprintln!("{}", e); std::process::exit(2); },
+        };
+        for o in list.iter_mut() {
+            if let Some(n) = op_moves { o.truncate(n); }
+            if let Err(e) = o.check(size) { eprintln!("{}", e); std::process::exit(2); }
+        }
+        cfg.openings = Openings::List(list);
+    } else if let Some(moves) = random {
+        cfg.openings = Openings::Random { moves, seed, max_lead: balance };
+    }
+
+    let mut m = match Match::new(cfg) {
+        Ok(m)  => m,
+        Err(e) => { eprintln!("{}", e); std::process::exit(2); },
+    };
+
+    match m.run(|game| println!("{}", game)) {
+        Ok(result) => { println!(); print!("{}", result); },
+        Err(e) => {
+            println!();
+            print!("{}", m.result());
+            eprintln!("match aborted: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! Runs the checks of `gtp::conformance` against a GTP engine:
+//!
+//! ```text
+//! gtp-conformance [--timeout <secs>] <engine> [<engine args>...]
+//! ```
+
+use std::time::Duration;
+use gtp::controller::Engine;
+
+fn usage() -> ! {
+    eprintln!("usage: gtp-conformance [--timeout <secs>] <engine> [<engine args>...]");
+    std::process::exit(2);
+}
+
+fn main() {
+    let mut args : Vec<String> = std::env::args().skip(1).collect();
+
+    let mut timeout = Duration::from_secs(10);
+    if args.first().map(|a| a == "--timeout").unwrap_or(false) {
+        if args.len() < 2 { usage(); }
+        match args[1].parse::<u64>() {
+            Ok(secs) => timeout = Duration::from_secs(secs),
+            Err(_)   => usage(),
+        }
+        args.drain(0..2);
+    }
+
+    if args.is_empty() { usage(); }
+
+    let engine_args : Vec<&str> = args[1..].iter().map(|s| &s[..]).collect();
+    let mut ctrl = Engine::new(&args[0], &engine_args);
+    if let Err(e) = ctrl.start() {
+        eprintln!("Couldn't start engine '{}': {:?}", args[0], e);
+        std::process::exit(2);
+    }
+
+    let report = gtp::conformance::run(&mut ctrl, timeout);
+    print!("{}", report);
+
+    let _ = ctrl.stop(timeout);
+    std::process::exit(if report.is_pass() { 0 } else { 1 });
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! Runs the same commands on two GTP engines and reports the differences:
+//!
+//! ```text
+//! gtp-diff [--timeout <secs>] [--timing <factor>] [--every <n>] [--probe <cmd>]...
+//!          <engine a> <engine b> <file>...
+//! ```
+//!
+//! Each engine is given as a single argument, quote it in the shell. The
+//! files are GTP scripts in the format of regression test files, or SGF
+//! files. For SGF files every `n`th position (default 10) is set up and
+//! the probe commands are sent, `{color}` is replaced by the color to
+//! move. The default probe is `genmove {color}`. A missing response
+//! within the timeout differs from any response. Exits with 1 if
+//! any responses differ, and with 2 if an engine failed.
+
+use std::time::Duration;
+use gtp::arbiter::{engine_name, EngineSpec};
+use gtp::diff::{compare, positions, DiffConfig};
+use gtp::regression::Suite;
+use gtp::sgf::GameRecord;
+
+fn usage() -> ! {
+    eprintln!("usage: gtp-diff [--timeout <secs>] [--timing <factor>] [--every <n>] [--probe <cmd>]...");
+    eprintln!("                <engine a> <engine b> <file>...");
+    std::process::exit(2);
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> T {
+    match s.parse::<T>() {
+        Ok(n)  => n,
+        Err(_) => { eprintln!("bad number '{}'", s); usage(); },
+    }
+}
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let mut cfg    = DiffConfig::new();
+    let mut every  = 10;
+    let mut probes = vec![];
+    let mut rest   = vec![];
+
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            rest.push(args[i].clone());
+            i += 1;
+            continue;
+        }
+        if i + 1 >= args.len() { usage(); }
+
+        let value = &args[i + 1];
+        match &args[i][..] {
+            "--timeout" => cfg.timeout       = Duration::from_secs(number(value)),
+            "--timing"  => cfg.timing_factor = number(value),
+            "--every"   => every             = number(value),
+            "--probe"   => probes.push(value.clone()),
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    if rest.len() < 3 { usage(); }
+    if probes.is_empty() { probes.push(String::from("genmove {color}")); }
+    let probes : Vec<&str> = probes.iter().map(|p| &p[..]).collect();
+
+    let mut suites = vec![];
+    for file in rest[2..].iter() {
+        let loaded =
+            if file.to_lowercase().ends_with(".sgf") {
+                GameRecord::load(std::path::Path::new(file))
+                    .map(|rec| positions(file, &rec, every, &probes))
+            } else {
+                Suite::load(file).map(|s| vec![s])
+            };
+        match loaded {
+            Ok(s)  => suites.extend(s),
+            Err(e) => { eprintln!("{}", e); std::process::exit(2); },
+        }
+    }
+
+    let mut engines = vec![];
+    for (label, cmd) in ["a", "b"].iter().zip(rest[..2].iter()) {
+        let spec = match EngineSpec::parse(cmd) {
+            Ok(spec) => spec,
+            Err(e)   => { eprintln!("{}", e); usage(); },
+        };
+        let mut engine = match spec.start() {
+            Ok(e)  => e,
+            Err(e) => { eprintln!("{}", e); std::process::exit(2); },
+        };
+        let name = engine_name(&mut engine, cfg.timeout).unwrap_or_else(|| spec.to_string());
+        println!("{}: {}", label, name);
+        engines.push(engine);
+    }
+
+    let mut code = 0;
+    for suite in suites.iter() {
+        let (a, b) = engines.split_at_mut(1);
+        match compare([&mut a[0], &mut b[0]], suite, &cfg) {
+            Ok(report) => {
+                print!("{}", report);
+                if !report.is_same() { code = 1; }
+            },
+            Err((idx, e)) => {
+                println!("{}: aborted: engine {}: {:?}", suite.name, ["a", "b"][idx], e);
+                code = 2;
+                break;
+            },
+        }
+    }
+
+    for e in engines.iter_mut() { let _ = e.stop(cfg.timeout); }
+    std::process::exit(code);
+}
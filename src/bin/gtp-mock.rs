@@ -0,0 +1,72 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! A scripted GTP engine on stdin/stdout, for testing controllers:
+//!
+//! ```text
+//! gtp-mock <script file>
+//! ```
+//!
+//! See `gtp::mock::Script::parse` for the script format. The mock
+//! exits when stdin is closed and aborts on `crash`. Unexpected
+//! commands are reported on stderr and make the exit status 1.
+
+use std::io::BufRead;
+use std::io::Write;
+use gtp::mock::{MockState, Output, Script};
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: gtp-mock <script file>");
+            std::process::exit(2);
+        },
+    };
+
+    let script =
+        std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Script::parse(&text));
+    let mut state = match script {
+        Ok(script) => MockState::new(script),
+        Err(e) => {
+            eprintln!("gtp-mock: {}: {}", path, e);
+            std::process::exit(2);
+        },
+    };
+
+    let stdin      = std::io::stdin();
+    let mut stdout = std::io::stdout();
+
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_)   => break,
+        };
+
+        let n_unexpected = state.unexpected().len();
+        let out          = state.handle(&line);
+        if state.unexpected().len() > n_unexpected {
+            eprintln!("gtp-mock: unexpected command: {}", line);
+        }
+
+        for o in out.into_iter() {
+            match o {
+                Output::Stdout(s) => {
+                    let _ = stdout.write_all(s.as_bytes());
+                    let _ = stdout.flush();
+                },
+                Output::Stderr(s) => eprint!("{}", s),
+                Output::Delay(d)  => std::thread::sleep(d),
+                Output::Crash     => std::process::abort(),
+            }
+        }
+    }
+
+    if !state.is_done() {
+        eprintln!("gtp-mock: script not finished");
+    }
+    let ok = state.is_done() && state.unexpected().is_empty();
+    std::process::exit(if ok { 0 } else { 1 });
+}
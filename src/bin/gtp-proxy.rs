@@ -0,0 +1,111 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! A GTP engine that forwards the commands to other engines:
+//!
+//! ```text
+//! gtp-proxy [--log <file>] [--route <cmd>=<name>]... [--rename <from>=<to>]...
+//!           [--stream <cmd>]... [--timeout <secs>]
+//!           <name>=<cmd> [<name>=<cmd>...]
+//! ```
+//!
+//! Each backend is given as a single argument, quote it in the shell. The
+//! first one gets all commands that are not routed elsewhere. A command
+//! pattern may end with `*` to match all commands with that prefix. For
+//! instance, to let Leela Zero play and GNU Go count:
+//!
+//! ```text
+//! gtp-proxy --route final_score=gnugo \
+//!     "leelaz=leelaz --gtp -w best.gz" "gnugo=gnugo --mode gtp"
+//! ```
+//!
+//! See `gtp::proxy` for which commands are sent to which backends.
+
+use std::time::Duration;
+use gtp::proxy::{Proxy, Rules};
+use gtp::tournament::Participant;
+
+fn usage() -> ! {
+    eprintln!("usage: gtp-proxy [--log <file>] [--route <cmd>=<name>]... [--rename <from>=<to>]...");
+    eprintln!("                 [--stream <cmd>]... [--timeout <secs>]");
+    eprintln!("                 <name>=<cmd> [<name>=<cmd>...]");
+    std::process::exit(2);
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> T {
+    match s.parse::<T>() {
+        Ok(n)  => n,
+        Err(_) => { eprintln!("bad number '{}'", s); usage(); },
+    }
+}
+
+fn pair(s: &str) -> (String, String) {
+    match s.find('=') {
+        Some(p) if p > 0 && p + 1 < s.len() => (s[..p].to_string(), s[p + 1..].to_string()),
+        _ => { eprintln!("expected <a>=<b>, got '{}'", s); usage(); },
+    }
+}
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let mut rules    = Rules::default();
+    let mut backends = vec![];
+    let mut routes   = vec![];
+    let mut log      = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            match Participant::parse(&args[i]) {
+                Ok(p)  => backends.push(p),
+                Err(e) => { eprintln!("{}", e); usage(); },
+            }
+            i += 1;
+            continue;
+        }
+        if i + 1 >= args.len() { usage(); }
+
+        let value = &args[i + 1];
+        match &args[i][..] {
+            "--log"     => log = Some(value.clone()),
+            "--route"   => routes.push(pair(value)),
+            "--rename"  => rules.renames.push(pair(value)),
+            "--stream"  => rules.streaming.push(value.clone()),
+            "--timeout" => rules.timeout = Duration::from_secs(number(value)),
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    if backends.is_empty() { usage(); }
+
+    for (cmd, name) in routes {
+        match backends.iter().position(|p| p.name == name) {
+            Some(idx) => rules.routes.push((cmd, idx)),
+            None      => { eprintln!("unknown backend '{}'", name); usage(); },
+        }
+    }
+
+    let mut engines = vec![];
+    for p in backends {
+        match p.spec.start() {
+            Ok(e)  => engines.push((p.name, e)),
+            Err(e) => { eprintln!("{}", e); std::process::exit(1); },
+        }
+    }
+
+    let mut proxy = Proxy::new(engines, rules);
+    if let Some(path) = log {
+        match std::fs::File::create(&path) {
+            Ok(f)  => proxy.set_log(Box::new(f)),
+            Err(e) => { eprintln!("Couldn't create '{}': {}", path, e); std::process::exit(2); },
+        }
+    }
+
+    let stdin = std::io::BufReader::new(std::io::stdin());
+    if let Err(e) = proxy.run(stdin, &mut std::io::stdout()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
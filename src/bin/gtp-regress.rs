@@ -0,0 +1,72 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! Runs GNU Go style regression test files against a GTP engine:
+//!
+//! ```text
+//! gtp-regress [--timeout <secs>] <file.tst>... -- <engine> [<engine args>...]
+//! ```
+//!
+//! The engine is restarted for each test file.
+
+use std::time::Duration;
+use gtp::controller::Engine;
+use gtp::regression;
+
+fn usage() -> ! {
+    eprintln!("usage: gtp-regress [--timeout <secs>] <file.tst>... -- <engine> [<engine args>...]");
+    std::process::exit(2);
+}
+
+fn main() {
+    let mut args : Vec<String> = std::env::args().skip(1).collect();
+
+    let mut timeout = Duration::from_secs(600);
+    if args.first().map(|a| a == "--timeout").unwrap_or(false) {
+        if args.len() < 2 { usage(); }
+        match args[1].parse::<u64>() {
+            Ok(secs) => timeout = Duration::from_secs(secs),
+            Err(_)   => usage(),
+        }
+        args.drain(0..2);
+    }
+
+    let sep = match args.iter().position(|a| a == "--") {
+        Some(sep) => sep,
+        None      => usage(),
+    };
+    if sep == 0 || sep + 1 >= args.len() { usage(); }
+
+    let files       = &args[..sep];
+    let engine_cmd  = &args[sep + 1];
+    let engine_args : Vec<&str> = args[sep + 2..].iter().map(|s| &s[..]).collect();
+
+    let mut all_pass = true;
+    for file in files.iter() {
+        let suite = match regression::Suite::load(file) {
+            Ok(suite) => suite,
+            Err(e)    => { eprintln!("{}", e); std::process::exit(2); },
+        };
+
+        let mut ctrl = Engine::new(engine_cmd, &engine_args);
+        if let Err(e) = ctrl.start() {
+            eprintln!("Couldn't start engine '{}': {:?}", engine_cmd, e);
+            std::process::exit(2);
+        }
+
+        match regression::run(&mut ctrl, &suite, timeout) {
+            Ok(report) => {
+                print!("{}", report);
+                all_pass = all_pass && report.is_pass();
+            },
+            Err(e) => {
+                println!("{}: aborted: {:?}", file, e);
+                all_pass = false;
+            },
+        }
+
+        let _ = ctrl.stop(timeout);
+    }
+
+    std::process::exit(if all_pass { 0 } else { 1 });
+}
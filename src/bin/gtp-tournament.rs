@@ -0,0 +1,111 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! Runs a round-robin or gauntlet tournament between GTP engines:
+//!
+//! ```text
+//! gtp-tournament [--gauntlet] [--rounds <n>] [--workers <n>] [--results <file>]
+//!                [--referee <cmd>] [--size <n>] [--komi <komi>]
+//!                [--sgf-dir <dir>] [--timeout <secs>] [--max-moves <n>]
+//!                [--adjudicate <score|winrate>,<threshold>,<moves>[,<cmd>]]
+//!                <name>=<cmd> <name>=<cmd>...
+//! ```
+//!
+//! Each participant is given as a single argument, quote it in the shell.
+//! With `--gauntlet` the first participant plays all others. An interrupted
+//! tournament continues where it stopped when started again with the same
+//! results file. Prints a line for each game and the standings at the end.
+
+use std::path::PathBuf;
+use std::time::Duration;
+use gtp::arbiter::{EngineSpec, ResignRule};
+use gtp::tournament::{Kind, Participant, Tournament, TournamentConfig};
+
+fn usage() -> ! {
+    eprintln!("usage: gtp-tournament [--gauntlet] [--rounds <n>] [--workers <n>] [--results <file>]");
+    eprintln!("                      [--referee <cmd>] [--size <n>] [--komi <komi>]");
+    eprintln!("                      [--sgf-dir <dir>] [--timeout <secs>] [--max-moves <n>]");
+    eprintln!("                      [--adjudicate <score|winrate>,<threshold>,<moves>[,<cmd>]]");
+    eprintln!("                      <name>=<cmd> <name>=<cmd>...");
+    std::process::exit(2);
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> T {
+    match s.parse::<T>() {
+        Ok(n)  => n,
+        Err(_) => { eprintln!("bad number '{}'", s); usage(); },
+    }
+}
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+
+    let mut cfg = TournamentConfig::new(vec![]);
+
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            match Participant::parse(&args[i]) {
+                Ok(p)  => cfg.participants.push(p),
+                Err(e) => { eprintln!("{}", e); usage(); },
+            }
+            i += 1;
+            continue;
+        }
+        if args[i] == "--gauntlet" {
+            cfg.kind = Kind::Gauntlet;
+            i += 1;
+            continue;
+        }
+        if i + 1 >= args.len() { usage(); }
+
+        let value = &args[i + 1];
+        match &args[i][..] {
+            "--rounds"  => cfg.rounds    = number(value),
+            "--workers" => cfg.workers   = number(value),
+            "--results" => cfg.results   = Some(PathBuf::from(value)),
+            "--size"    => cfg.game.size = number(value),
+            "--komi"    => cfg.game.komi = number(value),
+            "--sgf-dir" => cfg.sgf_dir   = Some(PathBuf::from(value)),
+            "--timeout" => cfg.game.move_timeout = Duration::from_secs(number(value)),
+            "--max-moves" => cfg.game.max_moves = Some(number(value)),
+            "--adjudicate" => {
+                match ResignRule::parse(value) {
+                    Ok(r)  => cfg.game.resign = Some(r),
+                    Err(e) => { eprintln!("{}", e); usage(); },
+                }
+            },
+            "--referee" => {
+                match EngineSpec::parse(value) {
+                    Ok(spec) => cfg.referee = Some(spec),
+                    Err(e)   => { eprintln!("{}", e); usage(); },
+                }
+            },
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    if cfg.participants.len() < 2 { usage(); }
+    if cfg.game.resign.is_some() && cfg.referee.is_none() {
+        eprintln!("--adjudicate needs a referee");
+        usage();
+    }
+
+    let mut t = match Tournament::new(cfg) {
+        Ok(t)  => t,
+        Err(e) => { eprintln!("{}", e); std::process::exit(2); },
+    };
+    if !t.games().is_empty() {
+        println!("resuming after {} games", t.games().len());
+    }
+
+    let res = t.run(|game| println!("{}", game));
+    println!();
+    print!("{}", t.standings());
+
+    if let Err(e) = res {
+        eprintln!("tournament aborted: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+A minimal Go board for checking the legality of moves exchanged
+between engines.
+
+Vertices are `(h, v)` tuples counted from 1, like in
+[`Entity::Vertex`](../enum.Entity.html), with `(0, 0)` being a pass.
+
+```
+use gtp::Color;
+use gtp::board::{Board, IllegalMove};
+
+let mut b = Board::new(9);
+assert!(b.play(Color::B, (1, 2)).is_ok());
+assert!(b.play(Color::B, (2, 1)).is_ok());
+assert_eq!(b.play(Color::W, (1, 1)), Err(IllegalMove::Suicide));
+```
+*/
+
+use super::Color;
+
+/// The reason a move was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IllegalMove {
+    OutOfBoard,
+    Occupied,
+    Suicide,
+    /// Retaking a ko immediately.
+    Ko,
+}
+
+impl std::fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IllegalMove::OutOfBoard => write!(f, "vertex outside of the board"),
+            IllegalMove::Occupied   => write!(f, "vertex occupied"),
+            IllegalMove::Suicide    => write!(f, "suicide"),
+            IllegalMove::Ko         => write!(f, "ko"),
+        }
+    }
+}
+
+/// A Go board with simple ko rule and without suicide.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Board {
+    size:   usize,
+    cells:  Vec<Option<Color>>,
+    ko:     Option<(i32, i32)>,
+}
+
+impl Board {
+    pub fn new(size: usize) -> Board {
+        Board { size, cells: vec![None; size * size], ko: None }
+    }
+
+    pub fn size(&self) -> usize { self.size }
+
+    fn index(&self, (h, v): (i32, i32)) -> Option<usize> {
+        let s = self.size as i32;
+        if h < 1 || v < 1 || h > s || v > s { return None; }
+        Some(((v - 1) * s + (h - 1)) as usize)
+    }
+
+    fn vertex(&self, idx: usize) -> (i32, i32) {
+        ((idx % self.size) as i32 + 1, (idx / self.size) as i32 + 1)
+    }
+
+    fn neighbours(&self, idx: usize) -> Vec<usize> {
+        let (h, v) = self.vertex(idx);
+        [(h - 1, v), (h + 1, v), (h, v - 1), (h, v + 1)]
+            .iter()
+            .filter_map(|n| self.index(*n))
+            .collect()
+    }
+
+    /// Returns the stone on the vertex, `None` for empty and
+    /// invalid vertices.
+    pub fn get(&self, vertex: (i32, i32)) -> Option<Color> {
+        self.index(vertex).and_then(|i| self.cells[i])
+    }
+
+    /// Returns the stones of the group at `idx` and its number of liberties.
+    fn group(&self, idx: usize) -> (Vec<usize>, usize) {
+        let color         = self.cells[idx];
+        let mut stones    = vec![idx];
+        let mut seen      = vec![false; self.cells.len()];
+        let mut liberties = 0;
+        seen[idx] = true;
+
+        let mut i = 0;
+        while i < stones.len() {
+            for n in self.neighbours(stones[i]) {
+                if seen[n] { continue; }
+                seen[n] = true;
+                if self.cells[n].is_none() {
+                    liberties += 1;
+                } else if self.cells[n] == color {
+                    stones.push(n);
+                }
+            }
+            i += 1;
+        }
+
+        (stones, liberties)
+    }
+
+    /// Checks whether the move would be legal, without playing it.
+    pub fn check(&self, color: Color, vertex: (i32, i32)) -> Result<(), IllegalMove> {
+        self.clone().play(color, vertex).map(|_| ())
+    }
+
+    /// Plays a move and returns the captured stones.
+    pub fn play(&mut self, color: Color, vertex: (i32, i32)) -> Result<Vec<(i32, i32)>, IllegalMove> {
+        if vertex == (0, 0) {
+            self.ko = None;
+            return Ok(vec![]);
+        }
+
+        let idx = self.index(vertex).ok_or(IllegalMove::OutOfBoard)?;
+        if self.cells[idx].is_some() { return Err(IllegalMove::Occupied); }
+        if self.ko == Some(vertex)   { return Err(IllegalMove::Ko); }
+
+        self.cells[idx] = Some(color);
+
+        let mut captured = vec![];
+        for n in self.neighbours(idx) {
+            if self.cells[n] != Some(color.opponent()) { continue; }
+            let (stones, liberties) = self.group(n);
+            if liberties == 0 {
+                for s in stones {
+                    self.cells[s] = None;
+                    captured.push(self.vertex(s));
+                }
+            }
+        }
+
+        let (stones, liberties) = self.group(idx);
+        if liberties == 0 {
+            self.cells[idx] = None;
+            return Err(IllegalMove::Suicide);
+        }
+
+        self.ko =
+            if captured.len() == 1 && stones.len() == 1 && liberties == 1 {
+                Some(captured[0])
+            } else {
+                None
+            };
+
+        Ok(captured)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_capture_and_ko() {
+        let mut b = Board::new(5);
+        // Black and white shape around a ko at (2, 2)/(3, 2):
+        for v in [(2, 1), (1, 2), (2, 3)].iter() { b.play(Color::B, *v).unwrap(); }
+        for v in [(3, 1), (4, 2), (3, 3)].iter() { b.play(Color::W, *v).unwrap(); }
+        b.play(Color::W, (2, 2)).unwrap();
+
+        assert_eq!(b.play(Color::B, (3, 2)), Ok(vec![(2, 2)]));
+        assert_eq!(b.get((2, 2)), None);
+        assert_eq!(b.play(Color::W, (2, 2)), Err(IllegalMove::Ko));
+
+        b.play(Color::W, (5, 5)).unwrap();
+        b.play(Color::B, (5, 4)).unwrap();
+        assert_eq!(b.play(Color::W, (2, 2)), Ok(vec![(3, 2)]));
+
+        assert_eq!(b.play(Color::B, (2, 2)), Err(IllegalMove::Occupied));
+        assert_eq!(b.play(Color::B, (6, 1)), Err(IllegalMove::OutOfBoard));
+        assert_eq!(b.play(Color::B, (0, 0)), Ok(vec![]));
+    }
+
+    #[test]
+    fn check_group_capture() {
+        let mut b = Board::new(3);
+        b.play(Color::W, (1, 1)).unwrap();
+        b.play(Color::W, (2, 1)).unwrap();
+        b.play(Color::B, (1, 2)).unwrap();
+        b.play(Color::B, (2, 2)).unwrap();
+        assert_eq!(b.play(Color::B, (3, 1)).unwrap().len(), 2);
+        assert_eq!(b.get((1, 1)), None);
+        assert_eq!(b.get((3, 1)), Some(Color::B));
+    }
+}
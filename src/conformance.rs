@@ -0,0 +1,448 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Checks a GTP engine for conformance with the GTP version 2 specification.
+
+The checks are run against a started [`Engine`](../controller/struct.Engine.html)
+and the outcome of each check is collected in a [`Report`](struct.Report.html):
+
+```no_run
+use std::time::Duration;
+use gtp::controller::Engine;
+
+let mut ctrl = Engine::new("/usr/bin/gnugo", &["--mode", "gtp"]);
+assert!(ctrl.start().is_ok());
+
+let report = gtp::conformance::run(&mut ctrl, Duration::from_secs(10));
+print!("{}", report);
+assert!(report.is_pass());
+```
+
+The checks change the board state of the engine. The `gtp-conformance`
+binary runs the checks against an engine command line and prints the report.
+*/
+
+use std::time::Duration;
+use std::time::Instant;
+
+use super::controller::Engine;
+use super::controller::Error;
+use super::controller::REQUIRED_COMMANDS;
+use super::Command;
+use super::Response;
+use super::Strictness;
+
+const BOGUS_COMMAND : &str = "gtp-rs-no-such-command";
+const POLL_SLICE_MS : u64  = 20;
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// The check could not be run, for instance because the
+    /// engine does not implement an optional command.
+    Skip,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "PASS"),
+            Outcome::Fail => write!(f, "FAIL"),
+            Outcome::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+/// A single conformance check and its outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name:       &'static str,
+    pub outcome:    Outcome,
+    /// Explains failures and skips, empty if the check passed.
+    pub detail:     String,
+}
+
+/// The outcome of all conformance checks.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Returns the number of checks with the given outcome.
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.checks.iter().filter(|c| c.outcome == outcome).count()
+    }
+
+    /// Returns true if no check failed.
+    pub fn is_pass(&self) -> bool { self.count(Outcome::Fail) == 0 }
+
+    /// Returns the check with the given name.
+    pub fn get(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|c| c.name == name)
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for c in self.checks.iter() {
+            if c.detail.is_empty() {
+                writeln!(f, "{} {}", c.outcome, c.name)?;
+            } else {
+                writeln!(f, "{} {:<18} {}", c.outcome, c.name, c.detail)?;
+            }
+        }
+        writeln!(f, "{} passed, {} failed, {} skipped",
+                 self.count(Outcome::Pass),
+                 self.count(Outcome::Fail),
+                 self.count(Outcome::Skip))
+    }
+}
+
+/// Waits for the next response of the engine.
+fn next_response(engine: &mut Engine, timeout: Duration) -> Result<Response, String> {
+    let instant = Instant::now();
+    loop {
+        match engine.wait_response(Duration::from_millis(POLL_SLICE_MS)) {
+            Ok(resp)              => return Ok(resp),
+            Err(Error::PollAgain) => (),
+            Err(e)                => return Err(format!("{:?}", e)),
+        }
+
+        if instant.elapsed() > timeout {
+            return Err(String::from("timeout"));
+        }
+    }
+}
+
+struct Checker<'a> {
+    engine:     &'a mut Engine,
+    timeout:    Duration,
+    commands:   Vec<String>,
+    bad_ids:    Vec<String>,
+    report:     Report,
+    /// Set once the engine did not even answer a resync.
+    hung:       bool,
+}
+
+impl<'a> Checker<'a> {
+    fn add(&mut self, name: &'static str, failures: Vec<String>) {
+        let outcome = if failures.is_empty() { Outcome::Pass } else { Outcome::Fail };
+        self.report.checks.push(Check { name, outcome, detail: failures.join("; ") });
+    }
+
+    fn skip(&mut self, name: &'static str, detail: &str) {
+        self.report.checks.push(Check {
+            name,
+            outcome: Outcome::Skip,
+            detail:  detail.to_string(),
+        });
+    }
+
+    /// Waits for the next response. A response arriving after a timeout
+    /// would be taken as the answer to the following check, so the
+    /// output is skipped up to the response to a `protocol_version` fence.
+    fn next(&mut self) -> Result<Response, String> {
+        if self.hung {
+            return Err(String::from("engine not responding"));
+        }
+
+        let resp = next_response(self.engine, self.timeout);
+        if resp.is_err() {
+            let fence = self.engine.send(Command::new("protocol_version"));
+            loop {
+                match next_response(self.engine, self.timeout) {
+                    Ok(r) if r.id_0() == fence => break,
+                    Ok(_)  => (),
+                    Err(_) => { self.hung = true; break; },
+                }
+            }
+        }
+        resp
+    }
+
+    /// Sends a command and waits for the next response, which must
+    /// carry the ID of the command.
+    fn request(&mut self, cmd: Command) -> Result<Response, String> {
+        let line = cmd.to_string();
+        let id   = self.engine.send(cmd);
+        let resp = self.next().map_err(|e| format!("{}: {}", line.trim(), e))?;
+
+        if resp.id_0() != id {
+            self.bad_ids.push(format!("sent {} got {}", id, resp.id_0()));
+        }
+        Ok(resp)
+    }
+
+    /// Sends a command and checks whether it succeeded or failed
+    /// as expected. Returns a description of the problem otherwise.
+    fn expect(&mut self, cmd: Command, success: bool) -> Option<String> {
+        let line = cmd.to_string();
+        match self.request(cmd) {
+            Ok(Response::Result(_)) if success  => None,
+            Ok(Response::Error(_))  if !success => None,
+            Ok(Response::Result(_)) => Some(format!("'{}' succeeded", line.trim())),
+            Ok(Response::Error((_, msg))) =>
+                Some(format!("'{}' failed: {}", line.trim(), msg)),
+            Err(e) => Some(e),
+        }
+    }
+
+    fn check_protocol_version(&mut self) {
+        let failures =
+            match self.request(Command::new("protocol_version")) {
+                Ok(Response::Result((_, v))) if v.trim() == "2" => vec![],
+                Ok(resp) => vec![format!("expected 2, got '{}'", resp.text())],
+                Err(e)   => vec![e],
+            };
+        self.add("protocol_version", failures);
+    }
+
+    fn check_required_commands(&mut self) {
+        let failures =
+            match self.request(Command::new("list_commands")) {
+                Ok(Response::Result((_, list))) => {
+                    self.commands =
+                        list.lines()
+                            .map(|l| l.trim().to_string())
+                            .filter(|l| !l.is_empty())
+                            .collect();
+                    let missing : Vec<&str> =
+                        REQUIRED_COMMANDS.iter()
+                            .filter(|c| !self.commands.iter().any(|l| l == *c))
+                            .copied()
+                            .collect();
+                    if missing.is_empty() {
+                        vec![]
+                    } else {
+                        vec![format!("missing: {}", missing.join(", "))]
+                    }
+                },
+                Ok(resp) => vec![format!("list_commands failed: {}", resp.text())],
+                Err(e)   => vec![e],
+            };
+        self.add("required_commands", failures);
+    }
+
+    fn check_known_command(&mut self) {
+        let mut failures = vec![];
+
+        let mut queries : Vec<(String, &str)> =
+            self.commands.iter().map(|c| (c.clone(), "true")).collect();
+        queries.push((BOGUS_COMMAND.to_string(), "false"));
+
+        for (name, expected) in queries.into_iter() {
+            let cmd = Command::cmd("known_command", |e| e.s(&name));
+            match self.request(cmd) {
+                Ok(Response::Result((_, ans))) if ans.trim() == expected => (),
+                Ok(resp) =>
+                    failures.push(format!("'{}': expected {}, got '{}'",
+                                          name, expected, resp.text())),
+                Err(e) => failures.push(e),
+            }
+        }
+        self.add("known_command", failures);
+    }
+
+    fn check_unknown_command(&mut self) {
+        let failures : Vec<String> =
+            self.expect(Command::new(BOGUS_COMMAND), false).into_iter().collect();
+        self.add("unknown_command", failures);
+    }
+
+    fn check_boardsize_range(&mut self) {
+        let failures : Vec<String> = vec![
+            self.expect(Command::cmd("boardsize", |e| e.i(0)),  false),
+            self.expect(Command::cmd("boardsize", |e| e.i(26)), false),
+            self.expect(Command::cmd("boardsize", |e| e.i(19)), true),
+        ].into_iter().flatten().collect();
+        self.add("boardsize_range", failures);
+    }
+
+    fn check_illegal_moves(&mut self) {
+        let failures : Vec<String> = vec![
+            self.expect(Command::cmd("boardsize", |e| e.i(19)),        true),
+            self.expect(Command::new("clear_board"),                  true),
+            self.expect(Command::cmd("play", |e| e.mv_b((4, 4))),     true),
+            // occupied vertex:
+            self.expect(Command::cmd("play", |e| e.mv_w((4, 4))),     false),
+            // outside of the board:
+            self.expect(Command::cmd("play", |e| e.mv_b((1, 20))),    false),
+            self.expect(Command::cmd("play", |e| e.mv_w((25, 1))),    false),
+        ].into_iter().flatten().collect();
+        self.add("illegal_moves", failures);
+    }
+
+    fn check_undo(&mut self) {
+        if !self.commands.iter().any(|c| c == "undo") {
+            self.skip("undo", "undo not supported");
+            return;
+        }
+
+        let failures : Vec<String> = vec![
+            self.expect(Command::new("clear_board"),               true),
+            // nothing to undo:
+            self.expect(Command::new("undo"),                      false),
+            self.expect(Command::cmd("play", |e| e.mv_b((3, 3))),  true),
+            self.expect(Command::new("undo"),                      true),
+            // the vertex must be free again:
+            self.expect(Command::cmd("play", |e| e.mv_w((3, 3))),  true),
+        ].into_iter().flatten().collect();
+        self.add("undo", failures);
+    }
+
+    /// Sends a raw line and checks that the next response
+    /// is a successful one with the ID `id`.
+    fn expect_raw(&mut self, line: &str, id: u32) -> Option<String> {
+        self.engine.send_str(line);
+        match self.next() {
+            Ok(Response::Result((Some(rid), _))) if rid == id => None,
+            Ok(resp) => Some(format!("{:?}: unexpected response {:?}", line, resp)),
+            Err(e)   => Some(format!("{:?}: {}", line, e)),
+        }
+    }
+
+    fn check_preprocessing(&mut self) {
+        let mut failures = vec![];
+
+        // Neither comment only nor empty lines get a response:
+        self.engine.send_str("# only a comment\n");
+        self.engine.send_str("\n \t \n");
+        failures.extend(self.expect_raw("9001 name\n", 9001));
+
+        failures.extend(self.expect_raw("9002\tname\t# comment\r\n", 9002));
+        failures.extend(self.expect_raw("9003 na\x01me\n", 9003));
+        self.add("preprocessing", failures);
+    }
+
+    fn check_id_echo(&mut self) {
+        let failures = self.bad_ids.clone();
+        self.add("id_echo", failures);
+    }
+
+    fn check_response_format(&mut self) {
+        let failures : Vec<String> =
+            self.engine.take_diagnostics()
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+        self.add("response_format", failures);
+    }
+}
+
+/// Runs all conformance checks against the started `engine`.
+/// `timeout` is the maximum time to wait for each response.
+///
+/// The responses are parsed in strict mode to check their format.
+/// The engine is left in lenient mode afterwards.
+pub fn run(engine: &mut Engine, timeout: Duration) -> Report {
+    engine.set_strictness(Strictness::Strict);
+    engine.take_diagnostics();
+
+    let mut c = Checker {
+        engine,
+        timeout,
+        commands:   Vec::new(),
+        bad_ids:    Vec::new(),
+        report:     Report::default(),
+        hung:       false,
+    };
+
+    c.check_protocol_version();
+    c.check_required_commands();
+    c.check_known_command();
+    c.check_unknown_command();
+    c.check_boardsize_range();
+    c.check_illegal_moves();
+    c.check_undo();
+    c.check_preprocessing();
+    c.check_id_echo();
+    c.check_response_format();
+
+    c.engine.set_strictness(Strictness::Lenient);
+    c.report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    /// Scripts the checks from `list_commands` on for an engine
+    /// implementing just the required commands.
+    fn required(script: Script, boardsize_0: bool) -> Script {
+        let mut script = script.expect("list_commands").respond(&REQUIRED_COMMANDS.join("\n"));
+        for c in REQUIRED_COMMANDS.iter() {
+            script = script.expect(&format!("known_command {}", c)).respond("true");
+        }
+        let script =
+            script.expect(&format!("known_command {}", BOGUS_COMMAND)).respond("false")
+                  .expect(BOGUS_COMMAND).fail("unknown command")
+                  .expect("boardsize 0");
+        let script = if boardsize_0 { script.respond("") } else { script.fail("unacceptable size") };
+        script
+            .expect("boardsize 26").fail("unacceptable size")
+            .expect("boardsize 19").respond("")
+            .expect("boardsize 19").respond("")
+            .expect("clear_board").respond("")
+            .expect("play *").respond("")
+            .expect("play *").fail("illegal move")
+            .expect("play *").fail("illegal move")
+            .expect("play *").fail("illegal move")
+            .expect("name").respond("mock")
+            .expect("name").respond("mock")
+            .expect("name").respond("mock")
+    }
+
+    fn failed(report: &Report) -> Vec<&'static str> {
+        report.checks.iter()
+            .filter(|c| c.outcome == Outcome::Fail)
+            .map(|c| c.name)
+            .collect()
+    }
+
+    #[test]
+    fn check_conformant() {
+        let mock = MockEngine::new(required(
+            Script::new().expect("protocol_version").respond("2"), false));
+        let mut ctrl = mock.engine();
+
+        let report = run(&mut ctrl, Duration::from_secs(1));
+        assert!(report.is_pass(), "{}", report);
+        assert_eq!(report.get("undo").unwrap().outcome, Outcome::Skip);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn check_non_conformant() {
+        let mock = MockEngine::new(required(
+            Script::new().expect("protocol_version").respond("1"), true));
+        let mut ctrl = mock.engine();
+
+        let report = run(&mut ctrl, Duration::from_secs(1));
+        assert_eq!(failed(&report), vec!["protocol_version", "boardsize_range"]);
+        assert_eq!(report.get("boardsize_range").unwrap().detail,
+                   "'boardsize 0' succeeded");
+        mock.assert_done();
+    }
+
+    #[test]
+    fn check_resync_after_timeout() {
+        // The late answer to the first check must not be
+        // taken as the answer to `list_commands`.
+        let mock = MockEngine::new(required(
+            Script::new()
+                .expect("protocol_version").delay(Duration::from_millis(300)).respond("2")
+                .expect("protocol_version").respond("2"),
+            false));
+        let mut ctrl = mock.engine();
+
+        let report = run(&mut ctrl, Duration::from_millis(200));
+        assert_eq!(failed(&report), vec!["protocol_version"]);
+        assert!(report.get("protocol_version").unwrap().detail.ends_with("timeout"));
+        mock.assert_done();
+    }
+}
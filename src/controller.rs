@@ -7,12 +7,44 @@ This module provides the abstraction of a GTP engine controller.
 See also [`Engine`](struct.Engine.html) for more information.
 */
 
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use super::detached_command::DetachedCommand;
+use super::detached_command::Encoding;
+use super::detached_command::ProcessConfig;
+use super::detached_command::Transport;
+use super::transcript::Direction;
+use super::transcript::Recorder;
+
 const WAIT_POLL_DIV : u32 = 4;
+const WAIT_SLICE_MS : u64 = 20;
+const MAX_PENDING   : usize = 4096;
+const STDERR_LINES  : usize = 1000;
+
+/// The commands every GTP version 2 engine must implement.
+pub const REQUIRED_COMMANDS : [&str; 11] = [
+    "protocol_version",
+    "name",
+    "version",
+    "known_command",
+    "list_commands",
+    "quit",
+    "boardsize",
+    "clear_board",
+    "komi",
+    "play",
+    "genmove",
+];
 
 /// This represents the controller of an GTP Engine.
 ///
 /// You establish a connection like this:
-/// ```
+/// ```no_run
 /// use std::time::Duration;
 /// use gtp::Command;
 /// use gtp::controller::Engine;
@@ -32,8 +64,334 @@ pub struct Engine {
     cmd:        String,
     rp:         super::ResponseParser,
     args:       Vec<String>,
-    handle:     Option<super::detached_command::DetachedCommand>,
-    stderr:     String,
+    config:     ProcessConfig,
+    handle:     Option<Box<dyn Transport>>,
+    stderr:     VecDeque<StderrLine>,
+    stderr_max: usize,
+    partial:    String,
+    sink:       Option<StderrSink>,
+    info:       Option<EngineInfo>,
+    transcript: Option<Recorder>,
+    watchdog:   Option<Watchdog>,
+    interrupt:  Option<Interrupt>,
+    pending:    VecDeque<Pending>,
+    decoding:   Vec<super::Diagnostic>,
+    activity:   Instant,
+    hung:       bool,
+}
+
+/// What the [`Watchdog`](struct.Watchdog.html) does with a hung engine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HangAction {
+    /// Only return `Error::Timeout`, once for each command. A late
+    /// response is still returned for its command.
+    Report,
+    /// Kill the engine process.
+    Kill,
+    /// Kill the engine process and start a new one.
+    Restart,
+}
+
+/// Detects engines that stop responding, see
+/// [`Engine::set_watchdog()`](struct.Engine.html#method.set_watchdog).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchdog {
+    /// The maximum time for a response, `None` for no limit.
+    pub deadline:       Option<Duration>,
+    /// Deadlines of specific commands, overriding `deadline`,
+    /// like a longer one for `genmove`.
+    pub deadlines:      Vec<(String, Duration)>,
+    /// The maximum time without any output on stdout or stderr
+    /// while a response is outstanding.
+    pub no_progress:    Option<Duration>,
+    pub action:         HangAction,
+}
+
+impl Watchdog {
+    /// Returns a watchdog reporting engines that don't write anything
+    /// for 60 seconds while a response is outstanding.
+    pub fn new() -> Watchdog {
+        Watchdog {
+            deadline:       None,
+            deadlines:      vec![],
+            no_progress:    Some(Duration::from_secs(60)),
+            action:         HangAction::Report,
+        }
+    }
+
+    /// Returns the deadline of the command with the given name.
+    pub fn deadline_for(&self, name: &str) -> Option<Duration> {
+        self.deadlines.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, d)| *d)
+            .or(self.deadline)
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self { Watchdog::new() }
+}
+
+/// A line the engine wrote to stderr.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StderrLine {
+    /// The ID of the oldest command without response when the
+    /// line was written, or `None` if no command was in flight.
+    pub id:         Option<u32>,
+    /// That command without ID, like `genmove b`.
+    pub command:    Option<String>,
+    /// The line without the line break.
+    pub text:       String,
+}
+
+impl std::fmt::Display for StderrLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.id, &self.command) {
+            (Some(id), Some(cmd)) => write!(f, "[{} {}] {}", id, cmd, self.text),
+            _                     => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// Where the stderr lines of an engine go besides the buffer of
+/// [`Engine::stderr()`](struct.Engine.html#method.stderr), see
+/// [`Engine::set_stderr_sink()`](struct.Engine.html#method.set_stderr_sink).
+pub enum StderrSink {
+    /// Calls the function with each line.
+    Callback(Box<dyn FnMut(&StderrLine) + Send>),
+    /// Logs each line with the `log` crate, at level info and with
+    /// target `gtp::stderr`. Needs the `log` feature.
+    #[cfg(feature = "log")]
+    Log,
+}
+
+impl StderrSink {
+    fn emit(&mut self, line: &StderrLine) {
+        match self {
+            StderrSink::Callback(f) => f(line),
+            #[cfg(feature = "log")]
+            StderrSink::Log         => log::info!(target: "gtp::stderr", "{}", line),
+        }
+    }
+}
+
+/// A command waiting for its response.
+#[derive(Debug, Clone)]
+struct Pending {
+    id:         u32,
+    command:    String,
+    sent:       Instant,
+    deadline:   Option<Instant>,
+    cancelled:  bool,
+    /// The watchdog already reported the command.
+    timed_out:  bool,
+}
+
+/// How a command is interrupted by
+/// [`Engine::cancel()`](struct.Engine.html#method.cancel).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    /// Nothing is sent, the engine finishes the command.
+    None,
+    /// An empty line, which ends `kata-analyze` and `lz-analyze`.
+    Newline,
+    /// The `# interrupt` comment of GoGui, for engines that list
+    /// `gogui-interrupt`.
+    GoGui,
+    /// SIGINT, for engines that stop searching on it. Only on Unix.
+    Signal,
+}
+
+impl Interrupt {
+    /// Picks the interrupt for the command from what the engine supports.
+    /// SIGINT is never picked, as it ends most engines.
+    pub fn choose(info: Option<&EngineInfo>, command: &str) -> Interrupt {
+        let name = command.split_whitespace().next().unwrap_or("");
+        let has  = |c: &str| info.map(|i| i.commands.iter().any(|x| x == c)).unwrap_or(false);
+
+        if name == "kata-analyze" || name == "lz-analyze" { Interrupt::Newline }
+        else if has("gogui-interrupt")                    { Interrupt::GoGui }
+        else                                              { Interrupt::None }
+    }
+}
+
+/// Commands sent at once with
+/// [`Engine::send_batch()`](struct.Engine.html#method.send_batch),
+/// waiting for their responses.
+pub struct Batch<'a> {
+    engine:         &'a mut Engine,
+    commands:       Vec<(u32, String)>,
+    sent:           Instant,
+    stop_on_error:  bool,
+}
+
+/// The response to a command of a [`Batch`](struct.Batch.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResponse {
+    pub id:         u32,
+    /// The command without ID, like `play b D4`.
+    pub command:    String,
+    pub response:   super::Response,
+    /// The time since the previous response, or since the batch was
+    /// sent for the first one. This is about the time the engine took
+    /// for the command.
+    pub elapsed:    Duration,
+    /// The time since the batch was sent.
+    pub latency:    Duration,
+}
+
+/// The responses collected by [`Batch::wait()`](struct.Batch.html#method.wait),
+/// in the order of the commands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchReport {
+    pub responses:  Vec<BatchResponse>,
+    /// Whether the collection stopped at an error response.
+    pub stopped:    bool,
+}
+
+impl BatchReport {
+    /// Returns the first error response.
+    pub fn first_error(&self) -> Option<&BatchResponse> {
+        self.responses.iter().find(|r| matches!(r.response, super::Response::Error(_)))
+    }
+
+    /// Returns true if no command failed.
+    pub fn is_ok(&self) -> bool { self.first_error().is_none() }
+}
+
+impl<'a> Batch<'a> {
+    /// Returns the IDs of the commands.
+    pub fn ids(&self) -> Vec<u32> { self.commands.iter().map(|(id, _)| *id).collect() }
+
+    /// Whether to stop collecting at the first error response. The
+    /// commands after it were already sent, so the engine executes them,
+    /// but their responses are discarded.
+    pub fn stop_on_error(mut self, stop: bool) -> Self {
+        self.stop_on_error = stop;
+        self
+    }
+
+    /// Waits up to `timeout` for all responses. Responses that aren't
+    /// for the batch are discarded. Returns `Error::Timeout` with the
+    /// first command still without response when the time is up.
+    pub fn wait(mut self, timeout: Duration) -> Result<BatchReport, Error> {
+        if self.engine.handle.is_none() { return Err(Error::NoHandle); }
+
+        let mut report = BatchReport { responses: vec![], stopped: false };
+        let mut last   = self.sent;
+        let slice      = Duration::from_millis(WAIT_SLICE_MS);
+
+        while report.responses.len() < self.commands.len() {
+            if self.sent.elapsed() > timeout {
+                let (id, command) = self.commands[report.responses.len()].clone();
+                let ids : Vec<u32> = self.ids();
+                self.engine.pending.retain(|p| !ids.contains(&p.id));
+                return Err(Error::Timeout { id, command });
+            }
+
+            let resp =
+                match self.engine.wait_response(slice) {
+                    Ok(resp)              => resp,
+                    Err(Error::PollAgain) => continue,
+                    Err(e)                => return Err(e),
+                };
+
+            // Responses without ID are for the next command.
+            let (id, command) = &self.commands[report.responses.len()];
+            if resp.id_0() != 0 && resp.id_0() != *id { continue; }
+
+            let now     = Instant::now();
+            let failed  = matches!(resp, super::Response::Error(_));
+            report.responses.push(BatchResponse {
+                id:         *id,
+                command:    command.clone(),
+                response:   resp,
+                elapsed:    now - last,
+                latency:    now - self.sent,
+            });
+            last = now;
+
+            if failed && self.stop_on_error {
+                report.stopped = true;
+                break;
+            }
+        }
+
+        if report.stopped {
+            self.drain(report.responses.len(), timeout.saturating_sub(self.sent.elapsed()));
+        }
+        Ok(report)
+    }
+
+    /// Reads and discards the responses to the commands from `from` on,
+    /// so later commands get their own responses.
+    fn drain(&mut self, from: usize, timeout: Duration) {
+        let last = match self.commands.last() {
+            Some((id, _)) => *id,
+            None          => return,
+        };
+        let start = Instant::now();
+        let mut left = self.commands.len() - from;
+        while left > 0 && start.elapsed() <= timeout {
+            match self.engine.wait_response(Duration::from_millis(WAIT_SLICE_MS)) {
+                Ok(resp) => {
+                    if resp.id_0() == last { break; }
+                    left -= 1;
+                },
+                Err(Error::PollAgain) => (),
+                Err(_)                => break,
+            }
+        }
+    }
+}
+
+/// Known engines, detected from the name and the commands of an engine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Family {
+    GnuGo,
+    LeelaZero,
+    Leela,
+    KataGo,
+    Pachi,
+    Unknown,
+}
+
+impl Family {
+    /// Detects the engine from the response to `name`, or from
+    /// its commands if the name is unknown.
+    ///
+    /// ```
+    /// use gtp::controller::Family;
+    /// assert_eq!(Family::detect("GNU Go", &[]), Family::GnuGo);
+    /// assert_eq!(Family::detect("Leela Zero", &[]), Family::LeelaZero);
+    /// assert_eq!(Family::detect("my-bot", &[String::from("kata-analyze")]), Family::KataGo);
+    /// ```
+    pub fn detect(name: &str, commands: &[String]) -> Family {
+        let name = name.to_lowercase();
+        let has  = |c: &str| commands.iter().any(|x| x == c);
+
+        if name.starts_with("gnu go")          { Family::GnuGo }
+        else if name.starts_with("leela zero") { Family::LeelaZero }
+        else if name.starts_with("leela")      { Family::Leela }
+        else if name.starts_with("katago")     { Family::KataGo }
+        else if name.starts_with("pachi")      { Family::Pachi }
+        else if has("kata-analyze")            { Family::KataGo }
+        else if has("lz-analyze")              { Family::LeelaZero }
+        else                                   { Family::Unknown }
+    }
+}
+
+/// What an engine told about itself in the
+/// [`handshake`](struct.Engine.html#method.handshake).
+/// Queries the engine didn't answer leave the fields empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineInfo {
+    pub name:       String,
+    pub version:    String,
+    pub protocol:   Option<u32>,
+    /// The response to `list_commands`.
+    pub commands:   Vec<String>,
+    pub family:     Family,
 }
 
 /// Error as returned by this module.
@@ -44,7 +402,8 @@ pub enum Error {
     ProcessError(super::detached_command::Error),
     /// This is an error when parsing responses from the engine.
     /// It might indicate either a bug in this crate or the
-    /// Engine.
+    /// Engine. The malformed line is skipped, so polling again
+    /// continues with the next response.
     ProtocolError(super::ResponseError),
     /// Returned when no engine has been `start()`ed.
     NoHandle,
@@ -52,6 +411,156 @@ pub enum Error {
     /// It means you have to call methods like `poll_response()` or `wait_response()`
     /// again.
     PollAgain,
+    /// The command with the ID didn't get a response in time, from
+    /// [`send_wait()`](struct.Engine.html#method.send_wait) or the
+    /// [`Watchdog`](struct.Watchdog.html).
+    Timeout { id: u32, command: String },
+    /// The response to the command with the ID arrived, but the command
+    /// was [`cancel()`](struct.Engine.html#method.cancel)ed.
+    Cancelled { id: u32, command: String },
+}
+
+/// Configures how the engine process is started: working directory,
+/// environment, buffering, resource limits and scheduling.
+///
+/// ```no_run
+/// use gtp::controller::EngineBuilder;
+///
+/// let mut ctrl =
+///     EngineBuilder::new("/usr/bin/leelaz")
+///         .args(&["--gtp", "-w", "best.gz"])
+///         .current_dir("/var/lib/leelaz")
+///         .env("OMP_NUM_THREADS", "2")
+///         .cpu_time_limit(3600)
+///         .address_space_limit(4 << 30)
+///         .nice(10)
+///         .cpu_affinity(&[2, 3])
+///         .process_group(true)
+///         .start()
+///         .unwrap();
+/// ```
+///
+/// With [`process_group(true)`](#method.process_group) the engine gets its
+/// own process group, and stopping or killing it also ends the helper
+/// processes the engine started. Limits, niceness and the process group
+/// need Unix, the CPU affinity needs Linux.
+#[derive(Debug, Clone)]
+pub struct EngineBuilder {
+    cmd:    String,
+    args:   Vec<String>,
+    config: ProcessConfig,
+}
+
+impl EngineBuilder {
+    /// Starts configuring the engine binary `cmd`.
+    pub fn new(cmd: &str) -> EngineBuilder {
+        EngineBuilder {
+            cmd:    cmd.to_string(),
+            args:   vec![],
+            config: ProcessConfig::default(),
+        }
+    }
+
+    /// Adds an argument.
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    /// Adds several arguments.
+    pub fn args(mut self, args: &[&str]) -> Self {
+        self.args.extend(args.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Sets the working directory of the engine.
+    pub fn current_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.config.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets an environment variable.
+    pub fn env<K: Into<OsString>, V: Into<OsString>>(mut self, key: K, value: V) -> Self {
+        self.config.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Removes an inherited environment variable.
+    pub fn env_remove<K: Into<OsString>>(mut self, key: K) -> Self {
+        self.config.env_remove.push(key.into());
+        self
+    }
+
+    /// Doesn't inherit the environment, only the variables set with
+    /// [`env()`](#method.env) are passed.
+    pub fn env_clear(mut self) -> Self {
+        self.config.env_clear = true;
+        self
+    }
+
+    /// Sets the capacity of the buffers for stdin, stdout and stderr.
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.config.buffer_size = Some(bytes);
+        self
+    }
+
+    /// Limits the CPU time of the engine, which is killed by the
+    /// system when it runs out.
+    pub fn cpu_time_limit(mut self, secs: u64) -> Self {
+        self.config.cpu_time = Some(secs);
+        self
+    }
+
+    /// Limits the address space (virtual memory) of the engine.
+    pub fn address_space_limit(mut self, bytes: u64) -> Self {
+        self.config.address_space = Some(bytes);
+        self
+    }
+
+    /// Sets the niceness of the engine, up to 19 for the lowest
+    /// priority. It's not added to the niceness of the controller.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.config.nice = Some(nice);
+        self
+    }
+
+    /// Restricts the engine to the given CPUs. Starting fails for
+    /// indexes of 1024 and above.
+    pub fn cpu_affinity(mut self, cpus: &[usize]) -> Self {
+        self.config.cpu_affinity = Some(cpus.to_vec());
+        self
+    }
+
+    /// Sets the encoding of the output of the engine, by default UTF-8.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.config.encoding = encoding;
+        self
+    }
+
+    /// Puts the engine into its own process group.
+    pub fn process_group(mut self, group: bool) -> Self {
+        self.config.process_group = group;
+        self
+    }
+
+    /// Returns the configured options.
+    pub fn config(&self) -> &ProcessConfig { &self.config }
+
+    /// Creates the engine without starting it. The configuration is
+    /// also used when the engine is restarted.
+    pub fn build(self) -> Engine {
+        let args : Vec<&str> = self.args.iter().map(|s| &s[..]).collect();
+        let mut engine = Engine::new(&self.cmd, &args);
+        engine.config = self.config;
+        engine
+    }
+
+    /// Creates and starts the engine.
+    pub fn start(self) -> Result<Engine, Error> {
+        let mut engine = self.build();
+        engine.start()?;
+        Ok(engine)
+    }
 }
 
 impl Engine {
@@ -64,50 +573,552 @@ impl Engine {
             rp:     super::ResponseParser::new(),
             cur_id: 0,
             args:   args.iter().map(|s| s.to_string()).collect(),
+            config: ProcessConfig::default(),
             handle: None,
-            stderr: String::from(""),
+            stderr: VecDeque::new(),
+            stderr_max: STDERR_LINES,
+            partial:    String::new(),
+            sink:       None,
+            info:   None,
+            transcript: None,
+            watchdog:   None,
+            interrupt:  None,
+            pending:    VecDeque::new(),
+            decoding:   Vec::new(),
+            activity:   Instant::now(),
+            hung:       false,
         }
     }
 
-    /// Starts the engine in the background.
+    /// Creates an Engine instance that talks to the engine
+    /// over the given transport, which is already running.
+    /// This is how scripted engines from the
+    /// [`mock`](../mock/index.html) module are connected.
+    ///
+    /// Calling `start()` on such an engine fails, as there is
+    /// no engine binary to start.
+    pub fn with_transport(transport: Box<dyn Transport>) -> Engine {
+        let mut engine = Engine::new("", &[]);
+        engine.handle = Some(transport);
+        engine
+    }
+
+    /// Starts the engine in the background. An engine made
+    /// [`with_transport()`](#method.with_transport) has no process to
+    /// start, it keeps its transport and `Error::ProcessError` is returned.
     pub fn start(&mut self) -> Result<(), Error> {
-        if self.handle.is_some() {
-            self.handle.as_mut().unwrap().shutdown();
-            self.handle = None;
+        if self.cmd.is_empty() && self.handle.is_some() {
+            return Err(Error::ProcessError(super::detached_command::Error::StartupFailed(
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "no engine command to start"))));
+        }
+        self.info = None;
+        self.hung = false;
+        self.pending.clear();
+        if let Some(mut hdl) = self.handle.take() {
+            hdl.shutdown();
         }
 
         let sl : Vec<&str> = self.args.iter().map(|s| &s[..]).collect();
 
-        match super::detached_command::DetachedCommand::start(&self.cmd, &sl[..]) {
+        match DetachedCommand::spawn(&self.cmd, &sl[..], &self.config) {
             Ok(hdl) => {
-                self.handle = Some(hdl);
-                return Ok(());
+                self.handle = Some(Box::new(hdl));
+                Ok(())
             },
-            Err(e) => {
-                return Err(Error::ProcessError(e));
-            }
+            Err(e) => Err(Error::ProcessError(e)),
         }
     }
 
+    /// Stops the engine gracefully: sends `quit` and waits up to `grace`
+    /// for the response, and then up to `grace` for the process to exit.
+    /// An engine that doesn't exit gets SIGTERM and another `grace`, and
+    /// is killed after that. A crashed engine isn't sent `quit`.
+    ///
+    /// Returns the exit status of the process, `None` if the engine has
+    /// no process, like a [`mock`](../mock/index.html) engine. Dropping an
+    /// `Engine` kills the process right away instead.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use gtp::controller::Engine;
+    ///
+    /// let mut ctrl = Engine::new("katago", &["gtp"]);
+    /// ctrl.start().unwrap();
+    /// let status = ctrl.stop(Duration::from_secs(5)).unwrap();
+    /// println!("exited with {:?}", status);
+    /// ```
+    pub fn stop(&mut self, grace: std::time::Duration)
+        -> Result<Option<std::process::ExitStatus>, Error>
+    {
+        if self.handle.is_none() { return Err(Error::NoHandle); }
+
+        // A crashed engine isn't asked to quit. Buffered output is kept
+        // for the parser, so `quit` isn't matched with an older response.
+        match self.collect() {
+            Err(Error::ProcessError(_)) => (),
+            out => {
+                if let Ok(out) = out { self.rp.feed(&out); }
+                let _ = self.send_wait(Command::new("quit"), grace);
+            },
+        }
+
+        let mut hdl = self.handle.take().unwrap();
+        self.info   = None;
+        let status  = hdl.stop(grace);
+        self.flush_stderr();
+        Ok(status)
+    }
+
     /// Sends a command to the engine. Returns the
     /// ID of the command.
-    pub fn send(&mut self, mut cmd: super::Command) -> u32 {
+    pub fn send(&mut self, cmd: super::Command) -> u32 {
         if self.handle.is_none() { return 0; }
 
+        let cmd_buf = self.prepare(cmd);
+        self.handle.as_mut().unwrap().send(cmd_buf);
+        self.cur_id
+    }
+
+    /// Writes all commands at once, without waiting for responses in
+    /// between. The returned [`Batch`](struct.Batch.html) collects the
+    /// responses.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use gtp::Command;
+    /// use gtp::mock::{MockEngine, Script};
+    ///
+    /// let mock = MockEngine::new(
+    ///     Script::new()
+    ///         .expect("play b D4").respond("")
+    ///         .expect("play w D4").fail("illegal move")
+    ///         .expect("play w Q16").respond(""));
+    /// let mut engine = mock.engine();
+    ///
+    /// let report =
+    ///     engine.send_batch(vec![
+    ///         Command::cmd("play", |e| e.mv_b((4, 4))),
+    ///         Command::cmd("play", |e| e.mv_w((4, 4))),
+    ///         Command::cmd("play", |e| e.mv_w((16, 16))),
+    ///     ])
+    ///     .stop_on_error(true)
+    ///     .wait(Duration::from_secs(1))
+    ///     .unwrap();
+    /// assert_eq!(report.responses.len(), 2);
+    /// assert_eq!(report.first_error().unwrap().command, "play w D4");
+    /// ```
+    pub fn send_batch(&mut self, cmds: Vec<super::Command>) -> Batch<'_> {
+        let mut commands = vec![];
+        if self.handle.is_some() {
+            let mut buf = vec![];
+            for cmd in cmds.into_iter() {
+                buf.extend(self.prepare(cmd));
+                let command = self.pending.back().map(|p| p.command.clone()).unwrap_or_default();
+                commands.push((self.cur_id, command));
+            }
+            self.handle.as_mut().unwrap().send(buf);
+        }
+
+        Batch {
+            engine:         self,
+            commands,
+            sent:           Instant::now(),
+            stop_on_error:  false,
+        }
+    }
+
+    /// Assigns the next ID to the command, registers it as pending and
+    /// records it. Returns the bytes to send.
+    fn prepare(&mut self, mut cmd: super::Command) -> Vec<u8> {
         self.cur_id += 1;
         cmd.set_id(self.cur_id);
         let cmd_buf = cmd.to_bytes();
-        self.handle.as_mut().unwrap().send(cmd_buf);
-        self.cur_id
+        let text = String::from_utf8_lossy(&cmd_buf);
+        let text = text.trim().split_once(' ').map(|(_, c)| c).unwrap_or("").to_string();
+        let name = text.split(' ').next().unwrap_or("");
+        let now      = Instant::now();
+        let deadline = self.watchdog.as_ref().and_then(|wd| wd.deadline_for(name)).map(|d| now + d);
+        // Responses read with recv_raw() never remove their commands.
+        if self.pending.len() >= MAX_PENDING { self.pending.pop_front(); }
+        self.pending.push_back(Pending {
+            id: self.cur_id, command: text, sent: now, deadline,
+            cancelled: false, timed_out: false,
+        });
+        if let Some(t) = self.transcript.as_mut() {
+            t.record(Direction::Sent, &String::from_utf8_lossy(&cmd_buf));
+        }
+        cmd_buf
+    }
+
+    /// Cancels the command with the ID, which is still waiting for its
+    /// response. The engine is interrupted as set with
+    /// [`set_interrupt()`](#method.set_interrupt), or as picked by
+    /// [`Interrupt::choose()`](enum.Interrupt.html#method.choose). The
+    /// engine still answers the command, to keep the responses in order,
+    /// but the response is returned as `Error::Cancelled`.
+    ///
+    /// Returns the interrupt that was sent, `None` if the command isn't
+    /// waiting for a response.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use gtp::Command;
+    /// use gtp::controller::Error;
+    /// use gtp::mock::{MockEngine, Script};
+    ///
+    /// let mock = MockEngine::new(
+    ///     Script::new()
+    ///         .expect("genmove b").delay(Duration::from_millis(100)).respond("D4")
+    ///         .expect("name").respond("mock"));
+    /// let mut engine = mock.engine();
+    ///
+    /// let id = engine.send(Command::cmd("genmove", |e| e.b()));
+    /// assert!(engine.cancel(id).unwrap().is_some());
+    /// assert!(matches!(engine.wait_response(Duration::from_secs(1)),
+    ///                  Err(Error::Cancelled { id: 1, .. })));
+    /// let resp = engine.send_wait(Command::new("name"), Duration::from_secs(1)).unwrap();
+    /// assert_eq!(resp.text(), "mock");
+    /// ```
+    pub fn cancel(&mut self, id: u32) -> Result<Option<Interrupt>, Error> {
+        if self.handle.is_none() { return Err(Error::NoHandle); }
+
+        let pending = match self.pending.iter_mut().find(|p| p.id == id && !p.cancelled) {
+            Some(p) => p,
+            None    => return Ok(None),
+        };
+        pending.cancelled = true;
+        pending.deadline  = None;
+        let command = pending.command.clone();
+
+        let interrupt =
+            self.interrupt.unwrap_or_else(|| Interrupt::choose(self.info.as_ref(), &command));
+        match interrupt {
+            Interrupt::None    => (),
+            Interrupt::Newline => self.send_str("\n"),
+            Interrupt::GoGui   => self.send_str("# interrupt\n"),
+            Interrupt::Signal  => {
+                if !self.handle.as_mut().unwrap().interrupt() { return Ok(Some(Interrupt::None)); }
+            },
+        }
+        Ok(Some(interrupt))
+    }
+
+    /// Sets how [`cancel()`](#method.cancel) interrupts the engine,
+    /// `None` to pick it by the command and the supported commands.
+    pub fn set_interrupt(&mut self, interrupt: Option<Interrupt>) {
+        self.interrupt = interrupt;
+    }
+
+    /// Sends a raw string to the engine, without assigning an ID.
+    /// Useful for sending text that `Command` can't represent,
+    /// like comments or empty lines.
+    pub fn send_str(&mut self, s: &str) {
+        if let Some(hdl) = self.handle.as_mut() {
+            if let Some(t) = self.transcript.as_mut() { t.record(Direction::Sent, s); }
+            hdl.send(s.as_bytes().to_vec());
+        }
+    }
+
+    /// Sends a command and waits up to `timeout` for the response
+    /// with the ID of the command. Responses to other commands that
+    /// arrive in the meantime are discarded.
+    pub fn send_wait(&mut self, cmd: super::Command, timeout: std::time::Duration)
+        -> Result<super::Response, Error>
+    {
+        let command = cmd.to_string().trim().to_string();
+        let id      = self.send(cmd);
+        if id == 0 { return Err(Error::NoHandle); }
+
+        let instant = std::time::Instant::now();
+        let slice   = std::time::Duration::from_millis(WAIT_SLICE_MS);
+        loop {
+            match self.wait_response(slice) {
+                Ok(resp) => {
+                    if resp.id_0() == id { return Ok(resp); }
+                },
+                Err(Error::PollAgain) => (),
+                Err(Error::Cancelled { id: other, .. }) if other != id => (),
+                Err(e) => return Err(e),
+            }
+
+            if instant.elapsed() > timeout {
+                self.pending.retain(|p| p.id != id);
+                return Err(Error::Timeout { id, command });
+            }
+        }
+    }
+
+    /// Asks the engine for `name`, `version`, `protocol_version` and
+    /// `list_commands` and caches the answers until the engine is
+    /// restarted. Errors of the engine are ignored, failing to
+    /// communicate with it is returned.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use gtp::controller::Family;
+    /// use gtp::mock::{MockEngine, Script};
+    ///
+    /// let mock = MockEngine::new(Script::new()
+    ///     .expect("name").respond("GNU Go")
+    ///     .expect("version").respond("3.8")
+    ///     .expect("protocol_version").respond("2")
+    ///     .expect("list_commands").respond("genmove\nplay"));
+    /// let mut engine = mock.engine();
+    /// let info = engine.handshake(Duration::from_secs(1)).unwrap();
+    /// assert_eq!(info.family, Family::GnuGo);
+    /// assert!(engine.supports("play"));
+    /// assert!(!engine.supports("kgs-genmove_cleanup"));
+    /// ```
+    pub fn handshake(&mut self, timeout: std::time::Duration) -> Result<&EngineInfo, Error> {
+        let mut query = |name: &str| -> Result<Option<String>, Error> {
+            match self.send_wait(Command::new(name), timeout)? {
+                super::Response::Result((_, t)) => Ok(Some(t.trim().to_string())),
+                super::Response::Error(_)       => Ok(None),
+            }
+        };
+
+        let name     = query("name")?.unwrap_or_default();
+        let version  = query("version")?.unwrap_or_default();
+        let protocol = query("protocol_version")?.and_then(|p| p.parse().ok());
+        let commands : Vec<String> =
+            query("list_commands")?
+                .unwrap_or_default()
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect();
+
+        let family = Family::detect(&name, &commands);
+        self.info  = Some(EngineInfo { name, version, protocol, commands, family });
+        Ok(self.info.as_ref().unwrap())
+    }
+
+    /// Returns the cached result of the last
+    /// [`handshake`](#method.handshake) since the engine was started.
+    pub fn info(&self) -> Option<&EngineInfo> { self.info.as_ref() }
+
+    /// Returns true if the engine listed the command in the
+    /// [`handshake`](#method.handshake). If the engine didn't answer
+    /// `list_commands`, the commands required by GTP are assumed.
+    /// Always false without a handshake.
+    pub fn supports(&self, cmd: &str) -> bool {
+        match &self.info {
+            Some(info) if info.commands.is_empty() =>
+                REQUIRED_COMMANDS.contains(&cmd),
+            Some(info) => info.commands.iter().any(|c| c == cmd),
+            None       => false,
+        }
+    }
+
+    /// Watches for a hung engine, or stops watching with `None`. While
+    /// waiting for a response, the engine is considered hung when
+    /// a command exceeds its deadline, or when the engine doesn't write
+    /// anything for `Watchdog::no_progress`. Then `Error::Timeout` is
+    /// returned for the command, and the engine is killed or restarted
+    /// according to `Watchdog::action`. If the restart fails, for
+    /// instance for a [`mock`](../mock/index.html) engine, the error of
+    /// [`start()`](#method.start) is returned instead.
+    ///
+    /// Commands already waiting for their response get the deadlines
+    /// of the new watchdog, counted from when they were sent.
+    ///
+    /// The watchdog doesn't look at the output read with
+    /// [`recv_raw()`](#method.recv_raw), like streamed analysis.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use gtp::Command;
+    /// use gtp::controller::{Error, Watchdog};
+    /// use gtp::mock::{MockEngine, Script};
+    ///
+    /// let mock = MockEngine::new(Script::new()
+    ///     .expect("genmove b").delay(Duration::from_secs(5)).respond("D4"));
+    /// let mut engine = mock.engine();
+    ///
+    /// let mut wd = Watchdog::new();
+    /// wd.no_progress = Some(Duration::from_millis(50));
+    /// engine.set_watchdog(Some(wd));
+    ///
+    /// let resp = engine.send_wait(Command::cmd("genmove", |e| e.b()), Duration::from_secs(10));
+    /// assert!(matches!(resp, Err(Error::Timeout { id: 1, .. })));
+    /// assert!(engine.is_hung());
+    /// ```
+    pub fn set_watchdog(&mut self, watchdog: Option<Watchdog>) {
+        for p in self.pending.iter_mut().filter(|p| !p.timed_out) {
+            let name = p.command.split(' ').next().unwrap_or("");
+            p.deadline = watchdog.as_ref().and_then(|wd| wd.deadline_for(name)).map(|d| p.sent + d);
+        }
+        self.watchdog = watchdog;
+    }
+
+    /// Returns true if the watchdog found the engine hung, until it
+    /// writes something again or is restarted with `start()`.
+    pub fn is_hung(&self) -> bool { self.hung }
+
+    /// Returns the error for a hung engine, and kills or restarts it.
+    fn check_hang(&mut self) -> Result<(), Error> {
+        let wd = match &self.watchdog {
+            Some(wd) => wd,
+            None     => return Ok(()),
+        };
+        let first = match self.pending.front() {
+            Some(p) => p,
+            None    => return Ok(()),
+        };
+
+        let now     = Instant::now();
+        let overdue =
+            self.pending.iter()
+                .position(|p| !p.timed_out && p.deadline.map(|d| now >= d).unwrap_or(false));
+        // A stall is reported once, until the engine writes again.
+        let stalled =
+            !self.hung && !first.timed_out
+            && wd.no_progress
+                 .map(|limit| now.duration_since(self.activity.max(first.sent)) >= limit)
+                 .unwrap_or(false);
+
+        let hung = match overdue {
+            Some(pos)        => pos,
+            None if stalled  => 0,
+            None             => return Ok(()),
+        };
+        let err = Error::Timeout {
+            id:      self.pending[hung].id,
+            command: self.pending[hung].command.clone(),
+        };
+
+        match wd.action {
+            HangAction::Report  => self.pending[hung].timed_out = true,
+            HangAction::Kill    => {
+                if let Some(mut hdl) = self.handle.take() { hdl.shutdown(); }
+                self.pending.clear();
+            },
+            HangAction::Restart => {
+                self.pending.clear();
+                if let Err(e) = self.start() {
+                    self.hung = true;
+                    return Err(e);
+                }
+            },
+        }
+        self.hung = true;
+        Err(err)
+    }
+
+    /// Records the session to a transcript, or stops recording with
+    /// `None`. See the [`transcript`](../transcript/index.html) module.
+    pub fn set_transcript(&mut self, transcript: Option<Recorder>) {
+        self.transcript = transcript;
     }
 
-    /// Returns the currently captured stderr output of the engine.
+    /// Sets how strictly the responses of the engine are parsed.
+    /// See also [`Strictness`](../enum.Strictness.html).
+    pub fn set_strictness(&mut self, strictness: super::Strictness) {
+        self.rp.set_strictness(strictness);
+    }
+
+    /// Returns and clears the specification violations found in the
+    /// responses of the engine. Only filled in strict mode, except for
+    /// output that isn't valid in the
+    /// [`encoding()`](struct.EngineBuilder.html#method.encoding) of the engine.
+    pub fn take_diagnostics(&mut self) -> Vec<super::Diagnostic> {
+        let mut diags = std::mem::take(&mut self.decoding);
+        diags.extend(self.rp.take_diagnostics());
+        diags
+    }
+
+    /// Returns the currently captured stderr output of the engine:
+    /// the last lines kept by [`set_stderr_limit()`](#method.set_stderr_limit)
+    /// and a started line.
     #[allow(dead_code)]
-    pub fn stderr(&self) -> String { self.stderr.clone() }
+    pub fn stderr(&self) -> String {
+        let mut out = String::new();
+        for line in self.stderr.iter() {
+            out += &line.text;
+            out += "\n";
+        }
+        out + &self.partial
+    }
+
+    /// Returns the captured stderr lines, tagged with the command
+    /// the engine was working on.
+    pub fn stderr_lines(&self) -> Vec<StderrLine> {
+        self.stderr.iter().cloned().collect()
+    }
 
     /// Clears the up to now returned output of the engine.
     #[allow(dead_code)]
-    pub fn clear_stderr(&mut self) { self.stderr = String::from(""); }
+    pub fn clear_stderr(&mut self) {
+        self.stderr.clear();
+        self.partial.clear();
+    }
+
+    /// Sets how many of the last stderr lines are kept, by default 1000.
+    /// With 0 only the sink gets the lines.
+    pub fn set_stderr_limit(&mut self, lines: usize) {
+        self.stderr_max = lines;
+        while self.stderr.len() > lines { self.stderr.pop_front(); }
+    }
+
+    /// Passes each complete stderr line also to the sink, or to no
+    /// sink with `None`.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    /// use gtp::Command;
+    /// use gtp::controller::StderrSink;
+    /// use gtp::mock::{MockEngine, Script};
+    ///
+    /// let mock = MockEngine::new(
+    ///     Script::new().expect("genmove b").stderr("thinking\n").respond("D4"));
+    /// let mut engine = mock.engine();
+    ///
+    /// let lines = Arc::new(Mutex::new(vec![]));
+    /// let l = lines.clone();
+    /// engine.set_stderr_sink(Some(StderrSink::Callback(Box::new(move |line| {
+    ///     l.lock().unwrap().push(line.to_string());
+    /// }))));
+    ///
+    /// engine.send_wait(Command::cmd("genmove", |e| e.b()), Duration::from_secs(1)).unwrap();
+    /// assert_eq!(*lines.lock().unwrap(), vec!["[1 genmove b] thinking"]);
+    /// ```
+    pub fn set_stderr_sink(&mut self, sink: Option<StderrSink>) {
+        self.sink = sink;
+    }
+
+    /// Splits new stderr output into lines tagged with the command in
+    /// flight, and passes them to the buffer and the sink.
+    fn push_stderr(&mut self, err: &str) {
+        self.partial += err;
+        while let Some(pos) = self.partial.find('\n') {
+            let mut text : String = self.partial.drain(..=pos).collect();
+            text.pop();
+            if text.ends_with('\r') { text.pop(); }
+            self.emit_stderr(text);
+        }
+    }
+
+    /// Passes on a started line, after the engine is gone.
+    fn flush_stderr(&mut self) {
+        if !self.partial.is_empty() {
+            let text = std::mem::take(&mut self.partial);
+            self.emit_stderr(text);
+        }
+    }
+
+    fn emit_stderr(&mut self, text: String) {
+        let line = StderrLine {
+            id:      self.pending.front().map(|p| p.id),
+            command: self.pending.front().map(|p| p.command.clone()),
+            text,
+        };
+        if let Some(sink) = self.sink.as_mut() { sink.emit(&line); }
+        if self.stderr_max > 0 {
+            if self.stderr.len() >= self.stderr_max { self.stderr.pop_front(); }
+            self.stderr.push_back(line);
+        }
+    }
 
     /// This method waits for a maximum amount of time for a response
     /// from the GTP engine.
@@ -133,44 +1144,111 @@ impl Engine {
         }
     }
 
-    /// This method polls once for a response from the GTP engine.
-    ///
-    /// If no response was found `Error::PollAgain` is returned.
-    pub fn poll_response(&mut self) -> Result<super::Response, Error> {
-        if self.handle.is_none() { return Err(Error::NoHandle); }
+    /// Polls once for output of the engine and returns it unparsed,
+    /// or an empty string if there was none. This bypasses the
+    /// response parser, for handling output that isn't a plain response,
+    /// like the streamed results of `lz-analyze`. Don't mix it with the
+    /// methods waiting for responses.
+    pub fn recv_raw(&mut self) -> Result<String, Error> {
+        self.collect()
+    }
 
-        let hdl = self.handle.as_mut().unwrap();
+    /// Gives output read with [`recv_raw()`](#method.recv_raw) back to
+    /// the response parser, for output after the streamed part.
+    pub fn feed_raw(&mut self, out: &str) {
+        if !out.is_empty() { self.rp.feed(out); }
+    }
+
+    /// Polls the engine, passes its stderr output to `push_stderr()` and
+    /// returns the new stdout output.
+    fn collect(&mut self) -> Result<String, Error> {
+        let hdl = match self.handle.as_mut() {
+            Some(hdl) => hdl,
+            None      => return Err(Error::NoHandle),
+        };
+
+        // Keep the last words of a crashing engine.
+        let polled = hdl.poll();
+        self.decoding.extend(hdl.take_diagnostics());
+        let err    = if hdl.stderr_available() { hdl.recv_stderr() } else { String::new() };
+        let out    = if hdl.stdout_available() { hdl.recv_stdout() } else { String::new() };
+
+        if !err.is_empty() || !out.is_empty() {
+            self.activity = Instant::now();
+            self.hung     = false;
+        }
 
-        let p = hdl.poll();
-        if p.is_err() {
-            return Err(Error::ProcessError(p.unwrap_err()));
+        if !err.is_empty() {
+            if let Some(t) = self.transcript.as_mut() { t.record(Direction::Stderr, &err); }
+            self.push_stderr(&err);
         }
 
-        if hdl.stderr_available() {
-            self.stderr += &hdl.recv_stderr();
-            println!("err: {}", self.stderr);
+        // The output before the disconnect comes first, the
+        // transport reports the error again on the next poll.
+        if let Err(e) = polled {
+            if out.is_empty() {
+                self.flush_stderr();
+                return Err(Error::ProcessError(e));
+            }
         }
 
-        if hdl.stdout_available() {
-            self.rp.feed(&hdl.recv_stdout());
+        if !out.is_empty() {
+            if let Some(t) = self.transcript.as_mut() { t.record(Direction::Stdout, &out); }
+        }
+        Ok(out)
+    }
 
-            if let Ok(resp) = self.rp.get_response() {
+    /// This method polls once for a response from the GTP engine.
+    ///
+    /// If no response was found `Error::PollAgain` is returned.
+    pub fn poll_response(&mut self) -> Result<super::Response, Error> {
+        let out = self.collect()?;
+
+        if !out.is_empty() {
+            self.rp.feed(&out);
+        }
+
+        // Also check without new output, there might be more than
+        // one response buffered:
+        match self.rp.get_response() {
+            Ok(resp) => {
+                // Responses without ID are for the oldest command.
+                let id = resp.id_0();
+                let done =
+                    if id == 0 {
+                        self.pending.pop_front()
+                    } else if let Some(pos) = self.pending.iter().position(|p| p.id == id) {
+                        self.pending.drain(..=pos).next_back()
+                    } else {
+                        None
+                    };
+                if let Some(Pending { id, command, cancelled: true, .. }) = done {
+                    return Err(Error::Cancelled { id, command });
+                }
                 return Ok(resp);
-            }
+            },
+            // The parser skipped the malformed line, the next
+            // poll continues with the output after it.
+            Err(e @ super::ResponseError::BadResponse { .. }) =>
+                return Err(Error::ProtocolError(e)),
+            Err(super::ResponseError::IncompleteResponse) => (),
         }
 
-        return Err(Error::PollAgain);
+        self.check_hang()?;
+        Err(Error::PollAgain)
     }
 }
 
-#[allow(unused_imports)]
 use super::Command;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
+    use crate::mock::{MockEngine, Script};
 
     #[test]
+    #[ignore = "requires /usr/bin/gnugo"]
     fn check_gnugo_version() {
         let mut ctrl = Engine::new("/usr/bin/gnugo", &["--mode", "gtp"]);
 
@@ -184,4 +1262,389 @@ mod tests {
         assert_eq!(resp.text(), "GNU Go");
     }
 
+    #[test]
+    fn check_mock_name() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("name").respond("GNU Go")
+                .expect("quit").respond(""));
+        let mut ctrl = mock.engine();
+
+        ctrl.send(Command::cmd("name", |e| e));
+        let resp = ctrl.wait_response(Duration::from_millis(500)).unwrap();
+        let ev = resp.entities(|ep| ep.s().s()).unwrap();
+        assert_eq!(ev[0].to_string(), "GNU");
+        assert_eq!(resp.id_0(), 1);
+        assert_eq!(resp.text(), "GNU Go");
+
+        ctrl.send(Command::new("quit"));
+        assert!(ctrl.wait_response(Duration::from_millis(500)).is_ok());
+        mock.assert_done();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_stop() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut ctrl = Engine::new("sh", &["-c", "read x; printf '=1\\n\\n'; exit 3"]);
+        ctrl.start().unwrap();
+        let status = ctrl.stop(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert!(matches!(ctrl.stop(Duration::from_secs(1)), Err(Error::NoHandle)));
+
+        let mut ctrl = Engine::new("sh", &["-c", "trap '' TERM; exec sleep 10"]);
+        ctrl.start().unwrap();
+        let status = ctrl.stop(Duration::from_millis(100)).unwrap().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+
+        let mock = MockEngine::new(Script::new().expect("quit").respond(""));
+        let mut ctrl = mock.engine();
+        assert!(ctrl.stop(Duration::from_secs(1)).unwrap().is_none());
+        mock.assert_done();
+
+        // A background child keeps stdout and stderr open.
+        let start = Instant::now();
+        let mut ctrl = Engine::new("sh", &["-c", "sleep 5 & read x"]);
+        ctrl.start().unwrap();
+        drop(ctrl);
+        let mut ctrl = Engine::new("sh", &["-c", "sleep 5 & read x; exit 4"]);
+        ctrl.start().unwrap();
+        let status = ctrl.stop(Duration::from_millis(100)).unwrap().unwrap();
+        assert_eq!(status.code(), Some(4));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn check_builder() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let script =
+            "read x; pg=$(cut -d' ' -f5 /proc/$$/stat); ni=$(cut -d' ' -f19 /proc/$$/stat); \
+             printf '=1 %s %s %s %s %s %s\\n\\n' \"$FOO\" \"${HOME:-none}\" \"$(pwd)\" \
+                    \"$(ulimit -t)\" \"$([ \"$pg\" = $$ ] && echo group || echo nogroup)\" \"$ni\"; \
+             read x";
+        let mut ctrl =
+            EngineBuilder::new("/bin/sh")
+                .args(&["-c", script])
+                .current_dir(&dir)
+                .env_clear()
+                .env("FOO", "bar")
+                .buffer_size(64)
+                .cpu_time_limit(100)
+                .nice(1)
+                .cpu_affinity(&[0])
+                .process_group(true)
+                .start()
+                .unwrap();
+
+        let resp = ctrl.send_wait(Command::new("x"), Duration::from_secs(5)).unwrap();
+        assert_eq!(resp.text(),
+                   format!("bar none {} 100 group 1", dir.display()));
+        ctrl.stop(Duration::from_secs(1)).unwrap();
+
+        // The helper in the process group is stopped with the engine.
+        let script = "sleep 5 & read x; printf '=1 %s\\n\\n' $!; read x; exit 4";
+        let mut ctrl =
+            EngineBuilder::new("/bin/sh").args(&["-c", script]).process_group(true).start().unwrap();
+        let helper = ctrl.send_wait(Command::new("x"), Duration::from_secs(5)).unwrap().text();
+        let status = ctrl.stop(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(status.code(), Some(4));
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", helper.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "helper still running: {}", stat);
+
+        let res = EngineBuilder::new("/bin/sh").args(&["-c", "exit 0"]).cpu_affinity(&[0, 4096]).start();
+        assert!(matches!(res, Err(Error::ProcessError(_))));
+
+        let mut ctrl = EngineBuilder::new("/bin/sh").args(&["-c", "exit 0"]).build();
+        assert!(ctrl.info().is_none());
+        assert!(matches!(ctrl.send_wait(Command::new("x"), Duration::from_millis(1)),
+                         Err(Error::NoHandle)));
+        assert!(ctrl.start().is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_encoding() {
+        let script =
+            "read x; printf '=1 G\\366t\\n\\n'; printf 'Z\\351ro\\n' >&2; \
+             read x; printf '=2 ok\\n\\n'; read x";
+        let t = Duration::from_secs(5);
+
+        let mut ctrl = EngineBuilder::new("sh").args(&["-c", script]).start().unwrap();
+        let resp = ctrl.send_wait(Command::new("name"), t).unwrap();
+        assert_eq!(resp.text(), "G\u{fffd}t");
+        let resp = ctrl.send_wait(Command::new("version"), t).unwrap();
+        assert_eq!(resp.text(), "ok");
+        assert_eq!(ctrl.stderr(), "Z\u{fffd}ro\n");
+
+        let mut diags = ctrl.take_diagnostics();
+        diags.sort_by_key(|d| d.position);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].position, 1);
+        assert_eq!(diags[0].violation,
+                   super::super::Violation::InvalidEncoding { stderr: true, bytes: vec![0xe9] });
+        assert_eq!(diags[1].position, 4);
+        assert_eq!(diags[1].violation,
+                   super::super::Violation::InvalidEncoding { stderr: false, bytes: vec![0xf6] });
+        assert_eq!(diags[1].to_string(), "invalid UTF-8 [f6] on stdout at byte 4");
+
+        let mut ctrl =
+            EngineBuilder::new("sh").args(&["-c", script]).encoding(Encoding::Latin1).start().unwrap();
+        let resp = ctrl.send_wait(Command::new("name"), t).unwrap();
+        assert_eq!(resp.text(), "G\u{f6}t");
+        ctrl.send_wait(Command::new("version"), t).unwrap();
+        assert_eq!(ctrl.stderr(), "Z\u{e9}ro\n");
+        assert!(ctrl.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn check_watchdog() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("genmove b").stderr("thinking\n")
+                                    .delay(Duration::from_millis(60))
+                                    .stderr("still thinking\n")
+                                    .delay(Duration::from_millis(60))
+                                    .respond("D4")
+                .expect("showboard").delay(Duration::from_millis(300)).respond("")
+                .expect("name").delay(Duration::from_secs(5)).respond("x"));
+        let mut ctrl = mock.engine();
+
+        let mut wd = Watchdog::new();
+        wd.no_progress = Some(Duration::from_millis(100));
+        wd.deadline    = Some(Duration::from_millis(200));
+        wd.deadlines.push((String::from("genmove"), Duration::from_secs(1)));
+        ctrl.set_watchdog(Some(wd.clone()));
+
+        let t = Duration::from_secs(10);
+        let resp = ctrl.send_wait(Command::cmd("genmove", |e| e.b()), t).unwrap();
+        assert_eq!(resp.text(), "D4");
+        assert!(!ctrl.is_hung());
+
+        wd.no_progress = None;
+        ctrl.set_watchdog(Some(wd.clone()));
+        match ctrl.send_wait(Command::new("showboard"), t) {
+            Err(Error::Timeout { id, command }) => assert_eq!((id, &command[..]), (2, "showboard")),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(ctrl.is_hung());
+
+        wd.action = HangAction::Kill;
+        ctrl.set_watchdog(Some(wd));
+        assert!(matches!(ctrl.send_wait(Command::new("name"), t), Err(Error::Timeout { .. })));
+        assert!(matches!(ctrl.send_wait(Command::new("name"), t), Err(Error::NoHandle)));
+
+        let mock = MockEngine::new(
+            Script::new().expect("version").delay(Duration::from_secs(1)).respond("1"));
+        let resp = mock.engine().send_wait(Command::new("version"), Duration::from_millis(50));
+        assert!(matches!(resp, Err(Error::Timeout { id: 1, .. })));
+
+        // A watchdog set later also watches the commands sent before.
+        let mock = MockEngine::new(
+            Script::new().expect("genmove b").delay(Duration::from_secs(5)).respond("D4"));
+        let mut ctrl = mock.engine();
+        ctrl.send(Command::cmd("genmove", |e| e.b()));
+        let mut wd = Watchdog::new();
+        wd.deadline = Some(Duration::from_millis(50));
+        wd.action   = HangAction::Restart;
+        ctrl.set_watchdog(Some(wd));
+        // Mock engines can't be restarted:
+        assert!(matches!(ctrl.wait_response(Duration::from_millis(400)),
+                         Err(Error::ProcessError(_))));
+        assert!(ctrl.is_hung());
+    }
+
+    #[test]
+    fn check_watchdog_late_response() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("genmove b").delay(Duration::from_millis(300)).respond("D4")
+                .expect("name").respond("mock"));
+        let mut ctrl = mock.engine();
+        let mut wd = Watchdog::new();
+        wd.deadline    = Some(Duration::from_millis(50));
+        wd.no_progress = Some(Duration::from_millis(50));
+        ctrl.set_watchdog(Some(wd));
+
+        ctrl.send(Command::cmd("genmove", |e| e.b()));
+        ctrl.send(Command::new("name"));
+        let t = Duration::from_millis(400);
+        assert!(matches!(ctrl.wait_response(t), Err(Error::Timeout { id: 1, .. })));
+        // The other command is reported once, the first one not again:
+        assert!(matches!(ctrl.wait_response(t), Err(Error::Timeout { id: 2, .. })));
+        let resp = ctrl.wait_response(t).unwrap();
+        assert_eq!((resp.id_0(), resp.text()), (1, String::from("D4")));
+        assert!(!ctrl.is_hung());
+        let resp = ctrl.wait_response(t).unwrap();
+        assert_eq!((resp.id_0(), resp.text()), (2, String::from("mock")));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn check_start_keeps_transport() {
+        let mock = MockEngine::new(Script::new().expect("name").respond("Mock"));
+        let mut ctrl = mock.engine();
+        assert!(matches!(ctrl.start(), Err(Error::ProcessError(_))));
+        let resp = ctrl.send_wait(Command::new("name"), Duration::from_secs(1)).unwrap();
+        assert_eq!(resp.text(), "Mock");
+        mock.assert_done();
+    }
+
+    #[test]
+    fn check_stderr() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("genmove b").stderr("one\ntw").stderr("o\r\n").respond("D4")
+                .expect("genmove w").stderr("three\n").respond("Q16")
+                .expect("quit").stderr("bye").respond(""));
+        let mut ctrl = mock.engine();
+        ctrl.set_stderr_limit(2);
+
+        let t = Duration::from_secs(1);
+        ctrl.send_wait(Command::cmd("genmove", |e| e.b()), t).unwrap();
+        ctrl.send_wait(Command::cmd("genmove", |e| e.w()), t).unwrap();
+        assert_eq!(ctrl.stderr(), "two\nthree\n");
+        assert_eq!(ctrl.stderr_lines()[1], StderrLine {
+            id:      Some(2),
+            command: Some(String::from("genmove w")),
+            text:    String::from("three"),
+        });
+
+        ctrl.clear_stderr();
+        ctrl.send_wait(Command::new("quit"), t).unwrap();
+        assert_eq!(ctrl.stderr(), "bye");
+        assert!(ctrl.stderr_lines().is_empty());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn check_batch() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("boardsize 9").respond("")
+                .expect("play b C3").delay(Duration::from_millis(50)).respond("")
+                .expect("play w C3").fail("illegal move")
+                .expect("play w G7").respond("")
+                .expect("name").respond("mock")
+                .expect("genmove b").delay(Duration::from_secs(5)).respond("D4"));
+        let mut ctrl = mock.engine();
+
+        let batch = ctrl.send_batch(vec![
+            Command::cmd("boardsize", |e| e.i(9)),
+            Command::cmd("play", |e| e.mv_b((3, 3))),
+            Command::cmd("play", |e| e.mv_w((3, 3))),
+            Command::cmd("play", |e| e.mv_w((7, 7))),
+        ]);
+        assert_eq!(batch.ids(), vec![1, 2, 3, 4]);
+        let report = batch.stop_on_error(true).wait(Duration::from_secs(1)).unwrap();
+        assert!(report.stopped);
+        assert!(!report.is_ok());
+        assert_eq!(report.responses.len(), 3);
+        assert_eq!(report.responses[1].command, "play b C3");
+        assert!(report.responses[1].elapsed >= Duration::from_millis(50));
+        assert!(report.responses[2].latency >= report.responses[1].latency);
+        assert_eq!(report.first_error().unwrap().id, 3);
+
+        let resp = ctrl.send_wait(Command::new("name"), Duration::from_secs(1)).unwrap();
+        assert_eq!(resp.text(), "mock");
+
+        let report = ctrl.send_batch(vec![]).wait(Duration::from_secs(1)).unwrap();
+        assert!(report.responses.is_empty() && report.is_ok());
+
+        let batch = ctrl.send_batch(vec![Command::cmd("genmove", |e| e.b())]);
+        match batch.wait(Duration::from_millis(50)) {
+            Err(Error::Timeout { id, command }) => assert_eq!((id, &command[..]), (6, "genmove b")),
+            r => panic!("unexpected {:?}", r),
+        }
+        mock.assert_done();
+    }
+
+    #[test]
+    fn check_cancel() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("name").respond("bot")
+                .expect("version").respond("1")
+                .expect("protocol_version").respond("2")
+                .expect("list_commands").respond("genmove\ngogui-interrupt\nlz-analyze")
+                .expect("genmove b").delay(Duration::from_millis(50)).respond("D4")
+                .expect("genmove w").delay(Duration::from_millis(50)).respond("Q16")
+                .expect("showboard").respond(""));
+        let mut ctrl = mock.engine();
+        ctrl.handshake(Duration::from_secs(1)).unwrap();
+
+        let info = ctrl.info();
+        assert_eq!(Interrupt::choose(info, "lz-analyze b 10"), Interrupt::Newline);
+        assert_eq!(Interrupt::choose(info, "genmove b"), Interrupt::GoGui);
+        assert_eq!(Interrupt::choose(None, "genmove b"), Interrupt::None);
+
+        let b = ctrl.send(Command::cmd("genmove", |e| e.b()));
+        let w = ctrl.send(Command::cmd("genmove", |e| e.w()));
+        assert_eq!(ctrl.cancel(b).unwrap(), Some(Interrupt::GoGui));
+        assert_eq!(ctrl.cancel(b).unwrap(), None);
+        ctrl.set_interrupt(Some(Interrupt::None));
+        assert_eq!(ctrl.cancel(w).unwrap(), Some(Interrupt::None));
+        let resp = ctrl.send_wait(Command::new("showboard"), Duration::from_secs(1));
+        assert!(resp.is_ok());
+        assert_eq!(ctrl.cancel(w).unwrap(), None);
+        mock.assert_done();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_interrupt_signal() {
+        let script =
+            "trap 'kill $!; printf \"=1 interrupted\\n\\n\"' INT; \
+             read x; sleep 5 & wait $!; read x";
+        let mut ctrl = Engine::new("sh", &["-c", script]);
+        ctrl.start().unwrap();
+        ctrl.set_interrupt(Some(Interrupt::Signal));
+
+        let start = Instant::now();
+        let id = ctrl.send(Command::cmd("genmove", |e| e.b()));
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(ctrl.cancel(id).unwrap(), Some(Interrupt::Signal));
+        match ctrl.wait_response(Duration::from_secs(3)) {
+            Err(Error::Cancelled { id, command }) => assert_eq!((id, &command[..]), (1, "genmove b")),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn check_mock_faults() {
+        let mock = MockEngine::new(
+            Script::new()
+                .expect("genmove b").delay(Duration::from_millis(100))
+                                    .stderr("thinking...\n")
+                                    .respond("D4")
+                .expect("play *").fail("illegal move")
+                .expect("showboard").raw("garbage\n\n").respond("")
+                .expect("undo").respond("").crash());
+        let mut ctrl = mock.engine();
+
+        ctrl.send(Command::cmd("genmove", |e| e.b()));
+        assert!(matches!(ctrl.poll_response(), Err(Error::PollAgain)));
+        let resp = ctrl.send_wait(Command::cmd("genmove", |e| e.b()), Duration::from_secs(1));
+        assert!(matches!(resp, Ok(super::super::Response::Error(_))));
+        assert_eq!(mock.unexpected(), vec![String::from("genmove b")]);
+
+        let resp = ctrl.send_wait(Command::cmd("play", |e| e.mv_w((4, 4))),
+                                  Duration::from_secs(1)).unwrap();
+        assert_eq!(resp, super::super::Response::Error((Some(3), String::from("illegal move"))));
+        assert_eq!(ctrl.stderr(), "thinking...\n");
+
+        ctrl.send(Command::new("showboard"));
+        assert!(matches!(ctrl.wait_response(Duration::from_millis(100)),
+                         Err(Error::ProtocolError(_))));
+        let resp = ctrl.wait_response(Duration::from_millis(100)).unwrap();
+        assert_eq!(resp, super::super::Response::Result((Some(4), String::new())));
+
+        ctrl.send(Command::new("undo"));
+        let resp = ctrl.wait_response(Duration::from_millis(100)).unwrap();
+        assert_eq!(resp, super::super::Response::Result((Some(5), String::new())));
+        assert!(matches!(ctrl.wait_response(Duration::from_millis(100)),
+                         Err(Error::ProcessError(_))));
+    }
 }
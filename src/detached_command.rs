@@ -18,23 +18,232 @@ engines is not the interface with the GTP controller. So this might
 never gets optimized.
 */
 
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::process::Command;
+use std::process::ExitStatus;
 use std::process::Stdio;
 use std::sync::mpsc;
 use std::io::Write;
 use std::io::BufRead;
+use std::io::Read;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
+use super::Diagnostic;
 use super::ResponseParser;
+use super::Violation;
+
+/// How long stopping waits for the I/O threads.
+const JOIN_WAIT_MS : u64 = 200;
 
 #[derive(Debug, Clone)]
 pub enum CapturedOutput {
     Stderr(String),
     Stdout(String),
+    /// Output that couldn't be decoded.
+    Diagnostic(Diagnostic),
+}
+
+/// How the output of the engine is decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    /// UTF-8, invalid bytes are replaced by U+FFFD and reported as
+    /// [`Violation::InvalidEncoding`](../enum.Violation.html#variant.InvalidEncoding).
+    #[default]
+    Utf8,
+    /// ISO 8859-1, where every byte is a character.
+    Latin1,
+}
+
+impl Encoding {
+    /// Decodes the bytes. Returns the text, and the offsets and bytes
+    /// of invalid sequences.
+    ///
+    /// ```
+    /// use gtp::detached_command::Encoding;
+    /// assert_eq!(Encoding::Utf8.decode(b"= G\xf6\n"), ("= G\u{fffd}\n".to_string(), vec![(3, vec![0xf6])]));
+    /// assert_eq!(Encoding::Latin1.decode(b"= G\xf6\n"), ("= G\u{f6}\n".to_string(), vec![]));
+    /// ```
+    pub fn decode(self, mut bytes: &[u8]) -> (String, Vec<(usize, Vec<u8>)>) {
+        if self == Encoding::Latin1 {
+            return (bytes.iter().map(|b| *b as char).collect(), vec![]);
+        }
+
+        let mut text    = String::new();
+        let mut invalid = vec![];
+        let mut offset  = 0;
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(s) => {
+                    text += s;
+                    return (text, invalid);
+                },
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    let len   = e.error_len().unwrap_or(bytes.len() - valid);
+                    text += std::str::from_utf8(&bytes[..valid]).unwrap();
+                    text.push(std::char::REPLACEMENT_CHARACTER);
+                    invalid.push((offset + valid, bytes[valid..valid + len].to_vec()));
+                    offset += valid + len;
+                    bytes   = &bytes[valid + len..];
+                },
+            }
+        }
+    }
+}
+
+/// Reads the lines of the output and sends them decoded.
+fn read_lines<R: Read>(rd: R, capacity: usize, encoding: Encoding, stderr: bool,
+                       tx: mpsc::Sender<CapturedOutput>)
+{
+    let mut br     = std::io::BufReader::with_capacity(capacity, rd);
+    let mut offset = 0;
+    loop {
+        let mut line = vec![];
+        match br.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let (text, invalid) = encoding.decode(&line);
+                for (pos, bytes) in invalid.into_iter() {
+                    let diag = Diagnostic {
+                        position:   offset + pos,
+                        violation:  Violation::InvalidEncoding { stderr, bytes },
+                    };
+                    if tx.send(CapturedOutput::Diagnostic(diag)).is_err() { return; }
+                }
+                let out = if stderr { CapturedOutput::Stderr(text) } else { CapturedOutput::Stdout(text) };
+                if tx.send(out).is_err() { return; }
+                offset += n;
+            },
+        }
+    }
+}
+
+/// Options for starting the engine process, see
+/// [`controller::EngineBuilder`](../controller/struct.EngineBuilder.html).
+///
+/// The resource limits, the niceness and the process group are only
+/// supported on Unix, the CPU affinity only on Linux. Elsewhere starting
+/// the process fails if they are set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessConfig {
+    /// The working directory of the process.
+    pub current_dir:    Option<PathBuf>,
+    /// Environment variables to set.
+    pub env:            Vec<(OsString, OsString)>,
+    /// Environment variables to remove.
+    pub env_remove:     Vec<OsString>,
+    /// Whether to start without inheriting the environment.
+    pub env_clear:      bool,
+    /// The capacity of the stdin, stdout and stderr buffers, in bytes.
+    pub buffer_size:    Option<usize>,
+    /// The limit of CPU time, in seconds (`RLIMIT_CPU`).
+    pub cpu_time:       Option<u64>,
+    /// The limit of the address space, in bytes (`RLIMIT_AS`).
+    pub address_space:  Option<u64>,
+    /// The niceness of the process, from -20 (highest priority) to 19
+    /// (lowest). It replaces the niceness inherited from the controller,
+    /// setting a lower one than that needs privileges.
+    pub nice:           Option<i32>,
+    /// The indexes of the CPUs the process may run on, below
+    /// `CPU_SETSIZE` (1024).
+    pub cpu_affinity:   Option<Vec<usize>>,
+    /// Whether to put the process in its own process group. Stopping
+    /// the engine then signals the whole group, including helper
+    /// processes the engine started.
+    pub process_group:  bool,
+    /// The encoding of stdout and stderr.
+    pub encoding:       Encoding,
+}
+
+impl ProcessConfig {
+    #[cfg(unix)]
+    fn apply(&self, cmd: &mut Command) -> std::io::Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        if self.process_group { cmd.process_group(0); }
+
+        #[cfg(target_os = "linux")]
+        let cpu_set =
+            match &self.cpu_affinity {
+                Some(cpus) => {
+                    if let Some(cpu) = cpus.iter().find(|c| **c >= libc::CPU_SETSIZE as usize) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!("CPU index {} out of range", cpu)));
+                    }
+                    let mut set : libc::cpu_set_t = unsafe { std::mem::zeroed() };
+                    for cpu in cpus.iter() { unsafe { libc::CPU_SET(*cpu, &mut set); } }
+                    Some(set)
+                },
+                None => None,
+            };
+        #[cfg(not(target_os = "linux"))]
+        {
+            if self.cpu_affinity.is_some() {
+                return Err(unsupported("CPU affinity"));
+            }
+        }
+
+        let limits = [(libc::RLIMIT_CPU, self.cpu_time), (libc::RLIMIT_AS, self.address_space)];
+        let nice   = self.nice;
+
+        // Runs in the forked child, so only calls async-signal-safe functions.
+        let setup = move || {
+            for (resource, limit) in limits.iter() {
+                if let Some(limit) = limit {
+                    let rlim = libc::rlimit {
+                        rlim_cur: *limit as libc::rlim_t,
+                        rlim_max: *limit as libc::rlim_t,
+                    };
+                    if unsafe { libc::setrlimit(*resource, &rlim) } != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+            }
+            if let Some(nice) = nice {
+                if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            #[cfg(target_os = "linux")]
+            {
+                if let Some(set) = &cpu_set {
+                    let size = std::mem::size_of::<libc::cpu_set_t>();
+                    if unsafe { libc::sched_setaffinity(0, size, set) } != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+            }
+            Ok(())
+        };
+        unsafe { cmd.pre_exec(setup); }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply(&self, _cmd: &mut Command) -> std::io::Result<()> {
+        if self.cpu_time.is_some() || self.address_space.is_some() {
+            return Err(unsupported("resource limits"));
+        }
+        if self.nice.is_some()         { return Err(unsupported("niceness")); }
+        if self.cpu_affinity.is_some() { return Err(unsupported("CPU affinity")); }
+        if self.process_group          { return Err(unsupported("process groups")); }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+fn unsupported(what: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported,
+                        format!("{} not supported on this platform", what))
 }
 
 pub struct DetachedCommand {
     child:          std::process::Child,
+    group:          bool,
     reader:         Option<std::thread::JoinHandle<()>>,
     err_reader:     Option<std::thread::JoinHandle<()>>,
     writer:         Option<std::thread::JoinHandle<()>>,
@@ -42,6 +251,7 @@ pub struct DetachedCommand {
     wr_tx:          Option<mpsc::Sender<Vec<u8>>>,
     stdout_chunks:  Vec<String>,
     stderr_chunks:  Vec<String>,
+    diagnostics:    Vec<Diagnostic>,
 }
 
 #[derive(Debug)]
@@ -50,8 +260,61 @@ pub enum Error {
     Disconnected,
 }
 
+/// The interface the [`controller::Engine`](../controller/struct.Engine.html)
+/// uses to exchange bytes with an engine.
+///
+/// It's implemented by `DetachedCommand` for engine processes and by
+/// [`mock::MockTransport`](../mock/struct.MockTransport.html) for
+/// scripted in-process engines.
+pub trait Transport: Send {
+    /// Queues bytes to be sent to the engine.
+    fn send(&mut self, buffer: Vec<u8>);
+    /// Collects the output the engine produced in the meantime.
+    /// Returns `Error::Disconnected` if the engine is gone.
+    fn poll(&mut self) -> Result<(), Error>;
+    fn stdout_available(&self) -> bool;
+    fn stderr_available(&self) -> bool;
+    /// Returns and clears the collected stdout output.
+    fn recv_stdout(&mut self) -> String;
+    /// Returns and clears the collected stderr output.
+    fn recv_stderr(&mut self) -> String;
+    /// Stops the engine immediately.
+    fn shutdown(&mut self);
+    /// Waits up to `grace` for the engine to exit on its own, then
+    /// escalates, see [`DetachedCommand::stop()`](struct.DetachedCommand.html#method.stop).
+    /// Returns the exit status, `None` if there is no process.
+    fn stop(&mut self, _grace: Duration) -> Option<ExitStatus> {
+        self.shutdown();
+        None
+    }
+    /// Sends SIGINT to the engine. Returns false if that isn't possible.
+    fn interrupt(&mut self) -> bool { false }
+    /// Returns and clears the problems with decoding the output.
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> { vec![] }
+}
+
+impl Transport for DetachedCommand {
+    fn send(&mut self, buffer: Vec<u8>) { DetachedCommand::send(self, buffer) }
+    fn poll(&mut self) -> Result<(), Error> { DetachedCommand::poll(self) }
+    fn stdout_available(&self) -> bool { DetachedCommand::stdout_available(self) }
+    fn stderr_available(&self) -> bool { DetachedCommand::stderr_available(self) }
+    fn recv_stdout(&mut self) -> String { DetachedCommand::recv_stdout(self) }
+    fn recv_stderr(&mut self) -> String { DetachedCommand::recv_stderr(self) }
+    fn shutdown(&mut self) { DetachedCommand::shutdown(self) }
+    fn stop(&mut self, grace: Duration) -> Option<ExitStatus> { DetachedCommand::stop(self, grace) }
+    fn interrupt(&mut self) -> bool { DetachedCommand::interrupt(self) }
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> { DetachedCommand::take_diagnostics(self) }
+}
+
 impl DetachedCommand {
     pub fn start(cmd: &str, args: &[&str]) -> Result<DetachedCommand, Error> {
+        DetachedCommand::spawn(cmd, args, &ProcessConfig::default())
+    }
+
+    /// Starts the process with the given options.
+    pub fn spawn(cmd: &str, args: &[&str], config: &ProcessConfig)
+        -> Result<DetachedCommand, Error>
+    {
         let mut o = Command::new(cmd);
         o.stdout(Stdio::piped())
          .stderr(Stdio::piped())
@@ -61,6 +324,12 @@ impl DetachedCommand {
             o.arg(arg);
         }
 
+        if let Some(dir) = &config.current_dir { o.current_dir(dir); }
+        if config.env_clear { o.env_clear(); }
+        for key in config.env_remove.iter() { o.env_remove(key); }
+        for (key, value) in config.env.iter() { o.env(key, value); }
+        config.apply(&mut o).map_err(Error::StartupFailed)?;
+
         let o = o.spawn();
 
         if let Err(io_err) = o {
@@ -72,11 +341,12 @@ impl DetachedCommand {
         let stdin    = o.stdin.take().unwrap();
         let stdout   = o.stdout.take().unwrap();
         let stderr   = o.stderr.take().unwrap();
+        let buffer_size = config.buffer_size.unwrap_or(8 * 1024);
         let (tx, rx) = std::sync::mpsc::channel();
         let (stdin_tx , stdin_rx) : (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>) = std::sync::mpsc::channel();
 
         let writer = thread::spawn(move || {
-            let mut bw = std::io::BufWriter::new(stdin);
+            let mut bw = std::io::BufWriter::with_capacity(buffer_size, stdin);
             while let Ok(bytes) = stdin_rx.recv() {
                 if let Ok(s) = bw.write(&bytes) {
                     if s == 0 { break; }
@@ -87,38 +357,23 @@ impl DetachedCommand {
             };
         });
 
+        let encoding  = config.encoding;
         let tx_stdout = tx.clone();
         let reader = thread::spawn(move || {
-            let mut br = std::io::BufReader::new(stdout);
-            loop {
-                let mut line = String::from("");
-                if let Ok(s) = br.read_line(&mut line) {
-                    if tx_stdout.send(CapturedOutput::Stdout(line)).is_err() { break; }
-                    if s == 0 { break; }
-                } else {
-                    break;
-                }
-            }
+            read_lines(stdout, buffer_size, encoding, false, tx_stdout);
         });
 
         let tx_stderr = tx.clone();
         let err_reader = thread::spawn(move || {
-            let mut br = std::io::BufReader::new(stderr);
-            loop {
-                let mut line = String::from("");
-                if let Ok(s) = br.read_line(&mut line) {
-                    if tx_stderr.send(CapturedOutput::Stderr(line)).is_err() { break; }
-                    if s == 0 { break; }
-                } else {
-                    break;
-                }
-            }
+            read_lines(stderr, buffer_size, encoding, true, tx_stderr);
         });
 
         Ok(DetachedCommand {
             child:              o,
+            group:              config.process_group,
             stderr_chunks:      Vec::new(),
             stdout_chunks:      Vec::new(),
+            diagnostics:        Vec::new(),
             reader:             Some(reader),
             err_reader:         Some(err_reader),
             writer:             Some(writer),
@@ -176,6 +431,9 @@ impl DetachedCommand {
                 Ok(CapturedOutput::Stderr(input)) => {
                     self.stderr_chunks.push(input);
                 },
+                Ok(CapturedOutput::Diagnostic(diag)) => {
+                    self.diagnostics.push(diag);
+                },
                 Err(mpsc::TryRecvError::Empty) => {
                     return Ok(());
                 },
@@ -186,14 +444,116 @@ impl DetachedCommand {
         }
     }
 
-    #[allow(unused_must_use)]
+    /// Kills the process and waits for it and the I/O threads.
+    /// Does nothing if the process was stopped already.
     pub fn shutdown(&mut self) {
-        drop(self.wr_tx.take().unwrap());
-        self.child.kill();
-        self.writer.take().unwrap().join();
-        self.reader.take().unwrap().join();
-        self.err_reader.take().unwrap().join();
+        if self.rd_rx.is_none() { return; }
+        drop(self.wr_tx.take());
+        self.kill();
+        let _ = self.child.wait();
+        self.join();
     }
+
+    /// Returns and clears the problems with decoding the output.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Sends SIGINT to the process, or its process group. Returns false
+    /// if the process was stopped already, or not on Unix.
+    pub fn interrupt(&mut self) -> bool {
+        if self.rd_rx.is_none() { return false; }
+        self.int()
+    }
+
+    #[cfg(unix)]
+    fn int(&mut self) -> bool {
+        self.signal(libc::SIGINT);
+        true
+    }
+
+    #[cfg(not(unix))]
+    fn int(&mut self) -> bool { false }
+
+    /// Closes stdin and waits up to `grace` for the process to exit.
+    /// If it doesn't, it's sent SIGTERM and given another `grace`,
+    /// and finally killed. With a process group, the signals go to the
+    /// whole group, and helpers left after the process exited on its own
+    /// get SIGTERM. Returns the exit status, `None` if the process was
+    /// stopped already.
+    pub fn stop(&mut self, grace: Duration) -> Option<ExitStatus> {
+        self.rd_rx.as_ref()?;
+        drop(self.wr_tx.take());
+
+        let mut status = self.wait_exit(grace);
+        if status.is_some() && self.group {
+            self.terminate();
+        }
+        if status.is_none() {
+            self.terminate();
+            status = self.wait_exit(grace);
+        }
+        if status.is_none() {
+            self.kill();
+            status = self.child.wait().ok();
+        }
+
+        self.join();
+        status
+    }
+
+    fn wait_exit(&mut self, timeout: Duration) -> Option<ExitStatus> {
+        let start = Instant::now();
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(status)) => return Some(status),
+                Ok(None)         => (),
+                Err(_)           => return None,
+            }
+            if start.elapsed() >= timeout { return None; }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[cfg(unix)]
+    fn signal(&mut self, signal: libc::c_int) {
+        let pid = self.child.id() as libc::pid_t;
+        unsafe { libc::kill(if self.group { -pid } else { pid }, signal); }
+    }
+
+    #[cfg(unix)]
+    fn terminate(&mut self) { self.signal(libc::SIGTERM); }
+
+    #[cfg(unix)]
+    fn kill(&mut self) { self.signal(libc::SIGKILL); }
+
+    #[cfg(not(unix))]
+    fn terminate(&mut self) { self.kill(); }
+
+    #[cfg(not(unix))]
+    fn kill(&mut self) { let _ = self.child.kill(); }
+
+    /// Joins the I/O threads, after the process exited. A helper process of the engine may still
+    /// hold the pipes open, then the threads are left to end on their
+    /// own after a short wait, instead of blocking forever.
+    fn join(&mut self) {
+        self.rd_rx = None;
+        let start = Instant::now();
+        for t in [self.writer.take(), self.reader.take(), self.err_reader.take()].iter_mut() {
+            while t.as_ref().map(|t| !t.is_finished()).unwrap_or(false)
+                  && start.elapsed() < Duration::from_millis(JOIN_WAIT_MS)
+            {
+                thread::sleep(Duration::from_millis(5));
+            }
+            if let Some(t) = t.take() {
+                if t.is_finished() { let _ = t.join(); }
+            }
+        }
+    }
+}
+
+impl Drop for DetachedCommand {
+    fn drop(&mut self) { self.shutdown(); }
 }
 
 pub fn doit() {
@@ -206,11 +566,10 @@ pub fn doit() {
 
     dc.send_str("10 list_commands\n");
     loop {
-        let p = dc.poll();
-        if p.is_err() {
+        if let Err(e) = dc.poll() {
             println!("stdout: [{}]", dc.recv_stdout());
             println!("stderr: [{}]", dc.recv_stderr());
-            println!("Error in poll: {:?}", p.unwrap_err());
+            println!("Error in poll: {:?}", e);
             break;
 
         }
@@ -226,7 +585,7 @@ pub fn doit() {
                     10 => {
                         let ents = resp.entities(|ep| { while !ep.is_eof() { ep.s(); } ep }).unwrap();
                         for cmd in ents.iter() {
-                            println!("command {}", cmd.to_string());
+                            println!("command {}", cmd);
                         }
                         dc.send_str("11 showboard\n");
                     },
@@ -0,0 +1,640 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Translation of abstract operations to the commands an engine supports.
+
+Engines implement different extensions of GTP. KataGo analyzes with
+`kata-analyze`, Leela Zero with `lz-analyze`. KGS engines know
+`kgs-genmove_cleanup` and `kgs-time_settings`, others only `genmove` and
+`time_settings`. A [`Translator`](struct.Translator.html) looks up the
+commands of the engine, found by
+[`Engine::handshake()`](../controller/struct.Engine.html#method.handshake),
+and picks the best command for an operation, falling back to plain GTP
+where possible:
+
+| Operation                   | Commands, in order of preference            |
+|-----------------------------|---------------------------------------------|
+| `genmove(color, true)`      | `kgs-genmove_cleanup`, `genmove`            |
+| `time_settings(..)`         | `kgs-time_settings`, `time_settings`        |
+| `analyze(..)`               | `kata-analyze`, `lz-analyze`                |
+| `heatmap()`                 | `heatmap`                                   |
+
+Some engines print what they thought about a move to stderr.
+[`Translator::genmove_analyzed()`](struct.Translator.html#method.genmove_analyzed)
+parses the stderr lines written during the `genmove` into a
+[`SearchSummary`](struct.SearchSummary.html): the candidate moves of the
+search summary of Leela Zero, or the warnings of GNU Go. This logs
+evaluations without the analysis extensions.
+
+Byo-yomi can't be expressed with `time_settings`, it is approximated
+with Canadian overtime of one stone per period, with only one period.
+
+```no_run
+use std::time::Duration;
+use gtp::Color;
+use gtp::controller::Engine;
+use gtp::dialect::{TimeSystem, Translator};
+
+let mut engine = Engine::new("katago", &["gtp"]);
+engine.start().unwrap();
+
+let mut t = Translator::new(engine, Duration::from_secs(10));
+t.time_settings(&TimeSystem::ByoYomi { main: 600, period: 30, periods: 5 }).unwrap();
+for info in t.analyze(Color::B, 50, Duration::from_secs(2)).unwrap() {
+    println!("{} {} {:.1}%", info.mv, info.visits, info.winrate * 100.0);
+}
+println!("cleanup move: {}", t.genmove(Color::W, true).unwrap());
+```
+*/
+
+use std::time::Duration;
+use std::time::Instant;
+
+use super::controller;
+use super::controller::Engine;
+use super::controller::Family;
+use super::controller::StderrLine;
+use super::Color;
+use super::Command;
+use super::Response;
+
+/// The streaming analysis commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Analyzer {
+    /// KataGo's `kata-analyze`, with winrates from 0 to 1.
+    Kata,
+    /// Leela Zero's `lz-analyze`, with winrates from 0 to 10000.
+    Lz,
+}
+
+impl Analyzer {
+    /// Returns the name of the command.
+    pub fn command(self) -> &'static str {
+        match self {
+            Analyzer::Kata => "kata-analyze",
+            Analyzer::Lz   => "lz-analyze",
+        }
+    }
+}
+
+/// Time controls, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSystem {
+    /// No time limit.
+    None,
+    /// Sudden death after the main time.
+    Absolute { main: u32 },
+    /// `periods` periods of `period` seconds each after the main time.
+    ByoYomi { main: u32, period: u32, periods: u32 },
+    /// `stones` moves every `period` seconds after the main time.
+    Canadian { main: u32, period: u32, stones: u32 },
+}
+
+/// A candidate move of an analysis, normalized over the analyzers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisInfo {
+    /// The vertex or `pass`.
+    pub mv:         String,
+    pub visits:     u32,
+    /// The winrate of the side to move, from 0 to 1.
+    pub winrate:    f32,
+    /// The policy prior, from 0 to 1.
+    pub prior:      Option<f32>,
+    /// The expected score lead of the side to move, only from KataGo.
+    pub score_lead: Option<f32>,
+    /// The principal variation, starting with `mv`.
+    pub pv:         Vec<String>,
+}
+
+fn is_vertex(s: &str) -> bool {
+    if s.eq_ignore_ascii_case("pass") { return true; }
+    let mut chars = s.chars();
+    let letter = chars.next().map(|c| c.is_ascii_alphabetic()).unwrap_or(false);
+    letter && s.len() > 1 && chars.all(|c| c.is_ascii_digit())
+}
+
+/// Parses a line of analysis output, which may contain several `info`
+/// entries. Returns an empty list for lines without any.
+///
+/// ```
+/// use gtp::dialect::{parse_analysis, Analyzer};
+/// let infos = parse_analysis(
+///     "info move D4 visits 10 winrate 5500 prior 1000 order 0 pv D4 Q16 \
+///      info move Q16 visits 2 winrate 4800 prior 900 order 1 pv Q16",
+///     Analyzer::Lz);
+/// assert_eq!(infos.len(), 2);
+/// assert_eq!(infos[0].winrate, 0.55);
+/// assert_eq!(infos[0].pv, vec!["D4", "Q16"]);
+/// ```
+pub fn parse_analysis(line: &str, analyzer: Analyzer) -> Vec<AnalysisInfo> {
+    let scale = match analyzer {
+        Analyzer::Kata => 1.0,
+        Analyzer::Lz   => 10000.0,
+    };
+
+    let mut infos = vec![];
+    let mut words = line.split_whitespace().peekable();
+    while let Some(word) = words.next() {
+        if word != "info" { continue; }
+
+        let mut info = AnalysisInfo {
+            mv: String::new(), visits: 0, winrate: 0.0,
+            prior: None, score_lead: None, pv: vec![],
+        };
+        while let Some(key) = words.peek().cloned() {
+            if key == "info" { break; }
+            words.next();
+
+            if key == "pv" {
+                while let Some(v) = words.peek() {
+                    if !is_vertex(v) { break; }
+                    info.pv.push(v.to_string());
+                    words.next();
+                }
+                continue;
+            }
+
+            let value = match words.peek() {
+                Some(v) if *v != "info" => *v,
+                _ => break,
+            };
+            let num = value.parse::<f32>().ok();
+            match key {
+                "move"      => info.mv         = value.to_string(),
+                "visits"    => info.visits     = num.unwrap_or(0.0) as u32,
+                "winrate"   => info.winrate    = num.unwrap_or(0.0) / scale,
+                "prior"     => info.prior      = num.map(|p| p / scale),
+                "scoreLead" => info.score_lead = num,
+                _ => continue,
+            }
+            words.next();
+        }
+
+        if !info.mv.is_empty() { infos.push(info); }
+    }
+    infos
+}
+
+/// The stderr output of an engine that can be parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StderrFormat {
+    /// The search summary of Leela Zero after a move:
+    /// `D4 -> 1234 (V: 51.23%) (LCB: 50.89%) (N: 23.45%) PV: D4 Q16`
+    /// for each candidate, then `1357 visits, 689 nodes, 1234 playouts, 245 n/s`.
+    LeelaZero,
+    /// The warnings of GNU Go, every line is one.
+    GnuGo,
+}
+
+impl StderrFormat {
+    /// Returns the format of the engine family, if known.
+    pub fn for_family(family: Family) -> Option<StderrFormat> {
+        match family {
+            Family::LeelaZero => Some(StderrFormat::LeelaZero),
+            Family::GnuGo     => Some(StderrFormat::GnuGo),
+            _                 => None,
+        }
+    }
+}
+
+/// What the engine printed to stderr about a generated move.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SearchSummary {
+    /// The candidate moves, best first.
+    pub candidates: Vec<AnalysisInfo>,
+    /// The visits of the whole search.
+    pub visits:     Option<u32>,
+    /// The playouts of the whole search.
+    pub playouts:   Option<u32>,
+    /// Warnings of the engine.
+    pub warnings:   Vec<String>,
+}
+
+fn percent(s: &str, key: &str) -> Option<f32> {
+    let start = s.find(key)? + key.len();
+    let rest  = &s[start..];
+    let end   = rest.find('%')?;
+    rest[..end].trim().parse::<f32>().ok().map(|p| p / 100.0)
+}
+
+fn parse_lz_candidate(line: &str) -> Option<AnalysisInfo> {
+    let (mv, rest) = line.split_once("->")?;
+    let mv = mv.trim();
+    if !is_vertex(mv) { return None; }
+
+    let visits = rest.split_whitespace().next()?.parse::<u32>().ok()?;
+    let pv =
+        match rest.split_once("PV:") {
+            Some((_, pv)) => pv.split_whitespace().filter(|v| is_vertex(v)).map(String::from).collect(),
+            None          => vec![mv.to_string()],
+        };
+    Some(AnalysisInfo {
+        mv:         mv.to_string(),
+        visits,
+        winrate:    percent(rest, "(V:")?,
+        prior:      percent(rest, "(N:"),
+        score_lead: None,
+        pv,
+    })
+}
+
+/// Parses the stderr lines an engine wrote about a move.
+///
+/// ```
+/// use gtp::dialect::{parse_stderr, StderrFormat};
+/// let summary = parse_stderr(&[
+///     "NN eval=0.484823",
+///     "  D4 ->     120 (V: 51.23%) (LCB: 50.10%) (N: 23.45%) PV: D4 Q16 D16",
+///     " Q16 ->      30 (V: 49.50%) (LCB: 45.00%) (N: 20.00%) PV: Q16 D4",
+///     "151 visits, 140 nodes, 150 playouts, 245 n/s",
+/// ], StderrFormat::LeelaZero);
+/// assert_eq!(summary.candidates.len(), 2);
+/// assert_eq!(summary.candidates[0].mv, "D4");
+/// assert_eq!(summary.candidates[0].pv, vec!["D4", "Q16", "D16"]);
+/// assert_eq!(summary.playouts, Some(150));
+/// ```
+pub fn parse_stderr(lines: &[&str], format: StderrFormat) -> SearchSummary {
+    let mut summary = SearchSummary::default();
+    for line in lines.iter() {
+        let line = line.trim();
+        if line.is_empty() { continue; }
+
+        match format {
+            StderrFormat::LeelaZero => {
+                if let Some(info) = parse_lz_candidate(line) {
+                    summary.candidates.push(info);
+                } else if line.ends_with("n/s") {
+                    for part in line.split(',') {
+                        let mut words = part.split_whitespace();
+                        let num = words.next().and_then(|n| n.parse::<u32>().ok());
+                        match words.next() {
+                            Some("visits")   => summary.visits   = num,
+                            Some("playouts") => summary.playouts = num,
+                            _ => (),
+                        }
+                    }
+                }
+            },
+            StderrFormat::GnuGo => summary.warnings.push(line.to_string()),
+        }
+    }
+    summary
+}
+
+/// A generated move, with what the engine printed about it, see
+/// [`Translator::genmove_analyzed()`](struct.Translator.html#method.genmove_analyzed).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyzedMove {
+    /// The vertex, `pass` or `resign`.
+    pub mv:         String,
+    /// The parsed stderr output, `None` if the format of the engine
+    /// isn't known.
+    pub search:     Option<SearchSummary>,
+}
+
+/// Error as returned by the [`Translator`](struct.Translator.html).
+#[derive(Debug)]
+pub enum Error {
+    /// Communicating with the engine failed.
+    Engine(controller::Error),
+    /// The engine answered the command with an error.
+    Rejected(String, String),
+    /// The engine supports no command for the operation.
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Engine(e)           => write!(f, "engine failed: {:?}", e),
+            Error::Rejected(cmd, msg)  => write!(f, "engine rejected '{}': {}", cmd, msg),
+            Error::Unsupported(op)     => write!(f, "engine doesn't support {}", op),
+        }
+    }
+}
+
+/// Wraps an [`Engine`](../controller/struct.Engine.html) and translates
+/// abstract operations to the commands it supports.
+pub struct Translator {
+    engine:     Engine,
+    timeout:    Duration,
+    format:     Option<Option<StderrFormat>>,
+}
+
+impl Translator {
+    /// Wraps a started engine. `timeout` is the maximum time to wait
+    /// for a response.
+    pub fn new(engine: Engine, timeout: Duration) -> Translator {
+        Translator { engine, timeout, format: None }
+    }
+
+    /// Returns the engine, for sending other commands.
+    pub fn engine(&mut self) -> &mut Engine { &mut self.engine }
+
+    /// Returns the engine, dropping the translator.
+    pub fn into_engine(self) -> Engine { self.engine }
+
+    /// Returns true if the engine supports the command. The commands are
+    /// queried with [`Engine::handshake()`](../controller/struct.Engine.html#method.handshake)
+    /// if the engine didn't do it yet since it was started.
+    pub fn supports(&mut self, cmd: &str) -> bool {
+        if self.engine.info().is_none() {
+            let _ = self.engine.handshake(self.timeout);
+        }
+        self.engine.supports(cmd)
+    }
+
+    fn request(&mut self, cmd: Command) -> Result<String, Error> {
+        let text = cmd.to_string().trim().to_string();
+        match self.engine.send_wait(cmd, self.timeout) {
+            Ok(Response::Result((_, t))) => Ok(t),
+            Ok(Response::Error((_, msg))) => Err(Error::Rejected(text, msg)),
+            Err(e) => Err(Error::Engine(e)),
+        }
+    }
+
+    /// Generates a move. With `cleanup`, the engine is asked to capture
+    /// all dead stones, if it supports `kgs-genmove_cleanup`.
+    /// Returns the vertex, `pass` or `resign`.
+    pub fn genmove(&mut self, color: Color, cleanup: bool) -> Result<String, Error> {
+        let name =
+            if cleanup && self.supports("kgs-genmove_cleanup") { "kgs-genmove_cleanup" }
+            else                                              { "genmove" };
+        let mv = self.request(Command::cmd(name, |e| e.color(color == Color::W)))?;
+        Ok(mv.trim().to_string())
+    }
+
+    /// Sets the format of the stderr output, instead of choosing it by
+    /// the [`Family`](../controller/enum.Family.html) of the engine.
+    /// `None` disables parsing.
+    pub fn set_stderr_format(&mut self, format: Option<StderrFormat>) {
+        self.format = Some(format);
+    }
+
+    /// Returns the format used for the stderr output of the engine.
+    pub fn stderr_format(&mut self) -> Option<StderrFormat> {
+        if let Some(format) = self.format { return format; }
+        if self.engine.info().is_none() {
+            let _ = self.engine.handshake(self.timeout);
+        }
+        self.engine.info().and_then(|i| StderrFormat::for_family(i.family))
+    }
+
+    /// Generates a move like [`genmove()`](#method.genmove), and parses
+    /// the stderr lines the engine wrote since the command was sent.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use gtp::Color;
+    /// use gtp::controller::Engine;
+    /// use gtp::dialect::Translator;
+    ///
+    /// let mut engine = Engine::new("leelaz", &["--gtp", "-w", "best.gz"]);
+    /// engine.start().unwrap();
+    ///
+    /// let mut t = Translator::new(engine, Duration::from_secs(30));
+    /// let mv = t.genmove_analyzed(Color::B, false).unwrap();
+    /// for info in mv.search.unwrap().candidates {
+    ///     println!("{} {} {:.1}%", info.mv, info.visits, info.winrate * 100.0);
+    /// }
+    /// ```
+    pub fn genmove_analyzed(&mut self, color: Color, cleanup: bool)
+        -> Result<AnalyzedMove, Error>
+    {
+        let format = self.stderr_format();
+        let name =
+            if cleanup && self.supports("kgs-genmove_cleanup") { "kgs-genmove_cleanup" }
+            else                                              { "genmove" };
+        let cmd  = Command::cmd(name, |e| e.color(color == Color::W));
+        let text = cmd.to_string().trim().to_string();
+
+        let resp = self.engine.send_wait(cmd, self.timeout).map_err(Error::Engine)?;
+        let id   = resp.id_0();
+        let mv =
+            match resp {
+                Response::Result((_, t))   => t.trim().to_string(),
+                Response::Error((_, msg))  => return Err(Error::Rejected(text, msg)),
+            };
+
+        let format = match format {
+            Some(format) => format,
+            None         => return Ok(AnalyzedMove { mv, search: None }),
+        };
+        self.settle_stderr()?;
+
+        // Lines written after the response have no command in flight.
+        let lines = self.engine.stderr_lines();
+        let lines : Vec<&str> =
+            lines.iter()
+                 .skip_while(|l| l.id != Some(id))
+                 .take_while(|l| l.id == Some(id) || l.id.is_none())
+                 .map(|l: &StderrLine| &l.text[..])
+                 .collect();
+        Ok(AnalyzedMove { mv, search: Some(parse_stderr(&lines, format)) })
+    }
+
+    /// Waits a little for stderr output that arrives after the response.
+    fn settle_stderr(&mut self) -> Result<(), Error> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(100) {
+            let len = self.engine.stderr().len();
+            std::thread::sleep(Duration::from_millis(20));
+            self.engine.recv_raw().map_err(Error::Engine)?;
+            if len > 0 && self.engine.stderr().len() == len { break; }
+        }
+        Ok(())
+    }
+
+    /// Sets the time controls for both colors.
+    pub fn time_settings(&mut self, time: &TimeSystem) -> Result<(), Error> {
+        let cmd =
+            if self.supports("kgs-time_settings") {
+                Command::cmd("kgs-time_settings", |e| match *time {
+                    TimeSystem::None => e.s("none"),
+                    TimeSystem::Absolute { main } => e.s("absolute").i(main).list(),
+                    TimeSystem::ByoYomi { main, period, periods } =>
+                        e.s("byoyomi").i(main).i(period).i(periods).list(),
+                    TimeSystem::Canadian { main, period, stones } =>
+                        e.s("canadian").i(main).i(period).i(stones).list(),
+                })
+            } else if self.supports("time_settings") {
+                let (main, period, stones) =
+                    match *time {
+                        TimeSystem::None                              => (0, 1, 0),
+                        TimeSystem::Absolute { main }                 => (main, 0, 0),
+                        TimeSystem::ByoYomi { main, period, .. }      => (main, period, 1),
+                        TimeSystem::Canadian { main, period, stones } => (main, period, stones),
+                    };
+                Command::cmd("time_settings", |e| e.i(main).i(period).i(stones).list())
+            } else {
+                return Err(Error::Unsupported("time settings"));
+            };
+        self.request(cmd)?;
+        Ok(())
+    }
+
+    /// Returns the analysis command of the engine, if any.
+    pub fn analyzer(&mut self) -> Option<Analyzer> {
+        if self.supports("kata-analyze")    { Some(Analyzer::Kata) }
+        else if self.supports("lz-analyze") { Some(Analyzer::Lz) }
+        else                                { None }
+    }
+
+    /// Analyzes the position for `color` for the given time, with
+    /// updates every `interval` centiseconds. Returns the candidate
+    /// moves of the last update.
+    pub fn analyze(&mut self, color: Color, interval: u32, duration: Duration)
+        -> Result<Vec<AnalysisInfo>, Error>
+    {
+        let analyzer = self.analyzer().ok_or(Error::Unsupported("analysis"))?;
+        let cmd  = Command::cmd(analyzer.command(), |e| e.color(color == Color::W).i(interval).list());
+        let text = cmd.to_string().trim().to_string();
+
+        self.engine.send(cmd);
+        let mut out   = String::new();
+        let mut infos = vec![];
+        let mut stop  = None;
+        let start     = Instant::now();
+
+        loop {
+            out += &self.engine.recv_raw().map_err(Error::Engine)?;
+
+            while let Some(nl) = out.find('\n') {
+                let line : String = out.drain(..=nl).collect();
+                let line = line.trim();
+
+                if let Some(rest) = line.strip_prefix('?') {
+                    let msg = rest.trim_start_matches(|c: char| c.is_ascii_digit()).trim();
+                    return Err(Error::Rejected(text, msg.to_string()));
+                }
+                if let Some(id) = stop {
+                    if line.starts_with(&format!("={}", id)) { return Ok(infos); }
+                }
+
+                let update = parse_analysis(line, analyzer);
+                if !update.is_empty() { infos = update; }
+            }
+
+            // Any command ends the analysis.
+            if stop.is_none() && start.elapsed() >= duration {
+                stop = Some(self.engine.send(Command::new("protocol_version")));
+            }
+            if start.elapsed() > duration + self.timeout {
+                return Err(Error::Engine(controller::Error::PollAgain));
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Returns the policy heatmap of Leela's `heatmap` command, which
+    /// prints it to stderr.
+    pub fn heatmap(&mut self) -> Result<String, Error> {
+        if !self.supports("heatmap") { return Err(Error::Unsupported("heatmap")); }
+
+        self.engine.clear_stderr();
+        let resp = self.request(Command::new("heatmap"))?;
+        if !resp.trim().is_empty() { return Ok(resp); }
+
+        // The stderr output may arrive a little after the response.
+        self.settle_stderr()?;
+        Ok(self.engine.stderr())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    #[test]
+    fn check_translation() {
+        let kata = MockEngine::new(Script::new()
+            .expect("name").respond("KataGo")
+            .expect("version").respond("1.0")
+            .expect("protocol_version").respond("2")
+            .expect("list_commands").respond("genmove\ntime_settings\nkata-analyze\nlz-analyze")
+            .expect("genmove w").respond("Q16")
+            .expect("time_settings 600 30 1").respond("")
+            .expect("kata-analyze b 10")
+                .raw("=\ninfo move D4 visits 10 winrate 0.55 prior 0.2 scoreLead 1.5 order 0 pv D4 Q16\n")
+            .expect("protocol_version")
+                .raw("info move D4 visits 20 winrate 0.6 order 0 pv D4 Q16 ownership 0.1 0.2\n\n")
+                .respond("2"));
+        let mut t = Translator::new(kata.engine(), Duration::from_secs(1));
+        assert_eq!(t.genmove(Color::W, true).unwrap(), "Q16");
+        t.time_settings(&TimeSystem::ByoYomi { main: 600, period: 30, periods: 5 }).unwrap();
+        let infos = t.analyze(Color::B, 10, Duration::from_millis(20)).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!((infos[0].visits, infos[0].winrate), (20, 0.6));
+        assert_eq!(infos[0].pv, vec!["D4", "Q16"]);
+        assert!(matches!(t.heatmap(), Err(Error::Unsupported(_))));
+        kata.assert_done();
+
+        let kgs = MockEngine::new(Script::new()
+            .expect("name").respond("bot")
+            .expect("version").respond("1.0")
+            .expect("protocol_version").respond("2")
+            .expect("list_commands").fail("unknown command")
+            .expect("genmove b").respond("pass")
+            .expect("name").respond("bot")
+            .expect("version").respond("1.0")
+            .expect("protocol_version").respond("2")
+            .expect("list_commands").respond("kgs-genmove_cleanup\nkgs-time_settings\nheatmap")
+            .expect("kgs-genmove_cleanup b").respond("C3")
+            .expect("kgs-time_settings byoyomi 600 30 5").fail("bad time")
+            .expect("heatmap").stderr("0 0 1\n").respond(""));
+        let mut t = Translator::new(kgs.engine(), Duration::from_secs(1));
+        assert_eq!(t.genmove(Color::B, true).unwrap(), "pass");
+        assert!(t.analyzer().is_none());
+        t.engine().handshake(Duration::from_secs(1)).unwrap();
+        assert_eq!(t.genmove(Color::B, true).unwrap(), "C3");
+        let err = t.time_settings(&TimeSystem::ByoYomi { main: 600, period: 30, periods: 5 });
+        assert!(matches!(err, Err(Error::Rejected(_, _))));
+        assert_eq!(t.heatmap().unwrap(), "0 0 1\n");
+        kgs.assert_done();
+    }
+
+    #[test]
+    fn check_stderr_parsing() {
+        let lz = MockEngine::new(Script::new()
+            .expect("name").respond("Leela Zero")
+            .expect("version").respond("0.17")
+            .expect("protocol_version").respond("2")
+            .expect("list_commands").respond("genmove\nlz-analyze")
+            .expect("genmove b").stderr("late line\n").respond("C3")
+            .expect("genmove w")
+                .stderr("Thinking at most 1.0 seconds...\nNN eval=0.484823\n")
+                .stderr(" Q16 ->      40 (V: 48.50%) (LCB: 45.10%) (N: 30.00%) PV: Q16 D4\n")
+                .respond("Q16")
+                .stderr("  D4 ->       2 (V: 40.00%) (N: 10.00%) PV: D4\n")
+                .stderr("43 visits, 41 nodes, 42 playouts, 100 n/s\n"));
+        let mut t = Translator::new(lz.engine(), Duration::from_secs(1));
+        assert_eq!(t.stderr_format(), Some(StderrFormat::LeelaZero));
+        t.genmove(Color::B, false).unwrap();
+
+        let mv = t.genmove_analyzed(Color::W, false).unwrap();
+        assert_eq!(mv.mv, "Q16");
+        let search = mv.search.unwrap();
+        assert_eq!(search.candidates.len(), 2);
+        assert_eq!(search.candidates[0], AnalysisInfo {
+            mv:         String::from("Q16"),
+            visits:     40,
+            winrate:    0.485,
+            prior:      Some(0.3),
+            score_lead: None,
+            pv:         vec![String::from("Q16"), String::from("D4")],
+        });
+        assert_eq!((search.visits, search.playouts), (Some(43), Some(42)));
+        assert!(search.warnings.is_empty());
+        lz.assert_done();
+
+        let gnugo = MockEngine::new(Script::new()
+            .expect("genmove b").stderr("warning: no moves\n").respond("PASS"));
+        let mut t = Translator::new(gnugo.engine(), Duration::from_secs(1));
+        t.set_stderr_format(Some(StderrFormat::GnuGo));
+        let mv = t.genmove_analyzed(Color::B, false).unwrap();
+        assert_eq!(mv.search.unwrap().warnings, vec!["warning: no moves"]);
+        gnugo.assert_done();
+    }
+}
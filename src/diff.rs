@@ -0,0 +1,396 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Differential testing: runs the same commands on two engines, for instance
+two versions of the same engine, and reports where they answer differently.
+
+The commands come from a GTP script in the format of a
+[regression test file](../regression/index.html), whose annotations are
+ignored, or are generated from SGF files by
+[`positions()`](fn.positions.html), which sets up positions of the games
+and sends probe commands like `genmove` in each of them.
+
+The responses are compared as typed [`Value`](enum.Value.html)s, so that
+`d4` and `D4`, or `B+3` and `B+3.0` count as the same. Commands that took
+much longer on one engine are reported too.
+
+```no_run
+use std::time::Duration;
+use gtp::controller::Engine;
+use gtp::diff::{compare, DiffConfig};
+use gtp::regression::Suite;
+
+let mut old = Engine::new("gnugo-3.8", &["--mode", "gtp"]);
+let mut new = Engine::new("gnugo-3.9", &["--mode", "gtp"]);
+old.start().unwrap();
+new.start().unwrap();
+
+let suite  = Suite::load("positions.tst").unwrap();
+let report = compare([&mut old, &mut new], &suite, &DiffConfig::new()).unwrap();
+print!("{}", report);
+```
+
+The `gtp-diff` binary provides this on the command line.
+*/
+
+use std::time::Duration;
+use std::time::Instant;
+
+use super::arbiter::GameResult;
+use super::controller;
+use super::controller::Engine;
+use super::regression::Suite;
+use super::regression::Entry;
+use super::sgf::GameRecord;
+use super::Command;
+use super::Entity;
+use super::EntityParser;
+use super::Response;
+
+/// A response, normalized for comparing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A list of vertices, sorted, in upper case. `pass` and `resign`
+    /// are kept as words.
+    Vertices(Vec<String>),
+    /// A score, like the response to `final_score`.
+    Score(GameResult),
+    Number(f64),
+    /// Any other text, with the whitespace of each line collapsed.
+    Text(String),
+    /// An error response. Two errors are the same, whatever the message.
+    Error,
+    /// No response within `DiffConfig::timeout`.
+    Timeout,
+}
+
+fn vertex(word: &str) -> Option<String> {
+    if word.eq_ignore_ascii_case("resign") { return Some(String::from("resign")); }
+    let mut ep = EntityParser::new(word);
+    match ep.vertex().result().as_ref().map(|v| &v[..]) {
+        Some([Entity::Vertex(v)]) => Some(Entity::Vertex(*v).to_string()),
+        _                         => None,
+    }
+}
+
+impl Value {
+    /// Normalizes a response.
+    ///
+    /// ```
+    /// use gtp::Response;
+    /// use gtp::diff::Value;
+    /// let v = |t: &str| Value::parse(&Response::Result((None, t.to_string())));
+    /// assert_eq!(v("d4 q16"), v("Q16  D4"));
+    /// assert_eq!(v("W+3"), v("w+3.0"));
+    /// assert_eq!(v("7.5"), Value::Number(7.5));
+    /// assert_eq!(v(" GNU  Go "), Value::Text(String::from("GNU Go")));
+    /// ```
+    pub fn parse(resp: &Response) -> Value {
+        let text = match resp {
+            Response::Error(_)       => return Value::Error,
+            Response::Result((_, t)) => t.trim(),
+        };
+
+        if let Some(result) = GameResult::parse(text) {
+            return Value::Score(result);
+        }
+        if let Ok(n) = text.parse::<f64>() {
+            return Value::Number(n);
+        }
+
+        let vertices : Option<Vec<String>> = text.split_whitespace().map(vertex).collect();
+        match vertices {
+            Some(mut v) if !v.is_empty() => { v.sort(); Value::Vertices(v) },
+            _ => Value::Text(
+                    text.lines()
+                        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+                        .collect::<Vec<_>>()
+                        .join("\n")),
+        }
+    }
+
+    /// Normalizes a response, or returns `Value::Timeout` for `None`.
+    pub fn of(resp: &Option<Response>) -> Value {
+        match resp {
+            Some(resp) => Value::parse(resp),
+            None       => Value::Timeout,
+        }
+    }
+}
+
+/// The responses of both engines to a command.
+#[derive(Debug, Clone)]
+pub struct Compared {
+    /// The line number in the script, counted from 1.
+    pub line:       usize,
+    pub command:    String,
+    /// The responses, `None` if the engine didn't respond in time.
+    pub responses:  [Option<Response>; 2],
+    pub elapsed:    [Duration; 2],
+}
+
+impl Compared {
+    /// Returns true if the responses differ.
+    pub fn differs(&self) -> bool {
+        Value::of(&self.responses[0]) != Value::of(&self.responses[1])
+    }
+
+    /// Returns true if one engine took more than `factor` times as long
+    /// as the other, ignoring commands that took less than `min` on both.
+    pub fn slower(&self, factor: f64, min: Duration) -> bool {
+        let (a, b) = (self.elapsed[0], self.elapsed[1]);
+        if a < min && b < min { return false; }
+        let (a, b) = (a.as_secs_f64(), b.as_secs_f64());
+        a > b * factor || b > a * factor
+    }
+}
+
+fn status(resp: &Option<Response>) -> String {
+    match resp {
+        Some(Response::Result((_, t))) => format!("= {}", t.trim()),
+        Some(Response::Error((_, t)))  => format!("? {}", t.trim()),
+        None                           => String::from("timeout"),
+    }
+}
+
+/// Parameters for [`compare()`](fn.compare.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffConfig {
+    /// Maximum time to wait for each response.
+    pub timeout:        Duration,
+    /// Commands taking this many times longer on one of the engines
+    /// are reported.
+    pub timing_factor:  f64,
+    /// Commands faster than this on both engines aren't compared by time.
+    pub timing_min:     Duration,
+}
+
+impl DiffConfig {
+    /// Returns a timeout of 60 seconds, and reports commands taking
+    /// twice as long, if they took at least 100ms.
+    pub fn new() -> DiffConfig {
+        DiffConfig {
+            timeout:        Duration::from_secs(60),
+            timing_factor:  2.0,
+            timing_min:     Duration::from_millis(100),
+        }
+    }
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self { DiffConfig::new() }
+}
+
+/// The results of [`compare()`](fn.compare.html).
+#[derive(Debug, Clone)]
+pub struct DiffReport {
+    pub name:           String,
+    pub compared:       Vec<Compared>,
+    pub timing_factor:  f64,
+    pub timing_min:     Duration,
+}
+
+impl DiffReport {
+    /// Returns the commands with different responses.
+    pub fn differences(&self) -> impl Iterator<Item = &Compared> {
+        self.compared.iter().filter(|c| c.differs())
+    }
+
+    /// Returns the commands where the time differs by more than
+    /// `DiffConfig::timing_factor`.
+    pub fn timing_differences(&self) -> impl Iterator<Item = &Compared> {
+        let (factor, min) = (self.timing_factor, self.timing_min);
+        self.compared.iter().filter(move |c| c.slower(factor, min))
+    }
+
+    /// Returns the total time of each engine.
+    pub fn total(&self) -> [Duration; 2] {
+        let mut total = [Duration::from_secs(0); 2];
+        for c in self.compared.iter() {
+            total[0] += c.elapsed[0];
+            total[1] += c.elapsed[1];
+        }
+        total
+    }
+
+    /// Returns true if there are no differences in the responses.
+    pub fn is_same(&self) -> bool { self.differences().next().is_none() }
+}
+
+impl std::fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for c in self.differences() {
+            writeln!(f, "{}:{} {}", self.name, c.line, c.command)?;
+            writeln!(f, "  a: {:<30} ({:.2}s)", status(&c.responses[0]), c.elapsed[0].as_secs_f64())?;
+            writeln!(f, "  b: {:<30} ({:.2}s)", status(&c.responses[1]), c.elapsed[1].as_secs_f64())?;
+        }
+        for c in self.timing_differences() {
+            writeln!(f, "{}:{} {} took {:.2}s vs {:.2}s",
+                     self.name, c.line, c.command,
+                     c.elapsed[0].as_secs_f64(), c.elapsed[1].as_secs_f64())?;
+        }
+
+        let total = self.total();
+        writeln!(f, "{}: {} commands, {} different, {} with different timing (a {:.2}s, b {:.2}s)",
+                 self.name, self.compared.len(),
+                 self.differences().count(), self.timing_differences().count(),
+                 total[0].as_secs_f64(), total[1].as_secs_f64())
+    }
+}
+
+fn command(line: &str) -> Command {
+    let mut parts = line.splitn(2, ' ');
+    let mut cmd   = Command::new(parts.next().unwrap_or(""));
+    if let Some(args) = parts.next() {
+        cmd.args(|e| e.s(args));
+    }
+    cmd
+}
+
+/// Runs the commands of `suite` on both engines, one engine after the
+/// other for each command, so they don't compete for the CPU.
+///
+/// A command timing out is recorded without response, and the comparison
+/// goes on. Returns an error with the index of the engine if it could not
+/// be communicated with.
+pub fn compare(engines: [&mut Engine; 2], suite: &Suite, cfg: &DiffConfig)
+    -> Result<DiffReport, (usize, controller::Error)>
+{
+    fn send(engine: &mut Engine, line: &str, timeout: Duration)
+        -> Result<(Option<Response>, Duration), controller::Error>
+    {
+        let t0 = Instant::now();
+        match engine.send_wait(command(line), timeout) {
+            Ok(resp)                              => Ok((Some(resp), t0.elapsed())),
+            Err(controller::Error::Timeout { .. }) => Ok((None, t0.elapsed())),
+            Err(e)                                => Err(e),
+        }
+    }
+
+    let mut report = DiffReport {
+        name:           suite.name.clone(),
+        compared:       vec![],
+        timing_factor:  cfg.timing_factor,
+        timing_min:     cfg.timing_min,
+    };
+    let [a, b] = engines;
+
+    for entry in suite.entries.iter() {
+        let (ra, ta) = send(a, &entry.command, cfg.timeout).map_err(|e| (0, e))?;
+        let (rb, tb) = send(b, &entry.command, cfg.timeout).map_err(|e| (1, e))?;
+
+        report.compared.push(Compared {
+            line:       entry.line,
+            command:    entry.command.clone(),
+            responses:  [ra, rb],
+            elapsed:    [ta, tb],
+        });
+    }
+    Ok(report)
+}
+
+/// Generates a script for each `every`th position of a game, including
+/// the final one. Each script sets up the board and sends the `probes`,
+/// where `{color}` is replaced by the color to move. The scripts are
+/// named `<name>@<move number>`.
+///
+/// ```
+/// let rec = gtp::sgf::GameRecord::parse("(;SZ[9]KM[6.5];B[ee];W[cc];B[gg])").unwrap();
+/// let suites = gtp::diff::positions("g.sgf", &rec, 2, &["genmove {color}"]);
+/// assert_eq!(suites.len(), 3);
+/// assert_eq!(suites[1].name, "g.sgf@2");
+/// let cmds : Vec<&str> = suites[1].entries.iter().map(|e| &e.command[..]).collect();
+/// assert_eq!(cmds, vec!["boardsize 9", "clear_board", "komi 6.5",
+///                       "play b E5", "play w C7", "genmove b"]);
+/// ```
+pub fn positions(name: &str, rec: &GameRecord, every: usize, probes: &[&str]) -> Vec<Suite> {
+    let every = every.max(1);
+    let mut numbers : Vec<usize> = (0..=rec.moves.len()).step_by(every).collect();
+    if numbers.last() != Some(&rec.moves.len()) { numbers.push(rec.moves.len()); }
+
+    numbers.into_iter().map(|n| {
+        let mut cmds = vec![
+            format!("boardsize {}", rec.size),
+            String::from("clear_board"),
+            format!("komi {}", rec.komi),
+        ];
+        for (color, v) in rec.moves[..n].iter() {
+            cmds.push(format!("play {} {}", color_word(*color), Entity::Vertex(*v)));
+        }
+
+        let to_move =
+            match rec.moves[..n].last() {
+                Some((c, _)) => c.opponent(),
+                None         => super::Color::B,
+            };
+        for p in probes.iter() {
+            cmds.push(p.replace("{color}", color_word(to_move)));
+        }
+
+        Suite {
+            name:    format!("{}@{}", name, n),
+            entries: cmds.into_iter().enumerate().map(|(i, command)| {
+                Entry { line: i + 1, id: None, command, expect: None }
+            }).collect(),
+        }
+    }).collect()
+}
+
+fn color_word(c: super::Color) -> &'static str {
+    match c {
+        super::Color::B => "b",
+        super::Color::W => "w",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    #[test]
+    fn check_compare() {
+        let a = MockEngine::new(Script::new()
+            .expect("boardsize 9").respond("")
+            .expect("genmove b").respond("d4")
+            .expect("final_score").respond("B+3")
+            .expect("estimate_score").delay(Duration::from_millis(150)).respond("B+1")
+            .expect("showboard").respond("\n . .\n . X")
+            .expect("genmove w").delay(Duration::from_millis(400)).respond("C3")
+            .expect("undo").respond(""));
+        let b = MockEngine::new(Script::new()
+            .expect("boardsize 9").respond("")
+            .expect("genmove b").respond("D4")
+            .expect("final_score").respond("W+0.5")
+            .expect("estimate_score").respond("B+1.0")
+            .expect("showboard").fail("unknown command")
+            .expect("genmove w").respond("C3")
+            .expect("undo").respond(""));
+
+        let suite = Suite::parse("t.tst",
+            "boardsize 9\n1 genmove b\n#? [D4]\nfinal_score\nestimate_score\nshowboard\n\
+             genmove w\nundo\n").unwrap();
+        let (mut ea, mut eb) = (a.engine(), b.engine());
+        let mut cfg = DiffConfig::new();
+        cfg.timeout = Duration::from_millis(250);
+        let report = compare([&mut ea, &mut eb], &suite, &cfg).unwrap();
+        a.assert_done();
+        b.assert_done();
+
+        let diffs : Vec<usize> = report.differences().map(|c| c.line).collect();
+        assert_eq!(diffs, vec![4, 6, 7]);
+        assert_eq!(Value::of(&report.compared[5].responses[0]), Value::Timeout);
+        // The late move isn't taken as the response to `undo`,
+        // which waits for the engine to finish it.
+        assert!(!report.compared[6].differs());
+        let slow : Vec<&str> = report.timing_differences().map(|c| &c.command[..]).collect();
+        assert_eq!(slow, vec!["estimate_score", "genmove w", "undo"]);
+        assert!(!report.is_same());
+
+        let text = report.to_string();
+        assert!(text.contains("t.tst:4 final_score\n  a: = B+3"), "{}", text);
+        assert!(text.contains("t.tst:7 genmove w\n  a: timeout"), "{}", text);
+        assert!(text.contains("t.tst: 7 commands, 3 different, 3 with different timing"), "{}", text);
+    }
+}
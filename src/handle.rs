@@ -0,0 +1,350 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+An engine running on a background thread, reporting events over channels.
+
+Instead of polling an [`Engine`](../controller/struct.Engine.html), spawn
+it into an [`EngineHandle`](struct.EngineHandle.html). The thread polls the
+engine and sends each [`EngineEvent`](enum.EngineEvent.html) to all
+subscribers: the responses, the lines on stderr, the updates of
+`kata-analyze` and `lz-analyze`, and finally the exit of the engine.
+
+Commands are sent with a [`CommandSender`](struct.CommandSender.html),
+which can be cloned and handed to the parts of an application that talk
+to the engine, like the board view and the analysis panel of a GUI. Each
+part can [`subscribe()`](struct.CommandSender.html#method.subscribe) to
+the events.
+
+```no_run
+use std::time::Duration;
+use gtp::Command;
+use gtp::controller::Engine;
+use gtp::handle::{EngineEvent, EngineHandle};
+
+let mut engine = Engine::new("katago", &["gtp"]);
+engine.start().unwrap();
+
+let handle = EngineHandle::spawn(engine);
+let sender = handle.sender();
+std::thread::spawn(move || {
+    sender.send(Command::cmd("kata-analyze", |e| e.b().i(50).list())).unwrap();
+});
+
+for event in handle.events().iter() {
+    match event {
+        EngineEvent::AnalysisUpdate(infos) => println!("best: {}", infos[0].mv),
+        EngineEvent::Exited(status)        => { println!("exited: {:?}", status); break; },
+        _                                  => (),
+    }
+}
+```
+*/
+
+use std::process::ExitStatus;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::controller::Engine;
+use super::controller::Error;
+use super::controller::StderrLine;
+use super::controller::StderrSink;
+use super::dialect::{parse_analysis, AnalysisInfo, Analyzer};
+use super::Command;
+use super::Response;
+
+const POLL_MS : u64 = 5;
+
+/// What happened with the engine of an
+/// [`EngineHandle`](struct.EngineHandle.html).
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// A response to a command.
+    Response(Response),
+    /// A line the engine wrote to stderr.
+    StderrLine(StderrLine),
+    /// The candidate moves of an update of `kata-analyze` or `lz-analyze`.
+    AnalysisUpdate(Vec<AnalysisInfo>),
+    /// The command was [`cancel()`](struct.CommandSender.html#method.cancel)ed,
+    /// and the engine is done with it.
+    Cancelled { id: u32, command: String },
+    /// The engine exited, with the exit status if it is known.
+    /// This is the last event.
+    Exited(Option<ExitStatus>),
+}
+
+enum Request {
+    Send(Command, mpsc::Sender<u32>),
+    SendStr(String),
+    Cancel(u32),
+    Stop(Duration),
+}
+
+/// A running `kata-analyze` or `lz-analyze`.
+struct Analysis {
+    analyzer:   Analyzer,
+    id:         u32,
+    command:    String,
+    cancelled:  bool,
+}
+
+type Subscribers = Arc<Mutex<Vec<mpsc::Sender<EngineEvent>>>>;
+
+fn broadcast(subscribers: &Subscribers, event: EngineEvent) {
+    if let Ok(mut subs) = subscribers.lock() {
+        subs.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
+
+/// Sends commands to the engine of an
+/// [`EngineHandle`](struct.EngineHandle.html). Clone it for each part
+/// of the application that talks to the engine.
+#[derive(Clone)]
+pub struct CommandSender {
+    tx:             mpsc::Sender<Request>,
+    subscribers:    Subscribers,
+}
+
+impl CommandSender {
+    /// Sends a command and returns its ID, which the
+    /// [`EngineEvent::Response`](enum.EngineEvent.html#variant.Response)
+    /// carries. Returns `Error::NoHandle` if the engine is gone.
+    pub fn send(&self, cmd: Command) -> Result<u32, Error> {
+        let (tx, rx) = mpsc::channel();
+        self.tx.send(Request::Send(cmd, tx)).map_err(|_| Error::NoHandle)?;
+        match rx.recv() {
+            Ok(0) | Err(_) => Err(Error::NoHandle),
+            Ok(id)         => Ok(id),
+        }
+    }
+
+    /// Sends a raw string, see
+    /// [`Engine::send_str()`](../controller/struct.Engine.html#method.send_str).
+    pub fn send_str(&self, s: &str) -> Result<(), Error> {
+        self.tx.send(Request::SendStr(s.to_string())).map_err(|_| Error::NoHandle)
+    }
+
+    /// Cancels the command with the ID, see
+    /// [`Engine::cancel()`](../controller/struct.Engine.html#method.cancel).
+    /// The subscribers get `EngineEvent::Cancelled` instead of the response.
+    pub fn cancel(&self, id: u32) -> Result<(), Error> {
+        self.tx.send(Request::Cancel(id)).map_err(|_| Error::NoHandle)
+    }
+
+    /// Stops the engine like
+    /// [`Engine::stop()`](../controller/struct.Engine.html#method.stop),
+    /// without waiting for it. The subscribers get `EngineEvent::Exited`.
+    pub fn stop(&self, grace: Duration) -> Result<(), Error> {
+        self.tx.send(Request::Stop(grace)).map_err(|_| Error::NoHandle)
+    }
+
+    /// Returns a new receiver for all events from now on.
+    pub fn subscribe(&self) -> mpsc::Receiver<EngineEvent> {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut subs) = self.subscribers.lock() { subs.push(tx); }
+        rx
+    }
+}
+
+/// An engine running on a background thread. Dropping the handle stops
+/// the engine.
+pub struct EngineHandle {
+    sender:     CommandSender,
+    events:     mpsc::Receiver<EngineEvent>,
+    thread:     Option<JoinHandle<Option<ExitStatus>>>,
+}
+
+impl EngineHandle {
+    /// Moves the started engine to a background thread. The stderr sink
+    /// of the engine is replaced, to send the lines as events.
+    pub fn spawn(mut engine: Engine) -> EngineHandle {
+        let subscribers : Subscribers = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = mpsc::channel();
+        let sender   = CommandSender { tx, subscribers: subscribers.clone() };
+        let events   = sender.subscribe();
+
+        let subs = subscribers.clone();
+        engine.set_stderr_sink(Some(StderrSink::Callback(Box::new(move |line| {
+            broadcast(&subs, EngineEvent::StderrLine(line.clone()));
+        }))));
+
+        let thread = std::thread::spawn(move || {
+            let status = run(&mut engine, &rx, &subscribers);
+            broadcast(&subscribers, EngineEvent::Exited(status));
+            status
+        });
+
+        EngineHandle { sender, events, thread: Some(thread) }
+    }
+
+    /// Returns a sender for commands.
+    pub fn sender(&self) -> CommandSender { self.sender.clone() }
+
+    /// Returns the events, as subscribed when the handle was spawned.
+    pub fn events(&self) -> &mpsc::Receiver<EngineEvent> { &self.events }
+
+    /// Stops the engine and waits for the thread. Returns the exit
+    /// status of the engine, `None` if it is not known.
+    pub fn stop(mut self, grace: Duration) -> Option<ExitStatus> {
+        let _ = self.sender.stop(grace);
+        self.thread.take()?.join().ok().flatten()
+    }
+}
+
+impl Drop for EngineHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = self.sender.stop(Duration::from_millis(500));
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Returns the analyzer if the command starts a streaming analysis.
+fn analyzer(cmd: &Command) -> Option<Analyzer> {
+    let text = cmd.to_string();
+    match text.split_whitespace().next() {
+        Some("kata-analyze") => Some(Analyzer::Kata),
+        Some("lz-analyze")   => Some(Analyzer::Lz),
+        _                    => None,
+    }
+}
+
+/// Polls the engine and serves the requests, until the engine exits or
+/// is stopped. Returns the exit status.
+fn run(engine: &mut Engine, rx: &mpsc::Receiver<Request>, subscribers: &Subscribers)
+    -> Option<ExitStatus>
+{
+    let mut analyzing : Option<Analysis> = None;
+    let mut out = String::new();
+
+    loop {
+        match rx.recv_timeout(Duration::from_millis(POLL_MS)) {
+            Ok(Request::Send(cmd, reply)) => {
+                let stream  = analyzer(&cmd);
+                let command = cmd.to_string().trim().to_string();
+                let id      = engine.send(cmd);
+                if let (Some(analyzer), true) = (stream, id != 0) {
+                    analyzing = Some(Analysis { analyzer, id, command, cancelled: false });
+                }
+                let _ = reply.send(id);
+            },
+            Ok(Request::SendStr(s))  => engine.send_str(&s),
+            Ok(Request::Cancel(id))  => {
+                if let Some(a) = analyzing.as_mut() {
+                    if a.id == id { a.cancelled = true; }
+                }
+                let _ = engine.cancel(id);
+            },
+            Ok(Request::Stop(grace)) => return engine.stop(grace).ok().flatten(),
+            Err(mpsc::RecvTimeoutError::Timeout)      => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) =>
+                return engine.stop(Duration::from_millis(500)).ok().flatten(),
+        }
+
+        if let Some(analysis) = analyzing.as_ref() {
+            let analyzer = analysis.analyzer;
+            match engine.recv_raw() {
+                Ok(o)  => out += &o,
+                Err(_) => return engine.stop(Duration::from_millis(0)).ok().flatten(),
+            }
+
+            while let Some(nl) = out.find('\n') {
+                let line = out[..nl].trim().to_string();
+
+                // An error response or the end of the analysis: the
+                // rest are plain responses again.
+                if line.starts_with('?') || line.is_empty() {
+                    if line.is_empty() { out.drain(..=nl); }
+                    engine.feed_raw(&out);
+                    out.clear();
+                    if let Some(Analysis { id, command, cancelled: true, .. }) = analyzing.take() {
+                        broadcast(subscribers, EngineEvent::Cancelled { id, command });
+                    }
+                    break;
+                }
+                out.drain(..=nl);
+
+                let infos = parse_analysis(&line, analyzer);
+                if !infos.is_empty() {
+                    broadcast(subscribers, EngineEvent::AnalysisUpdate(infos));
+                }
+            }
+            if analyzing.is_some() { continue; }
+        }
+
+        loop {
+            match engine.poll_response() {
+                Ok(resp)              => broadcast(subscribers, EngineEvent::Response(resp)),
+                Err(Error::PollAgain) => break,
+                Err(Error::Cancelled { id, command }) =>
+                    broadcast(subscribers, EngineEvent::Cancelled { id, command }),
+                Err(_)                => return engine.stop(Duration::from_millis(0)).ok().flatten(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    fn next(rx: &mpsc::Receiver<EngineEvent>) -> EngineEvent {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn check_events() {
+        let mock = MockEngine::new(Script::new()
+            .expect("genmove b").stderr("thinking\n").respond("D4")
+            .expect("lz-analyze b 10")
+                .raw("=\ninfo move D4 visits 10 winrate 5500 prior 1000 order 0 pv D4 Q16\n")
+            .expect("name")
+                .raw("info move Q16 visits 20 winrate 4000 order 0 pv Q16\n\n")
+                .respond("mock")
+            .expect("genmove w").delay(Duration::from_millis(50)).respond("Q16")
+            .expect("undo").crash());
+        let handle = EngineHandle::spawn(mock.engine());
+        let sender = handle.sender();
+        let other  = sender.subscribe();
+
+        let id = sender.clone().send(Command::cmd("genmove", |e| e.b())).unwrap();
+        assert_eq!(id, 1);
+        let mut events = [next(handle.events()), next(handle.events())];
+        events.sort_by_key(|e| matches!(e, EngineEvent::Response(_)));
+        assert_eq!(events[0], EngineEvent::StderrLine(StderrLine {
+            id:      Some(1),
+            command: Some(String::from("genmove b")),
+            text:    String::from("thinking"),
+        }));
+        assert_eq!(events[1], EngineEvent::Response(Response::Result((Some(1), String::from("D4")))));
+        assert_eq!(next(&other), events[0]);
+
+        sender.send(Command::cmd("lz-analyze", |e| e.b().i(10).list())).unwrap();
+        match next(handle.events()) {
+            EngineEvent::AnalysisUpdate(infos) => assert_eq!(infos[0].pv, vec!["D4", "Q16"]),
+            e => panic!("unexpected {:?}", e),
+        }
+        sender.send(Command::new("name")).unwrap();
+        match next(handle.events()) {
+            EngineEvent::AnalysisUpdate(infos) => assert_eq!(infos[0].mv, "Q16"),
+            e => panic!("unexpected {:?}", e),
+        }
+        assert_eq!(next(handle.events()),
+                   EngineEvent::Response(Response::Result((Some(3), String::from("mock")))));
+
+        let id = sender.send(Command::cmd("genmove", |e| e.w())).unwrap();
+        sender.cancel(id).unwrap();
+        assert_eq!(next(handle.events()),
+                   EngineEvent::Cancelled { id: 4, command: String::from("genmove w") });
+
+        sender.send(Command::new("undo")).unwrap();
+        assert_eq!(next(handle.events()), EngineEvent::Exited(None));
+        assert!(handle.stop(Duration::from_millis(10)).is_none());
+        assert!(matches!(sender.send(Command::new("name")), Err(Error::NoHandle)));
+        mock.assert_done();
+    }
+}
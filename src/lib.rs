@@ -23,7 +23,7 @@ A short overview of the API of this crate.
 This is the basic usage on how to communicate with a GTP
 engine like `GNU Go`, `Leela Zero` or `KataGo`:
 
-```
+```no_run
 use std::time::Duration;
 use gtp::Command;
 use gtp::controller::Engine;
@@ -107,8 +107,8 @@ this matter with other developers.
 First about _why I write code for free_ at all:
 
 - It's my passion to write computer programs. In my free time I can
-write the code I want, when I want and the way I want. I can freely
-allocate my time and freely choose the projects I want to work on.
+  write the code I want, when I want and the way I want. I can freely
+  allocate my time and freely choose the projects I want to work on.
 - To help a friend or member of my family.
 - To solve a problem I have.
 
@@ -164,9 +164,27 @@ without any additional terms or conditions.
 
 */
 
+pub mod arbiter;
+pub mod board;
+pub mod conformance;
 pub mod controller;
+pub mod dialect;
+pub mod diff;
+pub mod handle;
+pub mod mock;
+pub mod opening;
+pub mod proxy;
+pub mod regression;
+pub mod sgf;
+pub mod sprt;
+pub mod supervisor;
+pub mod tournament;
+pub mod transcript;
 pub mod detached_command;
 
+#[cfg(test)]
+mod test_util;
+
 /// The color of a move
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Color {
@@ -174,6 +192,16 @@ pub enum Color {
     B,
 }
 
+impl Color {
+    /// Returns the other color.
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::W => Color::B,
+            Color::B => Color::W,
+        }
+    }
+}
+
 /// Helper class for constructing an Entity data structure.
 ///
 /// Use it like this:
@@ -343,11 +371,7 @@ fn gen_move_char(i: u32) -> char {
     } else {
         ('A' as u32) + i
     };
-    if let Some(c) = std::char::from_u32(c) {
-        c
-    } else {
-        'Z'
-    }
+    std::char::from_u32(c).unwrap_or('Z')
 }
 
 impl std::fmt::Display for Entity {
@@ -359,7 +383,7 @@ impl std::fmt::Display for Entity {
             Entity::Vertex((h, v)) => {
                 let mut s = String::from("");
                 if *h <= 0 || *v <= 0 {
-                    s += &"pass".to_string();
+                    s += "pass";
                 } else {
                     s += &format!("{}", gen_move_char(*h as u32));
                     s += &format!("{}", v);
@@ -371,7 +395,7 @@ impl std::fmt::Display for Entity {
             Entity::Move((Color::W, (h, v))) => {
                 let mut s = String::from("");
                 if *h <= 0 || *v <= 0 {
-                    s += &"w pass".to_string();
+                    s += "w pass";
                 } else {
                     s += &format!("w {}", gen_move_char(*h as u32));
                     s += &format!("{}", v);
@@ -381,7 +405,7 @@ impl std::fmt::Display for Entity {
             Entity::Move((Color::B, (h, v))) => {
                 let mut s = String::from("");
                 if *h <= 0 || *v <= 0 {
-                    s += &"b pass".to_string();
+                    s += "b pass";
                 } else {
                     s += &format!("b {}", gen_move_char(*h as u32));
                     s += &format!("{}", v);
@@ -413,141 +437,427 @@ impl std::fmt::Display for Entity {
     }
 }
 
+/// How closely the parsers follow the GTP specification.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strictness {
+    /// Accept common deviations from the specification silently.
+    /// This is the default, as many engines deviate in small ways.
+    #[default]
+    Lenient,
+    /// Parse like in lenient mode, but report every violation of the
+    /// specification as a [`Diagnostic`](struct.Diagnostic.html).
+    /// Useful for testing engines for conformance.
+    Strict,
+}
+
+/// A violation of the GTP specification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// A carriage return, lines must end with a single newline.
+    CarriageReturn,
+    /// A horizontal tab, which should have been converted to a space.
+    HorizontalTab,
+    /// A control character other than newline.
+    ControlCharacter(char),
+    /// A `#` comment in a response.
+    Comment,
+    /// Whitespace or empty lines in front of a response.
+    LeadingWhitespace,
+    /// The response does not start with `=` or `?`.
+    BadStatus(char),
+    /// The status character or id is not followed by a space.
+    MissingSpace,
+    /// A vertex with the letter `I`, which is not used in GTP coordinates.
+    IllegalVertexLetter(char),
+    /// A vertex outside of the maximum board size of 25x25.
+    VertexOutOfRange(String),
+    /// An int with a sign or larger than 2^31 - 1.
+    BadInt(String),
+    /// A float that is not written as a decimal number, like `inf`.
+    BadFloat(String),
+    /// A boolean not written as `true` or `false`.
+    BadBoolean(String),
+    /// Bytes on stdout or stderr that aren't valid UTF-8. The position
+    /// of the diagnostic is the byte offset in the stream.
+    InvalidEncoding { stderr: bool, bytes: Vec<u8> },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Violation::CarriageReturn         => write!(f, "carriage return"),
+            Violation::HorizontalTab          => write!(f, "horizontal tab"),
+            Violation::ControlCharacter(c)    => write!(f, "control character {:?}", c),
+            Violation::Comment                => write!(f, "comment in response"),
+            Violation::LeadingWhitespace      => write!(f, "whitespace before response"),
+            Violation::BadStatus(c)           => write!(f, "bad status character {:?}", c),
+            Violation::MissingSpace           => write!(f, "missing space after status or id"),
+            Violation::IllegalVertexLetter(c) => write!(f, "illegal vertex letter '{}'", c),
+            Violation::VertexOutOfRange(s)    => write!(f, "vertex '{}' out of range", s),
+            Violation::BadInt(s)              => write!(f, "bad int '{}'", s),
+            Violation::BadFloat(s)            => write!(f, "bad float '{}'", s),
+            Violation::BadBoolean(s)          => write!(f, "bad boolean '{}'", s),
+            Violation::InvalidEncoding { stderr, bytes } =>
+                write!(f, "invalid UTF-8 {:02x?} on {}", bytes, if *stderr { "stderr" } else { "stdout" }),
+        }
+    }
+}
+
+/// A specification violation found by a parser in
+/// [`Strictness::Strict`](enum.Strictness.html) mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Character offset into the input fed to the parser. For
+    /// `Violation::InvalidEncoding` it's the byte offset into the
+    /// stdout or stderr stream of the engine instead, as the bytes
+    /// never reach the parser.
+    pub position:   usize,
+    pub violation:  Violation,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.violation {
+            Violation::InvalidEncoding { .. } =>
+                write!(f, "{} at byte {}", self.violation, self.position),
+            _ => write!(f, "{} at position {}", self.violation, self.position),
+        }
+    }
+}
+
+/// The kind of entity an [`EntityParser`](struct.EntityParser.html) method
+/// tried to read. Used for reporting parse errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityKind {
+    Int,
+    Float,
+    String,
+    Vertex,
+    Color,
+    Move,
+    Boolean,
+}
+
+impl std::fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EntityKind::Int     => write!(f, "int"),
+            EntityKind::Float   => write!(f, "float"),
+            EntityKind::String  => write!(f, "string"),
+            EntityKind::Vertex  => write!(f, "vertex"),
+            EntityKind::Color   => write!(f, "color"),
+            EntityKind::Move    => write!(f, "move"),
+            EntityKind::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
+/// Describes the token an [`EntityParser`](struct.EntityParser.html)
+/// could not parse.
+///
+/// ```
+/// let mut ep = gtp::EntityParser::new("b resign");
+/// ep.color().vertex();
+/// let err = ep.error().unwrap();
+/// assert_eq!(err.token_index, 1);
+/// assert_eq!(err.column, 3);
+/// assert_eq!(err.text, "resign");
+/// assert_eq!(err.to_string(),
+///            "expected vertex, got 'resign' at token 1 (line 1, column 3)");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityParseError {
+    /// Index of the offending token, counted from 0.
+    pub token_index: usize,
+    /// Line of the offending token, counted from 1.
+    pub line:        usize,
+    /// Column of the offending token in characters, counted from 1.
+    pub column:      usize,
+    /// The offending token. Empty if the input ended prematurely.
+    pub text:        String,
+    /// The kind of entity that was expected.
+    pub expected:    EntityKind,
+}
+
+impl std::fmt::Display for EntityParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.text.is_empty() {
+            write!(f, "expected {}, got end of input", self.expected)?;
+        } else {
+            write!(f, "expected {}, got '{}'", self.expected, self.text)?;
+        }
+        write!(f, " at token {} (line {}, column {})",
+               self.token_index, self.line, self.column)
+    }
+}
+
+impl std::error::Error for EntityParseError { }
+
+/// A single whitespace separated token of the entity parser input
+/// together with its position.
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text:   String,
+    index:  usize,
+    offset: usize,
+    line:   usize,
+    column: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct EntityParser {
     buffer:         String,
     entities:       Vec<Entity>,
-    parse_error:    bool,
+    error:          Option<EntityParseError>,
+    strictness:     Strictness,
+    diagnostics:    Vec<Diagnostic>,
+    token_index:    usize,
+    offset:         usize,
+    line:           usize,
+    column:         usize,
 }
 
 impl std::iter::Iterator for EntityParser {
     type Item = String;
 
     fn next(&mut self) -> Option<String> {
-        self.buffer = self.buffer.chars().skip_while(|c| *c == ' ' || *c == '\n').collect();
-
-        let mut s = String::from("");
-        let mut skip_count = 0;
-        for c in self.buffer.chars() {
-            skip_count += 1;
-            if c == ' ' || c == '\n' { break; }
-            s.push(c);
-        }
+        let tok = self.next_token();
+        if tok.text.is_empty() { None } else { Some(tok.text) }
+    }
 
-        self.buffer = self.buffer.chars().skip(skip_count).collect();
+}
+
+fn parse_color(s: &str) -> Option<Color> {
+    let s = s.to_lowercase();
+    if s == "w" || s == "white" { return Some(Color::W); }
+    if s == "b" || s == "black" { return Some(Color::B); }
+    None
+}
 
-        if s.is_empty() { None } else { Some(s) }
+/// Checks a token, that was already parsed successfully,
+/// for specification violations.
+fn check_entity(kind: EntityKind, s: &str) -> Option<Violation> {
+    match kind {
+        EntityKind::Int
+            if s.starts_with('+') || s.parse::<i32>().is_err() =>
+            Some(Violation::BadInt(s.to_string())),
+        EntityKind::Float
+            if !s.chars().any(|c| c.is_ascii_digit())
+               || s.chars().any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E') =>
+            Some(Violation::BadFloat(s.to_string())),
+        EntityKind::Vertex => {
+            let h = s.chars().next().unwrap_or(' ').to_ascii_uppercase();
+            if h == 'I' {
+                return Some(Violation::IllegalVertexLetter(h));
+            }
+            match parse_vertex(s) {
+                Some((h, v)) if (h, v) != (0, 0) && (h > 25 || !(1..=25).contains(&v)) =>
+                    Some(Violation::VertexOutOfRange(s.to_string())),
+                _ => None,
+            }
+        },
+        EntityKind::Boolean if s != "true" && s != "false" =>
+            Some(Violation::BadBoolean(s.to_string())),
+        _ => None,
     }
+}
 
+fn parse_vertex(s: &str) -> Option<(i32, i32)> {
+    let s = s.to_uppercase();
+    if s == "PASS" { return Some((0, 0)); }
+    if s.len() < 2 || s.len() > 3 { return None; }
+
+    let h = s.chars().next().unwrap();
+    if !h.is_ascii_alphabetic() { return None; }
+    let mut h = (h as u32 - ('A' as u32)) + 1;
+    if h > 8 { h -= 1; }
+
+    let v = s[1..].parse::<i32>().ok()?;
+    Some((h as i32, v))
 }
 
 impl EntityParser {
     pub fn new(s: &str) -> Self {
+        Self::with_strictness(s, Strictness::Lenient)
+    }
+
+    /// Creates an entity parser that reports specification violations,
+    /// like a vertex with the letter `I`, if `strictness` is
+    /// `Strictness::Strict`. Colors and vertices are case insensitive
+    /// in GTP and are not reported.
+    ///
+    /// ```
+    /// use gtp::{EntityParser, Strictness, Violation};
+    /// let mut ep = EntityParser::with_strictness("b I5", Strictness::Strict);
+    /// ep.mv();
+    /// assert_eq!(ep.diagnostics()[0].violation, Violation::IllegalVertexLetter('I'));
+    /// assert_eq!(ep.diagnostics()[0].position, 2);
+    /// ```
+    pub fn with_strictness(s: &str, strictness: Strictness) -> Self {
         EntityParser {
-            buffer:     String::from(s),
-            entities:   Vec::new(),
-            parse_error: false,
+            buffer:         String::from(s),
+            entities:       Vec::new(),
+            error:          None,
+            strictness,
+            diagnostics:    Vec::new(),
+            token_index:    0,
+            offset:         0,
+            line:           1,
+            column:         1,
         }
     }
 
     pub fn result(&self) -> Option<Vec<Entity>> {
-        if self.parse_error { return None; }
+        if self.error.is_some() { return None; }
         Some(self.entities.clone())
     }
 
     pub fn is_eof(&self) -> bool { self.buffer.is_empty() }
-    pub fn had_parse_error(&self) -> bool { self.parse_error }
+    pub fn had_parse_error(&self) -> bool { self.error.is_some() }
+
+    /// Returns the first parse error that occurred, if any.
+    pub fn error(&self) -> Option<&EntityParseError> { self.error.as_ref() }
+
+    /// Returns the specification violations found so far.
+    /// Always empty in lenient mode.
+    pub fn diagnostics(&self) -> &[Diagnostic] { &self.diagnostics }
+
+    /// Removes the next token from the input buffer and
+    /// keeps track of its position.
+    fn next_token(&mut self) -> Token {
+        let mut start = self.buffer.len();
+        for (i, c) in self.buffer.char_indices() {
+            match c {
+                '\n' => { self.line += 1; self.column = 1; },
+                ' '  => { self.column += 1; },
+                _    => { start = i; break; },
+            }
+            self.offset += 1;
+        }
+
+        let rest = &self.buffer[start..];
+        let len  = rest.find([' ', '\n']).unwrap_or(rest.len());
+        let tok  = Token {
+            text:   rest[..len].to_string(),
+            index:  self.token_index,
+            offset: self.offset,
+            line:   self.line,
+            column: self.column,
+        };
+        let tok_len  = tok.text.chars().count();
+        self.column += tok_len;
+        self.offset += tok_len;
+
+        // Also consume the separator following the token:
+        let mut end = start + len;
+        match self.buffer[end..].chars().next() {
+            Some('\n') => { self.line += 1; self.column = 1; end += 1; self.offset += 1; },
+            Some(_)    => { self.column += 1; end += 1; self.offset += 1; },
+            None       => (),
+        }
+        self.buffer = self.buffer[end..].to_string();
+
+        if !tok.text.is_empty() { self.token_index += 1; }
+        tok
+    }
+
+    fn fail(&mut self, expected: EntityKind, tok: Token) {
+        if self.error.is_some() { return; }
+        self.error = Some(EntityParseError {
+            token_index: tok.index,
+            line:        tok.line,
+            column:      tok.column,
+            text:        tok.text,
+            expected,
+        });
+    }
+
+    fn check(&mut self, kind: EntityKind, tok: &Token) {
+        if self.strictness != Strictness::Strict { return; }
+        if let Some(violation) = check_entity(kind, &tok.text) {
+            self.diagnostics.push(Diagnostic { position: tok.offset, violation });
+        }
+    }
 
     pub fn s(&mut self) -> &mut Self {
-        let s = self.next().unwrap_or_else(|| String::from(""));
-        if s.is_empty() { self.parse_error = true; return self; }
-        self.entities.push(Entity::String(s));
+        let tok = self.next_token();
+        if tok.text.is_empty() {
+            self.fail(EntityKind::String, tok);
+        } else {
+            self.entities.push(Entity::String(tok.text));
+        }
         self
     }
 
     pub fn i(&mut self) -> &mut Self {
-        let s = self.next().unwrap_or_else(|| String::from(""));
-        if let Ok(i) = s.parse::<u32>() {
+        let tok = self.next_token();
+        if let Ok(i) = tok.text.parse::<u32>() {
+            self.check(EntityKind::Int, &tok);
             self.entities.push(Entity::Int(i));
         } else {
-            self.parse_error = true;
+            self.fail(EntityKind::Int, tok);
         }
         self
     }
 
     pub fn f(&mut self) -> &mut Self {
-        let s = self.next().unwrap_or_else(|| String::from(""));
-        if let Ok(f) = s.parse::<f32>() {
+        let tok = self.next_token();
+        if let Ok(f) = tok.text.parse::<f32>() {
+            self.check(EntityKind::Float, &tok);
             self.entities.push(Entity::Float(f));
         } else {
-            self.parse_error = true;
+            self.fail(EntityKind::Float, tok);
         }
         self
     }
 
     pub fn color(&mut self) -> &mut Self {
-        let s = self.next().unwrap_or_else(|| String::from(""));
-        let s = s.to_lowercase();
-        if s == "w" || s == "white" { self.entities.push(Entity::Color(Color::W)); return self; }
-        if s == "b" || s == "black" { self.entities.push(Entity::Color(Color::B)); return self; }
-        self.parse_error = true;
+        let tok = self.next_token();
+        if let Some(c) = parse_color(&tok.text) {
+            self.entities.push(Entity::Color(c));
+        } else {
+            self.fail(EntityKind::Color, tok);
+        }
         self
     }
 
     pub fn vertex(&mut self) -> &mut Self {
-        let s = self.next().unwrap_or_else(|| String::from(""));
-        let s = s.to_uppercase();
-        if s == "PASS" { self.entities.push(Entity::Vertex((0, 0))); return self; }
-        if s.len() < 2 || s.len() > 3 {
-            self.parse_error = true;
-            return self;
-        }
-
-        let h = s.chars().nth(0).unwrap();
-        if !h.is_ascii_alphabetic() {
-            self.parse_error = true;
-            return self;
-        }
-        let h = h as u32;
-        let mut h = (h - ('A' as u32)) + 1;
-        if h > 8 { h -= 1; }
-
-        let v : String = s.chars().skip(1).collect();
-        if let Ok(v) = i32::from_str_radix(&v, 10) {
-            self.entities.push(Entity::Vertex((h as i32, v)));
+        let tok = self.next_token();
+        if let Some(v) = parse_vertex(&tok.text) {
+            self.check(EntityKind::Vertex, &tok);
+            self.entities.push(Entity::Vertex(v));
         } else {
-            self.parse_error = true;
+            self.fail(EntityKind::Vertex, tok);
         }
-
         self
     }
 
     pub fn mv(&mut self) -> &mut Self {
-        self.color();
-        if self.parse_error { return self; }
-        self.vertex();
-        if self.parse_error { self.entities.pop(); }
-
-        let m = self.entities.pop().unwrap();
-        let c = self.entities.pop().unwrap();
-
-        if let Entity::Vertex((h, v)) = m {
-            if let Entity::Color(c) = c {
-                self.entities.push(Entity::Move((c, (h, v))));
-                return self;
-            }
-        }
+        let tok = self.next_token();
+        let c = match parse_color(&tok.text) {
+            Some(c) => c,
+            None    => { self.fail(EntityKind::Move, tok); return self; },
+        };
 
-        self.parse_error = true;
+        let tok = self.next_token();
+        if let Some(v) = parse_vertex(&tok.text) {
+            self.check(EntityKind::Vertex, &tok);
+            self.entities.push(Entity::Move((c, v)));
+        } else {
+            self.fail(EntityKind::Move, tok);
+        }
         self
     }
 
     pub fn bool(&mut self) -> &mut Self {
-        let s = self.next().unwrap_or_else(|| String::from(""));
-        let s = s.to_uppercase();
-        if s == "TRUE" { self.entities.push(Entity::Boolean(true)); return self; }
-        if s == "FALSE" { self.entities.push(Entity::Boolean(false)); return self; }
-        self.parse_error = true;
+        let tok = self.next_token();
+        let b = match &tok.text.to_uppercase()[..] {
+            "TRUE"  => true,
+            "FALSE" => false,
+            _       => { self.fail(EntityKind::Boolean, tok); return self; },
+        };
+        self.check(EntityKind::Boolean, &tok);
+        self.entities.push(Entity::Boolean(b));
         self
     }
 }
@@ -647,23 +957,6 @@ impl Command {
         self.args = Some(args.clone());
     }
 
-    /// Generates a String representation of the GTP command.
-    pub fn to_string(&self) -> String {
-        let mut out = String::from("");
-        if self.id.is_some() {
-            out += &format!("{}", self.id.unwrap());
-            out += " ";
-        }
-        out += &self.name;
-
-        if self.args.is_some() {
-            out += " ";
-            out += &self.args.as_ref().unwrap().to_string();
-        }
-        out += "\n";
-        out
-    }
-
     /// Generates a byte vector representation of the GTP command,
     /// ready to be sent to another process.
     #[allow(dead_code)]
@@ -672,6 +965,21 @@ impl Command {
     }
 }
 
+/// Generates the String representation of the GTP command.
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(id) = self.id {
+            write!(f, "{} ", id)?;
+        }
+        write!(f, "{}", self.name)?;
+
+        if let Some(args) = &self.args {
+            write!(f, " {}", args)?;
+        }
+        writeln!(f)
+    }
+}
+
 /// Represents a GTP response from the GTP engine.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Response {
@@ -682,7 +990,8 @@ pub enum Response {
 #[derive(Debug)]
 pub enum ResponseParseError {
     NoInput,
-    BadEntityInput,
+    /// The response text did not match the requested entities.
+    BadEntityInput(EntityParseError),
     BadResponse
 }
 
@@ -747,50 +1056,118 @@ impl Response {
 
         let mut ep = EntityParser::new(&response);
         parse_fn(&mut ep);
-        if ep.had_parse_error() {
-            return Err(ResponseParseError::BadEntityInput);
+        if let Some(err) = ep.error() {
+            return Err(ResponseParseError::BadEntityInput(err.clone()));
         }
         Ok(ep.result().unwrap())
     }
 }
 
 /// A parser for a GTP response.
+///
+/// The parser is lenient by default and accepts some common deviations
+/// from the GTP specification. Construct it with
+/// [`Strictness::Strict`](enum.Strictness.html) to get a
+/// [`Diagnostic`](struct.Diagnostic.html) for every violation:
+///
+/// ```
+/// let mut rp = gtp::ResponseParser::with_strictness(gtp::Strictness::Strict);
+/// rp.feed("=1\r\n\r\n");
+/// assert_eq!(rp.get_response().unwrap().id_0(), 1);
+/// assert_eq!(rp.diagnostics()[0].violation, gtp::Violation::CarriageReturn);
+/// assert_eq!(rp.diagnostics()[0].position, 2);
+/// assert_eq!(rp.diagnostics()[1].violation, gtp::Violation::MissingSpace);
+/// ```
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct ResponseParser {
-    buffer:     String,
+    buffer:         String,
+    strictness:     Strictness,
+    diagnostics:    Vec<Diagnostic>,
+    consumed:       usize,
 }
 
 /// Error for the ResponseParser.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ResponseError {
     IncompleteResponse,
-    BadResponse(String),
+    /// The response framing is broken. `position` is the character
+    /// offset into `buffer` where the parser stopped.
+    BadResponse { buffer: String, position: usize },
 }
 
-fn refine_input(s: String) -> String {
-    let mut ret : String =
-        s.chars()
-         .filter(|c| *c != '\r')
-         .map(|c| if c == '\x09' { ' ' } else { c })
-         .skip_while(|c| *c == '\n' || *c == ' ' || *c == '\x09')
-         .collect();
-
-    loop {
-        let comment_pos = ret.find('#');
-        if comment_pos.is_some() {
-            let end_comment_pos = (&ret[comment_pos.unwrap()..]).find('\n');
-            if end_comment_pos.is_some() {
-                ret = String::from(&ret[..comment_pos.unwrap()])
-                      + &ret[comment_pos.unwrap() + end_comment_pos.unwrap() + 1..];
-            } else {
-                break;
+impl std::fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResponseError::IncompleteResponse => write!(f, "incomplete response"),
+            ResponseError::BadResponse { buffer, position } => {
+                match buffer.chars().nth(*position) {
+                    Some(c) =>
+                        write!(f, "bad response at position {}: unexpected {:?}",
+                               position, c),
+                    None =>
+                        write!(f, "bad response at position {}", position),
+                }
+            },
+        }
+    }
+}
+
+/// Preprocessed response input. `pos` maps each character of `text`
+/// back to its character offset in the raw input.
+struct RefinedInput {
+    text:       Vec<char>,
+    pos:        Vec<usize>,
+    violations: Vec<(usize, Violation)>,
+}
+
+fn refine_input(s: &str) -> RefinedInput {
+    let raw : Vec<char> = s.chars().collect();
+    let mut ri = RefinedInput {
+        text:       Vec::new(),
+        pos:        Vec::new(),
+        violations: Vec::new(),
+    };
+
+    let mut leading_ws = false;
+    let mut i = 0;
+    while i < raw.len() {
+        let c = raw[i];
+        match c {
+            '\r' => {
+                ri.violations.push((i, Violation::CarriageReturn));
+                i += 1;
+                continue;
+            },
+            '#' => {
+                if let Some(len) = raw[i..].iter().position(|c| *c == '\n') {
+                    ri.violations.push((i, Violation::Comment));
+                    i += len + 1;
+                    continue;
+                }
+            },
+            '\x09' => {
+                ri.violations.push((i, Violation::HorizontalTab));
+            },
+            c if c.is_control() && c != '\n' => {
+                ri.violations.push((i, Violation::ControlCharacter(c)));
+            },
+            _ => (),
+        }
+
+        let c = if c == '\x09' { ' ' } else { c };
+        if ri.text.is_empty() && (c == '\n' || c == ' ') {
+            if !leading_ws {
+                ri.violations.push((i, Violation::LeadingWhitespace));
+                leading_ws = true;
             }
         } else {
-            break;
+            ri.text.push(c);
+            ri.pos.push(i);
         }
+        i += 1;
     }
 
-    ret
+    ri
 }
 
 impl ResponseParser {
@@ -806,88 +1183,146 @@ impl ResponseParser {
         ResponseParser::default()
     }
 
+    /// Constructs a new GTP engine response parser with the
+    /// given strictness.
+    pub fn with_strictness(strictness: Strictness) -> ResponseParser {
+        ResponseParser { strictness, ..ResponseParser::default() }
+    }
+
+    /// Changes the strictness of the parser.
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
     /// Feed the response text to the parser.
     pub fn feed(&mut self, s: &str) {
         self.buffer += s;
     }
 
+    /// Returns the specification violations found in the responses
+    /// returned so far. Always empty in lenient mode.
+    pub fn diagnostics(&self) -> &[Diagnostic] { &self.diagnostics }
+
+    /// Returns and clears the collected diagnostics.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Returns the error for the malformed input at `position` and
+    /// discards the input up to the end of its line, so the next
+    /// response can be read. Until the line is complete, it's
+    /// `IncompleteResponse`.
+    fn bad_response(&mut self, position: usize) -> ResponseError {
+        let chars : Vec<char> = self.buffer.chars().collect();
+        // Wait for the rest of the line, so it's reported only once.
+        let mut end =
+            match chars[position..].iter().position(|c| *c == '\n') {
+                Some(p) => position + p,
+                None    => return ResponseError::IncompleteResponse,
+            };
+        let err = ResponseError::BadResponse { buffer: self.buffer.to_string(), position };
+        while end < chars.len() && (chars[end] == '\n' || chars[end] == '\r') {
+            end += 1;
+        }
+        self.consume(end);
+        err
+    }
+
+    /// Removes the first `end` characters from the buffer.
+    fn consume(&mut self, end: usize) {
+        let byte_end =
+            self.buffer.char_indices().nth(end)
+                .map(|(i, _)| i)
+                .unwrap_or_else(|| self.buffer.len());
+        self.buffer    = self.buffer[byte_end..].to_string();
+        self.consumed += end;
+    }
+
+    fn report(&mut self, position: usize, violation: Violation) {
+        if self.strictness == Strictness::Strict {
+            self.diagnostics.push(Diagnostic {
+                position: self.consumed + position,
+                violation,
+            });
+        }
+    }
+
     /// Tries to read the response from the until now feeded input.
     ///
-    /// Returns `Ok(None)` if no response is available yet.
-    /// Returns an error if the response is malformed.
+    /// Returns `Err(ResponseError::IncompleteResponse)` if no response
+    /// is available yet.
+    /// Returns an error if the response is malformed, the rest of
+    /// the malformed line is skipped then.
     /// Returns the Ok([`Response`](enum.Response.html)) if one could be read.
-    #[allow(unused_assignments, clippy::collapsible_if)]
     pub fn get_response(&mut self) -> Result<Response, ResponseError> {
-        self.buffer = refine_input(self.buffer.to_string());
-        if self.buffer.is_empty() { return Err(ResponseError::IncompleteResponse); }
+        let ri = refine_input(&self.buffer);
+        if ri.text.is_empty() { return Err(ResponseError::IncompleteResponse); }
 
-        let is_error = self.buffer.chars().nth(0).unwrap() != '=';
+        let status   = ri.text[0];
+        let is_error = status != '=';
 
         let mut id_str   = String::from("");
         let mut response = String::from("");
-
-        let mut read_id =
-            !(   self.buffer.len() > 1
-              && self.buffer.chars().nth(1).unwrap() == ' ');
-
-        let mut found_start      = false;
-        let mut found_end        = false;
+        let mut found_id_end     = false;
+        let mut missing_space    = None;
         let mut last_was_newline = false;
-        let mut skip_count       = 1;
-
-        for c in self.buffer.chars().skip(1) {
-            skip_count += 1;
+        let mut end              = None;
 
-            if read_id {
+        for (i, c) in ri.text.iter().enumerate().skip(1) {
+            if !found_id_end {
                 match c {
                     c if c.is_ascii_digit() => {
-                        id_str.push(c);
+                        id_str.push(*c);
                     },
                     ' ' => {
-                        found_start = true;
-                        read_id     = false;
+                        found_id_end = true;
                     },
-                    _ => { return Err(ResponseError::BadResponse(self.buffer.to_string())); }
+                    // Some engines omit the space if the response
+                    // text is empty or starts on the next line:
+                    '\n' => {
+                        found_id_end     = true;
+                        last_was_newline = true;
+                        missing_space    = Some(ri.pos[i]);
+                    },
+                    _ => { return Err(self.bad_response(ri.pos[i])); }
                 }
-            } else if !found_start {
-                if c == ' ' {
-                    found_start = true;
+            } else if *c == '\n' {
+                if last_was_newline {
+                    end = Some(ri.pos[i] + 1);
+                    break;
                 } else {
-                    return Err(ResponseError::BadResponse(self.buffer.to_string()));
+                    last_was_newline = true;
                 }
             } else {
-                if c == '\n' {
-                    if last_was_newline {
-                        found_end = true;
-                        break;
-                    } else {
-                        last_was_newline = true;
-                    }
-                } else {
-                    if last_was_newline {
-                        response.push('\n');
-                    }
-                    last_was_newline = false;
-                    response.push(c);
+                if last_was_newline {
+                    response.push('\n');
                 }
+                last_was_newline = false;
+                response.push(*c);
             }
         }
 
-        if found_end {
-            self.buffer = self.buffer.chars().skip(skip_count).collect();
-        } else {
-            return Err(ResponseError::IncompleteResponse);
+        let end = match end {
+            Some(end) => end,
+            None      => return Err(ResponseError::IncompleteResponse),
+        };
+
+        if status != '=' && status != '?' {
+            self.report(ri.pos[0], Violation::BadStatus(status));
+        }
+        if let Some(pos) = missing_space {
+            self.report(pos, Violation::MissingSpace);
+        }
+        for (pos, violation) in ri.violations.into_iter() {
+            if pos < end { self.report(pos, violation); }
+        }
+        if self.strictness == Strictness::Strict {
+            self.diagnostics.sort_by_key(|d| d.position);
         }
 
-        let id = if !id_str.is_empty() {
-            if let Ok(cn) = u32::from_str_radix(&id_str, 10) {
-                Some(cn)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        self.consume(end);
+
+        let id = id_str.parse::<u32>().ok();
 
         if is_error {
             Ok(Response::Error((id, response)))
@@ -970,6 +1405,99 @@ mod tests {
         assert_eq!(res[3].to_string(), "D2");
     }
 
+    #[test]
+    fn check_entity_parse_error() {
+        let mut ep = EntityParser::new("10 w\n  resign");
+        ep.i().mv();
+        let err = ep.error().unwrap();
+        assert_eq!(err.token_index, 2);
+        assert_eq!(err.line,        2);
+        assert_eq!(err.column,      3);
+        assert_eq!(err.text,        "resign");
+        assert_eq!(err.expected,    EntityKind::Move);
+        assert!(ep.result().is_none());
+
+        let mut ep = EntityParser::new("a1");
+        ep.vertex().vertex();
+        assert_eq!(ep.error().unwrap().to_string(),
+                   "expected vertex, got end of input at token 1 (line 1, column 3)");
+
+        let mut rp = ResponseParser::new();
+        rp.feed("= 12\n\n");
+        match rp.get_response().unwrap().entities(|ep| ep.i().color()) {
+            Err(ResponseParseError::BadEntityInput(err)) => {
+                assert_eq!(err.expected, EntityKind::Color);
+                assert_eq!(err.token_index, 1);
+            },
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn check_strict_response_parser() {
+        let mut rp = ResponseParser::with_strictness(Strictness::Strict);
+        rp.feed("\n= ok\n\n!2 \tfoo\n\n=3\n\n");
+        assert_eq!(rp.get_response().unwrap().text(), "ok");
+        assert_eq!(rp.take_diagnostics(), vec![
+            Diagnostic { position: 0, violation: Violation::LeadingWhitespace },
+        ]);
+
+        let resp = rp.get_response().unwrap();
+        assert_eq!(resp, Response::Error((Some(2), String::from(" foo"))));
+        assert_eq!(rp.take_diagnostics(), vec![
+            Diagnostic { position: 7, violation: Violation::BadStatus('!') },
+            Diagnostic { position: 10, violation: Violation::HorizontalTab },
+        ]);
+
+        assert_eq!(rp.get_response().unwrap(), Response::Result((Some(3), String::from(""))));
+        assert_eq!(rp.take_diagnostics(), vec![
+            Diagnostic { position: 18, violation: Violation::MissingSpace },
+        ]);
+
+        let mut rp = ResponseParser::new();
+        rp.feed("\n=3\r\n\r\n");
+        assert_eq!(rp.get_response().unwrap(), Response::Result((Some(3), String::from(""))));
+        assert!(rp.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn check_strict_entity_parser() {
+        let mut ep = EntityParser::with_strictness(
+            "+3 inf i1 A26 Z1 TRUE pass", Strictness::Strict);
+        ep.i().f().vertex().vertex().vertex().bool().vertex();
+        assert!(ep.result().is_some());
+        let violations : Vec<Violation> =
+            ep.diagnostics().iter().map(|d| d.violation.clone()).collect();
+        assert_eq!(violations, vec![
+            Violation::BadInt(String::from("+3")),
+            Violation::BadFloat(String::from("inf")),
+            Violation::IllegalVertexLetter('I'),
+            Violation::VertexOutOfRange(String::from("A26")),
+            Violation::BadBoolean(String::from("TRUE")),
+        ]);
+        assert_eq!(ep.diagnostics()[3].position, 10);
+    }
+
+    #[test]
+    fn check_bad_response_position() {
+        let mut rp = ResponseParser::new();
+        rp.feed("=12x ok\n\n");
+        assert_eq!(rp.get_response(),
+                   Err(ResponseError::BadResponse {
+                       buffer:   String::from("=12x ok\n\n"),
+                       position: 3,
+                   }));
+        rp.feed("=13 ok\n\n");
+        assert_eq!(rp.get_response(), Ok(Response::Result((Some(13), String::from("ok")))));
+
+        // A malformed line split across two reads is reported once.
+        rp.feed("=14x o");
+        assert_eq!(rp.get_response(), Err(ResponseError::IncompleteResponse));
+        rp.feed("k\n\n=15 ok\n\n");
+        assert!(matches!(rp.get_response(), Err(ResponseError::BadResponse { position: 3, .. })));
+        assert_eq!(rp.get_response(), Ok(Response::Result((Some(15), String::from("ok")))));
+    }
+
     #[test]
     fn check_build_command() {
         let mut c = Command::new("list_commands");
@@ -991,8 +1519,7 @@ mod tests {
     fn must_parse(s: &str) -> Response {
         let mut rp = ResponseParser::new();
         rp.feed(s);
-        let s = rp.get_response().unwrap();
-        s
+        rp.get_response().unwrap()
     }
 
     #[test]
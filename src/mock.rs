@@ -0,0 +1,459 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+A scripted GTP engine for testing controller code without a real engine.
+
+A [`Script`](struct.Script.html) lists the commands the engine expects, in
+order, and what it does in reply. Besides regular responses it can delay,
+print to stderr, write malformed output or crash:
+
+```
+use std::time::Duration;
+use gtp::Command;
+use gtp::mock::{MockEngine, Script};
+
+let mock = MockEngine::new(
+    Script::new()
+        .expect("name").respond("Mock")
+        .expect("genmove b").delay(Duration::from_millis(10)).respond("D4"));
+let mut ctrl = mock.engine();
+
+let resp = ctrl.send_wait(Command::new("name"), Duration::from_secs(1)).unwrap();
+assert_eq!(resp.text(), "Mock");
+let resp = ctrl.send_wait(Command::cmd("genmove", |e| e.b()), Duration::from_secs(1)).unwrap();
+assert_eq!(resp.text(), "D4");
+
+mock.assert_done();
+```
+
+Commands that don't match the next expected command are recorded as
+unexpected and answered with an error response.
+[`MockEngine::assert_done`](struct.MockEngine.html#method.assert_done)
+panics if there were any, or if the script was not used up.
+
+The same scripts can be loaded from a text file with
+[`Script::parse`](struct.Script.html#method.parse) and run as a real
+engine process with the `gtp-mock` binary.
+*/
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::controller::Engine;
+use super::detached_command::Error;
+use super::detached_command::Transport;
+
+/// What the mock engine does in reply to a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Writes a successful response with the given text.
+    Respond(String),
+    /// Writes an error response with the given text.
+    Fail(String),
+    /// Writes the text to stdout as is.
+    Raw(String),
+    /// Writes the text to stderr.
+    Stderr(String),
+    /// Waits before performing the following actions.
+    Delay(Duration),
+    /// Terminates the engine.
+    Crash,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Exchange {
+    expect:     String,
+    actions:    Vec<Action>,
+}
+
+impl Exchange {
+    /// Checks whether the command line matches the expected command,
+    /// word by word. A trailing `*` word in the expectation matches
+    /// any remaining arguments.
+    fn matches(&self, cmd: &str) -> bool {
+        let expect : Vec<&str> = self.expect.split_whitespace().collect();
+        let words  : Vec<&str> = cmd.split_whitespace().collect();
+        match expect.split_last() {
+            Some((&"*", prefix)) =>
+                words.len() >= prefix.len() && words[..prefix.len()] == *prefix,
+            _ => words == expect,
+        }
+    }
+}
+
+/// The list of expected commands and the replies of a mock engine.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    exchanges:  VecDeque<Exchange>,
+}
+
+fn unescape(s: &str) -> String {
+    let mut out   = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' { out.push(c); continue; }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(c)   => out.push(c),
+            None      => out.push('\\'),
+        }
+    }
+    out
+}
+
+impl Script {
+    pub fn new() -> Script { Script::default() }
+
+    /// Adds a command the engine expects next. The command is given
+    /// without ID, like `"play b D4"`. A trailing `*` word matches any
+    /// arguments, like in `"play *"`, but `"play*"` only matches itself.
+    pub fn expect(mut self, cmd: &str) -> Self {
+        self.exchanges.push_back(Exchange {
+            expect:  cmd.trim().to_string(),
+            actions: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds an action to the last expected command.
+    ///
+    /// Panics if no command was expected yet.
+    pub fn action(mut self, action: Action) -> Self {
+        self.exchanges.back_mut()
+            .expect("Script::expect() must be called before adding actions")
+            .actions.push(action);
+        self
+    }
+
+    pub fn respond(self, text: &str) -> Self { self.action(Action::Respond(text.to_string())) }
+    pub fn fail(self, text: &str) -> Self { self.action(Action::Fail(text.to_string())) }
+    pub fn raw(self, text: &str) -> Self { self.action(Action::Raw(text.to_string())) }
+    pub fn stderr(self, text: &str) -> Self { self.action(Action::Stderr(text.to_string())) }
+    pub fn delay(self, d: Duration) -> Self { self.action(Action::Delay(d)) }
+    pub fn crash(self) -> Self { self.action(Action::Crash) }
+
+    /// Parses a script from its text representation:
+    ///
+    /// ```text
+    /// # Lines starting with '#' are comments.
+    /// > name              expected command, '*' at the end matches any arguments
+    /// = Mock              successful response
+    /// > list_commands
+    /// = name              multi line responses are continued with '|'
+    /// | list_commands
+    /// > genmove *
+    /// stderr thinking\n   text for stderr, \n \r \t and \\ are unescaped
+    /// delay 500           milliseconds
+    /// = D4
+    /// > play *
+    /// ? illegal move      error response
+    /// > showboard
+    /// raw garbage\n\n     raw text for stdout
+    /// > quit
+    /// crash
+    /// ```
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut script = Script::new();
+
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') { continue; }
+            if !line.starts_with('>') && script.exchanges.is_empty() {
+                return Err(format!("line {}: action before first '>'", i + 1));
+            }
+
+            let (word, rest) = match line.find(' ') {
+                Some(p) => (&line[..p], &line[p + 1..]),
+                None    => (line, ""),
+            };
+
+            script = match word {
+                ">"      => script.expect(rest),
+                "="      => script.respond(rest),
+                "?"      => script.fail(rest),
+                "|"      => {
+                    let last = script.exchanges.back_mut().unwrap().actions.last_mut();
+                    match last {
+                        Some(Action::Respond(t)) | Some(Action::Fail(t)) => {
+                            t.push('\n');
+                            t.push_str(rest);
+                        },
+                        _ => return Err(format!("line {}: '|' without response", i + 1)),
+                    }
+                    script
+                },
+                "raw"    => script.raw(&unescape(rest)),
+                "stderr" => script.stderr(&unescape(rest)),
+                "delay"  => {
+                    let ms = rest.trim().parse::<u64>()
+                        .map_err(|_| format!("line {}: bad delay '{}'", i + 1, rest))?;
+                    script.delay(Duration::from_millis(ms))
+                },
+                "crash"  => script.crash(),
+                _        => return Err(format!("line {}: unknown directive '{}'", i + 1, word)),
+            };
+        }
+
+        Ok(script)
+    }
+}
+
+/// Output of the mock engine, as produced by `MockState::handle()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    Stdout(String),
+    Stderr(String),
+    Delay(Duration),
+    Crash,
+}
+
+/// The state of a running script, shared between a `MockEngine`
+/// and its transports.
+#[derive(Debug, Clone, Default)]
+pub struct MockState {
+    script:     Script,
+    received:   Vec<String>,
+    unexpected: Vec<String>,
+}
+
+impl MockState {
+    pub fn new(script: Script) -> MockState {
+        MockState { script, ..MockState::default() }
+    }
+
+    /// Processes one line of input like a GTP engine, and returns
+    /// what to output in reply. Comment and empty lines get no reply.
+    pub fn handle(&mut self, line: &str) -> Vec<Output> {
+        let line : String =
+            line.chars()
+                .filter(|c| !c.is_control() || *c == '\t')
+                .map(|c| if c == '\t' { ' ' } else { c })
+                .collect();
+        let line = match line.find('#') {
+            Some(p) => &line[..p],
+            None    => &line[..],
+        };
+        let line = line.trim();
+        if line.is_empty() { return vec![]; }
+
+        let mut words = line.split_whitespace().peekable();
+        let id =
+            match words.peek().map(|w| w.parse::<u32>()) {
+                Some(Ok(id)) => { words.next(); id.to_string() },
+                _            => String::from(""),
+            };
+        let cmd : Vec<&str> = words.collect();
+        let cmd = cmd.join(" ");
+        self.received.push(cmd.clone());
+
+        let exchange =
+            match self.script.exchanges.front() {
+                Some(ex) if ex.matches(&cmd) => self.script.exchanges.pop_front().unwrap(),
+                _ => {
+                    self.unexpected.push(cmd);
+                    return vec![Output::Stdout(format!("?{} unexpected command\n\n", id))];
+                },
+            };
+
+        exchange.actions.into_iter().map(|a| {
+            match a {
+                Action::Respond(t) => Output::Stdout(format!("={} {}\n\n", id, t)),
+                Action::Fail(t)    => Output::Stdout(format!("?{} {}\n\n", id, t)),
+                Action::Raw(t)     => Output::Stdout(t),
+                Action::Stderr(t)  => Output::Stderr(t),
+                Action::Delay(d)   => Output::Delay(d),
+                Action::Crash      => Output::Crash,
+            }
+        }).collect()
+    }
+
+    /// Returns the received commands that did not match the script.
+    pub fn unexpected(&self) -> &[String] { &self.unexpected }
+
+    /// Returns true if all expected commands were received.
+    pub fn is_done(&self) -> bool { self.script.exchanges.is_empty() }
+}
+
+/// A scripted engine. Use [`engine()`](#method.engine) to connect a
+/// controller and the other methods for checking what it received.
+#[derive(Debug, Clone)]
+pub struct MockEngine {
+    state:  Arc<Mutex<MockState>>,
+}
+
+impl MockEngine {
+    pub fn new(script: Script) -> MockEngine {
+        MockEngine { state: Arc::new(Mutex::new(MockState::new(script))) }
+    }
+
+    /// Returns a controller connected to this mock engine.
+    pub fn engine(&self) -> Engine {
+        Engine::with_transport(Box::new(self.transport()))
+    }
+
+    /// Returns a transport connected to this mock engine.
+    pub fn transport(&self) -> MockTransport {
+        MockTransport {
+            state:      self.state.clone(),
+            queue:      VecDeque::new(),
+            busy_until: Instant::now(),
+            stdout:     Vec::new(),
+            stderr:     Vec::new(),
+            crashed:    false,
+        }
+    }
+
+    /// Returns all received commands, without IDs.
+    pub fn received(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Returns the received commands that did not match the script.
+    pub fn unexpected(&self) -> Vec<String> {
+        self.state.lock().unwrap().unexpected().to_vec()
+    }
+
+    /// Returns true if all expected commands were received.
+    pub fn is_done(&self) -> bool { self.state.lock().unwrap().is_done() }
+
+    /// Panics if unexpected commands were received or
+    /// if expected commands are left.
+    pub fn assert_done(&self) {
+        let st = self.state.lock().unwrap();
+        if !st.unexpected.is_empty() {
+            panic!("mock engine received unexpected commands: {:?}", st.unexpected);
+        }
+        if let Some(ex) = st.script.exchanges.front() {
+            panic!("mock engine still expects '{}' ({} commands left)",
+                   ex.expect, st.script.exchanges.len());
+        }
+    }
+}
+
+/// An in-memory [`Transport`](../detached_command/trait.Transport.html)
+/// to a `MockEngine`. Delays are simulated by holding back the output
+/// until it's due, the commands are processed one after another like
+/// a real engine would do.
+pub struct MockTransport {
+    state:      Arc<Mutex<MockState>>,
+    queue:      VecDeque<(Instant, Output)>,
+    busy_until: Instant,
+    stdout:     Vec<String>,
+    stderr:     Vec<String>,
+    crashed:    bool,
+}
+
+impl Transport for MockTransport {
+    fn send(&mut self, buffer: Vec<u8>) {
+        let input = String::from_utf8_lossy(&buffer).to_string();
+        let mut t = std::cmp::max(self.busy_until, Instant::now());
+
+        for line in input.lines() {
+            let out = self.state.lock().unwrap().handle(line);
+            for o in out.into_iter() {
+                match o {
+                    Output::Delay(d) => { t += d; },
+                    o                => self.queue.push_back((t, o)),
+                }
+            }
+        }
+
+        self.busy_until = t;
+    }
+
+    fn poll(&mut self) -> Result<(), Error> {
+        if self.crashed { return Err(Error::Disconnected); }
+
+        let now = Instant::now();
+        while let Some((due, _)) = self.queue.front() {
+            if *due > now { break; }
+
+            match self.queue.pop_front().unwrap().1 {
+                Output::Stdout(s) => self.stdout.push(s),
+                Output::Stderr(s) => self.stderr.push(s),
+                Output::Crash     => {
+                    self.crashed = true;
+                    self.queue.clear();
+                    return Err(Error::Disconnected);
+                },
+                Output::Delay(_)  => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stdout_available(&self) -> bool { !self.stdout.is_empty() }
+    fn stderr_available(&self) -> bool { !self.stderr.is_empty() }
+
+    fn recv_stdout(&mut self) -> String {
+        let ret = self.stdout.join("");
+        self.stdout.clear();
+        ret
+    }
+
+    fn recv_stderr(&mut self) -> String {
+        let ret = self.stderr.join("");
+        self.stderr.clear();
+        ret
+    }
+
+    fn shutdown(&mut self) {
+        self.crashed = true;
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_script_parse() {
+        let script = Script::parse("\
+            # comment\n\
+            > list_commands\n\
+            = name\n\
+            | quit\n\
+            > genmove *\n\
+            stderr thinking\\n\n\
+            delay 20\n\
+            ? no\n\
+            > quit\n\
+            raw =\\n\\n\n\
+            crash\n").unwrap();
+
+        assert_eq!(script,
+            Script::new()
+                .expect("list_commands").respond("name\nquit")
+                .expect("genmove *").stderr("thinking\n")
+                                    .delay(Duration::from_millis(20))
+                                    .fail("no")
+                .expect("quit").raw("=\n\n").crash());
+
+        assert!(Script::parse("= foo\n").is_err());
+        assert!(Script::parse("> foo\nbar\n").is_err());
+    }
+
+    #[test]
+    fn check_state_handle() {
+        let mut st = MockState::new(Script::new().expect("play *").respond(""));
+        assert_eq!(st.handle("# only a comment"), vec![]);
+        assert_eq!(st.handle("12\tplay  b D4 # comment"),
+                   vec![Output::Stdout(String::from("=12 \n\n"))]);
+        assert_eq!(st.handle("name"),
+                   vec![Output::Stdout(String::from("? unexpected command\n\n"))]);
+        assert_eq!(st.received, vec![String::from("play b D4"), String::from("name")]);
+        assert!(st.is_done());
+
+        let mut st = MockState::new(Script::new().expect("play *").respond(""));
+        assert_eq!(st.handle("playfoo b D4"),
+                   vec![Output::Stdout(String::from("? unexpected command\n\n"))]);
+        assert_eq!(st.handle("play"), vec![Output::Stdout(String::from("= \n\n"))]);
+    }
+}
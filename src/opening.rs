@@ -0,0 +1,221 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Opening suites, so that deterministic engines don't play the same game
+in every game of a match.
+
+Openings are read from a directory of SGF files, using the main line of
+each game, or from a text file with one opening per line. The moves of a
+line are vertices played alternately by black and white, starting with
+black. Empty lines and everything after a `#` are ignored:
+
+```text
+# Two star points
+D4 Q16
+# Komoku and a pass
+R16 D4 pass
+```
+
+[`random()`](fn.random.html) generates random openings instead.
+
+See [`MatchConfig::openings`](../arbiter/struct.MatchConfig.html) for
+how a match uses them.
+*/
+
+use std::path::Path;
+
+use super::board::Board;
+use super::sgf::GameRecord;
+use super::Color;
+use super::Entity;
+use super::EntityParser;
+
+/// A sequence of moves played before the engines take over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opening {
+    /// The file name, or the file name and line number.
+    pub name:   String,
+    pub moves:  Vec<(Color, (i32, i32))>,
+}
+
+impl Opening {
+    /// Parses a line of vertices, played alternately starting with black.
+    ///
+    /// ```
+    /// use gtp::Color;
+    /// let o = gtp::opening::Opening::parse_line("o", "D4 q16 PASS").unwrap();
+    /// assert_eq!(o.moves, vec![(Color::B, (4, 4)), (Color::W, (16, 16)), (Color::B, (0, 0))]);
+    /// ```
+    pub fn parse_line(name: &str, line: &str) -> Result<Opening, String> {
+        let mut opening = Opening { name: name.to_string(), moves: Vec::new() };
+        let mut color   = Color::B;
+
+        for word in line.split_whitespace() {
+            let mut ep = EntityParser::new(word);
+            match ep.vertex().result().as_ref().map(|v| &v[..]) {
+                Some([Entity::Vertex(v)]) => opening.moves.push((color, *v)),
+                _ => return Err(format!("{}: bad vertex '{}'", name, word)),
+            }
+            color = color.opponent();
+        }
+        Ok(opening)
+    }
+
+    /// Shortens the opening to at most `moves` moves.
+    pub fn truncate(&mut self, moves: usize) { self.moves.truncate(moves); }
+
+    /// Checks that the moves are legal on a board of the given size.
+    pub fn check(&self, size: usize) -> Result<(), String> {
+        let mut board = Board::new(size);
+        for (i, (color, vertex)) in self.moves.iter().enumerate() {
+            board.play(*color, *vertex).map_err(|e| {
+                format!("opening {}: move {} {}: {}",
+                        self.name, i + 1, Entity::Vertex(*vertex), e)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the openings of a text file.
+pub fn load_file(path: &Path) -> Result<Vec<Opening>, String> {
+    let text =
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read '{}': {}", path.display(), e))?;
+
+    let mut openings = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(p) => &line[..p],
+            None    => line,
+        };
+        if line.trim().is_empty() { continue; }
+        let name = format!("{}:{}", path.display(), i + 1);
+        openings.push(Opening::parse_line(&name, line)?);
+    }
+    Ok(openings)
+}
+
+/// Reads the main lines of the `*.sgf` files in a directory, sorted by
+/// file name. The games must be for boards of the given size.
+pub fn load_dir(path: &Path, size: usize) -> Result<Vec<Opening>, String> {
+    let dir =
+        std::fs::read_dir(path)
+            .map_err(|e| format!("Couldn't read '{}': {}", path.display(), e))?;
+
+    let mut files : Vec<_> =
+        dir.filter_map(|e| e.ok().map(|e| e.path()))
+           .filter(|p| p.extension().map(|e| e.eq_ignore_ascii_case("sgf")).unwrap_or(false))
+           .collect();
+    files.sort();
+
+    let mut openings = vec![];
+    for file in files {
+        let rec = GameRecord::load(&file)?;
+        if rec.size != size {
+            return Err(format!("{}: board size {}, expected {}",
+                               file.display(), rec.size, size));
+        }
+        let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        openings.push(Opening { name, moves: rec.moves });
+    }
+    Ok(openings)
+}
+
+/// Reads the openings of a directory of SGF files or of a text file.
+pub fn load(path: &Path, size: usize) -> Result<Vec<Opening>, String> {
+    if path.is_dir() { load_dir(path, size) } else { load_file(path) }
+}
+
+/// A xorshift64* pseudo random number generator, good enough for
+/// generating openings reproducibly from a seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state must not be zero.
+        Rng { state: seed ^ 0x9e37_79b9_7f4a_7c15 | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+}
+
+/// Generates a random opening of `moves` legal moves. The moves avoid
+/// the first two lines, so the positions stay reasonable. The opening is
+/// shorter if no legal move is found there anymore, which only happens
+/// for small boards and long openings.
+///
+/// ```
+/// use gtp::opening::{random, Rng};
+/// let o = random("r1", &mut Rng::new(42), 19, 6);
+/// assert_eq!(o.moves.len(), 6);
+/// assert!(o.check(19).is_ok());
+/// assert_eq!(o, random("r1", &mut Rng::new(42), 19, 6));
+/// ```
+pub fn random(name: &str, rng: &mut Rng, size: usize, moves: usize) -> Opening {
+    let mut board   = Board::new(size);
+    let mut opening = Opening { name: name.to_string(), moves: Vec::new() };
+    let mut color   = Color::B;
+
+    let (lo, hi) = if size >= 7 { (3, size as i32 - 2) } else { (1, size as i32) };
+    let span     = (hi - lo + 1) as usize;
+
+    while opening.moves.len() < moves {
+        let mut legal = None;
+        for _ in 0..(span * span * 4) {
+            let v = (lo + rng.below(span) as i32, lo + rng.below(span) as i32);
+            if board.check(color, v).is_ok() { legal = Some(v); break; }
+        }
+        let v = match legal {
+            Some(v) => v,
+            None    => break,
+        };
+        board.play(color, v).unwrap();
+        opening.moves.push((color, v));
+        color = color.opponent();
+    }
+    opening
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn check_load() {
+        let dir = TempDir::new("openings");
+        std::fs::write(dir.join("b.sgf"), "(;SZ[9];B[ee];W[cc])").unwrap();
+        std::fs::write(dir.join("a.SGF"), "(;SZ[9];B[cc])").unwrap();
+        std::fs::write(dir.join("notes.txt"), "# comment\n\nE5 C7 # two moves\nfoo\n").unwrap();
+
+        let o = load(dir.path(), 9).unwrap();
+        assert_eq!(o.len(), 2);
+        assert_eq!(o[0].name, "a.SGF");
+        assert_eq!(o[1].moves, vec![(Color::B, (5, 5)), (Color::W, (3, 7))]);
+        assert!(load(dir.path(), 19).is_err());
+
+        let err = load(&dir.join("notes.txt"), 9).unwrap_err();
+        assert!(err.ends_with("notes.txt:4: bad vertex 'foo'"), "{}", err);
+
+        std::fs::write(dir.join("notes.txt"), "E5 C7 # two moves\nE5 E5\n").unwrap();
+        let o = load(&dir.join("notes.txt"), 9).unwrap();
+        assert_eq!(o[0].moves.len(), 2);
+        assert!(o[0].check(9).is_ok());
+        assert!(o[1].check(9).is_err());
+    }
+}
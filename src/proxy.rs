@@ -0,0 +1,613 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+A GTP proxy, that acts as an engine towards a GUI and forwards the
+commands to one or more backend engines.
+
+The proxy sends the commands to the backends with its own IDs and answers
+with the ID of the GUI. Which backends get a command is decided like this:
+
+- A command matching a route goes to the backend of the route only.
+  A route pattern is a command name, or a prefix ending with `*`.
+- Commands changing the game state, like `play`, `boardsize` or `undo`,
+  go to all backends to keep them in sync. The response of the first
+  backend is returned, or an error naming the backend that rejected
+  the command, as the backends disagree about the game state then.
+- Everything else goes to the first backend.
+
+Moves generated with `genmove` (and its variants) are played on the
+other backends, as is the handicap of `place_free_handicap`. If a backend
+rejects the move, an error is returned instead of the move, or logged
+for streamed responses.
+
+Commands can be renamed, for instance to use `kata-genmove_analyze`
+when the GUI sends `genmove_analyze`. `list_commands` returns the commands
+of all backends.
+
+Streaming commands, like `lz-analyze`, are passed through: the output is
+forwarded to the GUI as it arrives, until the backend ends it with an
+empty line. When the GUI sends the next command, the proxy interrupts the
+stream by sending `protocol_version` to the backend.
+
+```no_run
+use gtp::controller::Engine;
+use gtp::proxy::{Proxy, Rules};
+
+let mut leela = Engine::new("leelaz", &["--gtp", "-w", "best.gz"]);
+let mut gnugo = Engine::new("gnugo", &["--mode", "gtp"]);
+leela.start().unwrap();
+gnugo.start().unwrap();
+
+let mut rules = Rules::default();
+rules.routes.push((String::from("final_score"), 1));
+
+let mut proxy = Proxy::new(vec![(String::from("leelaz"), leela),
+                                (String::from("gnugo"), gnugo)], rules);
+proxy.run(std::io::BufReader::new(std::io::stdin()), &mut std::io::stdout()).unwrap();
+```
+
+The `gtp-proxy` binary provides this on the command line.
+*/
+
+use std::io::BufRead;
+use std::io::Write;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::controller;
+use super::controller::Engine;
+use super::Command;
+use super::Response;
+use super::ResponseParser;
+
+/// Commands that change the game state and are sent to all backends.
+pub const BROADCAST_COMMANDS : &[&str] = &[
+    "boardsize", "clear_board", "komi", "play", "undo", "fixed_handicap",
+    "set_free_handicap", "loadsgf", "time_settings", "time_left",
+    "kgs-time_settings", "kgs-rules",
+];
+
+/// Commands generating a move, which is played on the other backends.
+pub const GENMOVE_COMMANDS : &[&str] = &[
+    "genmove", "kgs-genmove_cleanup", "lz-genmove_analyze",
+    "kata-genmove_analyze", "genmove_analyze",
+];
+
+/// The default streaming commands.
+pub const STREAMING_COMMANDS : &[&str] = &[
+    "lz-analyze", "kata-analyze", "analyze",
+    "lz-genmove_analyze", "kata-genmove_analyze", "genmove_analyze",
+];
+
+/// How commands are routed and rewritten.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+    /// Command patterns and the index of the backend they go to.
+    pub routes:     Vec<(String, usize)>,
+    /// Commands of the GUI and the names they are sent with.
+    pub renames:    Vec<(String, String)>,
+    /// Patterns of the commands whose output is streamed.
+    pub streaming:  Vec<String>,
+    /// Maximum time to wait for a response of a backend.
+    pub timeout:    Duration,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            routes:     Vec::new(),
+            renames:    Vec::new(),
+            streaming:  STREAMING_COMMANDS.iter().map(|s| s.to_string()).collect(),
+            timeout:    Duration::from_secs(3600),
+        }
+    }
+}
+
+fn matches(pattern: &str, cmd: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => cmd.starts_with(prefix),
+        None         => pattern == cmd,
+    }
+}
+
+impl Rules {
+    /// Returns the backend a command is routed to, if any.
+    pub fn route(&self, cmd: &str) -> Option<usize> {
+        self.routes.iter().find(|(p, _)| matches(p, cmd)).map(|(_, b)| *b)
+    }
+
+    /// Returns the name a command is sent with.
+    pub fn rename<'a>(&'a self, cmd: &'a str) -> &'a str {
+        self.renames.iter()
+            .find(|(from, _)| from == cmd)
+            .map(|(_, to)| &to[..])
+            .unwrap_or(cmd)
+    }
+
+    /// Returns true if the output of the command is streamed.
+    pub fn is_streaming(&self, cmd: &str) -> bool {
+        self.streaming.iter().any(|p| matches(p, cmd))
+    }
+}
+
+/// Errors that stop the proxy. Errors of backends are reported to the
+/// GUI as error responses instead.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from the GUI or writing to it failed.
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self { Error::Io(e) }
+}
+
+struct Backend {
+    name:       String,
+    engine:     Engine,
+    rp:         ResponseParser,
+}
+
+/// A response that is being streamed to the GUI.
+struct Stream {
+    backend:    usize,
+    gui_id:     String,
+    /// Unprocessed output of the backend.
+    buffer:     String,
+    started:    bool,
+    /// The color of a streaming `genmove` variant, whose move is
+    /// played on the other backends.
+    relay:      Option<String>,
+    /// The move from the final `play` line of the stream.
+    played:     Option<String>,
+}
+
+/// The proxy, see the [module documentation](index.html).
+pub struct Proxy {
+    rules:      Rules,
+    backends:   Vec<Backend>,
+    log:        Option<Box<dyn Write + Send>>,
+    stream:     Option<Stream>,
+}
+
+/// Splits a command line of the GUI into ID, command name and arguments.
+fn parse_line(line: &str) -> Option<(String, String, String)> {
+    let line : String =
+        line.chars()
+            .filter(|c| !c.is_control() || *c == '\t')
+            .map(|c| if c == '\t' { ' ' } else { c })
+            .collect();
+    let line = match line.find('#') {
+        Some(p) => &line[..p],
+        None    => &line[..],
+    };
+
+    let mut words = line.split_whitespace().peekable();
+    let id =
+        match words.peek().map(|w| w.parse::<u32>()) {
+            Some(Ok(id)) => { words.next(); id.to_string() },
+            _            => String::new(),
+        };
+    let name = words.next()?.to_string();
+    let args : Vec<&str> = words.collect();
+    Some((id, name, args.join(" ")))
+}
+
+fn command(name: &str, args: &str) -> Command {
+    let mut cmd = Command::new(name);
+    if !args.is_empty() { cmd.args(|e| e.s(args)); }
+    cmd
+}
+
+fn reply(out: &mut dyn Write, id: &str, resp: &Response) -> std::io::Result<()> {
+    match resp {
+        Response::Result((_, t)) => write!(out, "={} {}\n\n", id, t)?,
+        Response::Error((_, t))  => write!(out, "?{} {}\n\n", id, t)?,
+    }
+    out.flush()
+}
+
+/// Replaces the ID of the first line of a response.
+fn rewrite_id(line: &str, id: &str) -> String {
+    let mut chars = line.chars();
+    let status    = chars.next().unwrap_or('=');
+    let rest      = chars.as_str().trim_start_matches(|c: char| c.is_ascii_digit());
+    format!("{}{}{}", status, id, rest)
+}
+
+impl Proxy {
+    /// Creates a proxy for the named and started backends.
+    /// The first backend is the primary one.
+    pub fn new(backends: Vec<(String, Engine)>, rules: Rules) -> Proxy {
+        Proxy {
+            rules,
+            backends: backends.into_iter().map(|(name, engine)| {
+                Backend { name, engine, rp: ResponseParser::new() }
+            }).collect(),
+            log:    None,
+            stream: None,
+        }
+    }
+
+    /// Logs all commands and responses to `log`. The lines are prefixed
+    /// with `gui>` for commands of the GUI, `<backend>>` for commands
+    /// sent to a backend and `<backend><` for its output. Errors that
+    /// can't be reported to the GUI are logged with `proxy!`.
+    pub fn set_log(&mut self, log: Box<dyn Write + Send>) { self.log = Some(log); }
+
+    fn log(&mut self, who: &str, dir: char, text: &str) {
+        if let Some(log) = self.log.as_mut() {
+            for line in text.trim_end_matches('\n').lines() {
+                let _ = writeln!(log, "{}{} {}", who, dir, line);
+            }
+            let _ = log.flush();
+        }
+    }
+
+    /// Returns true while a response is streamed.
+    pub fn is_streaming(&self) -> bool { self.stream.is_some() }
+
+    fn send(&mut self, idx: usize, name: &str, args: &str) -> u32 {
+        let cmd  = command(self.rules.rename(name), args);
+        let text = cmd.to_string();
+        let who  = self.backends[idx].name.clone();
+        self.log(&who, '>', &text);
+        self.backends[idx].engine.send(cmd)
+    }
+
+    /// Waits for the response with the given ID.
+    fn wait(&mut self, idx: usize, id: u32) -> Result<Response, controller::Error> {
+        if id == 0 { return Err(controller::Error::NoHandle); }
+
+        let start = Instant::now();
+        loop {
+            let raw = self.backends[idx].engine.recv_raw()?;
+            if !raw.is_empty() {
+                let who = self.backends[idx].name.clone();
+                self.log(&who, '<', &raw);
+                self.backends[idx].rp.feed(&raw);
+            }
+
+            while let Ok(resp) = self.backends[idx].rp.get_response() {
+                if resp.id_0() == id { return Ok(resp); }
+            }
+
+            if start.elapsed() > self.rules.timeout {
+                return Err(controller::Error::PollAgain);
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    fn failure(&self, idx: usize, e: controller::Error) -> Response {
+        let msg = match e {
+            controller::Error::PollAgain      => String::from("timeout"),
+            controller::Error::Timeout { .. } => String::from("timeout"),
+            controller::Error::Cancelled { .. } => String::from("cancelled"),
+            e                                 => format!("{:?}", e),
+        };
+        Response::Error((None, format!("backend {} failed: {}", self.backends[idx].name, msg)))
+    }
+
+    /// Sends a command to a backend and waits for the response.
+    fn request(&mut self, idx: usize, name: &str, args: &str) -> Response {
+        let id = self.send(idx, name, args);
+        match self.wait(idx, id) {
+            Ok(resp) => resp,
+            Err(e)   => self.failure(idx, e),
+        }
+    }
+
+    /// Waits for the response to a command that keeps the backends
+    /// in sync. A rejection is returned as an error naming the backend.
+    fn wait_sync(&mut self, idx: usize, id: u32) -> Response {
+        match self.wait(idx, id) {
+            Ok(Response::Error((_, msg))) =>
+                Response::Error((None, format!("backend {} failed: {}",
+                                               self.backends[idx].name, msg))),
+            Ok(resp) => resp,
+            Err(e)   => self.failure(idx, e),
+        }
+    }
+
+    /// Plays a generated move on all backends except `from`.
+    /// Returns the error of the first backend rejecting it.
+    fn relay(&mut self, from: usize, name: &str, args: &str) -> Option<Response> {
+        let mut failed = None;
+        for i in 0..self.backends.len() {
+            if i == from { continue; }
+            let id = self.send(i, name, args);
+            if let resp @ Response::Error(_) = self.wait_sync(i, id) {
+                failed.get_or_insert(resp);
+            }
+        }
+        failed
+    }
+
+    /// Handles a command line of the GUI and writes the response to
+    /// `out`. Returns false after `quit`.
+    pub fn handle(&mut self, line: &str, out: &mut dyn Write) -> Result<bool, Error> {
+        let (id, name, args) = match parse_line(line) {
+            Some(cmd) => cmd,
+            None      => return Ok(true),
+        };
+        self.finish_stream(out)?;
+        self.log("gui", '>', line);
+
+        let primary = self.rules.route(&name);
+        let target  = primary.unwrap_or(0);
+
+        let resp =
+            match &name[..] {
+                "quit" => {
+                    for i in 0..self.backends.len() {
+                        self.send(i, "quit", "");
+                    }
+                    reply(out, &id, &Response::Result((None, String::new())))?;
+                    return Ok(false);
+                },
+                "list_commands" if primary.is_none() => self.list_commands(),
+                _ if self.rules.is_streaming(&name) => {
+                    self.send(target, &name, &args);
+                    let relay =
+                        if GENMOVE_COMMANDS.contains(&&name[..]) {
+                            args.split_whitespace().next().map(|c| c.to_string())
+                        } else {
+                            None
+                        };
+                    self.stream = Some(Stream {
+                        backend: target,
+                        gui_id: id,
+                        buffer: String::new(),
+                        started: false,
+                        relay,
+                        played: None,
+                    });
+                    return self.pump(out).map(|_| true);
+                },
+                _ if primary.is_none() && BROADCAST_COMMANDS.contains(&&name[..]) => {
+                    let ids : Vec<u32> =
+                        (0..self.backends.len()).map(|i| self.send(i, &name, &args)).collect();
+                    let resps : Vec<Response> =
+                        ids.into_iter().enumerate().map(|(i, bid)| self.wait_sync(i, bid)).collect();
+                    resps.iter()
+                        .find(|r| matches!(r, Response::Error(_)))
+                        .or_else(|| resps.first())
+                        .cloned()
+                        .unwrap_or_else(|| Response::Error((None, String::from("no backend"))))
+                },
+                _ => {
+                    let resp = self.request(target, &name, &args);
+                    let failed =
+                        match &resp {
+                            Response::Result((_, text)) => {
+                                let text = text.trim();
+                                if GENMOVE_COMMANDS.contains(&&name[..])
+                                   && !text.eq_ignore_ascii_case("resign")
+                                {
+                                    let color = args.split_whitespace().next().unwrap_or("");
+                                    self.relay(target, "play", &format!("{} {}", color, text))
+                                } else if name == "place_free_handicap" {
+                                    self.relay(target, "set_free_handicap", text)
+                                } else {
+                                    None
+                                }
+                            },
+                            Response::Error(_) => None,
+                        };
+                    failed.unwrap_or(resp)
+                },
+            };
+
+        let mut text = Vec::new();
+        reply(&mut text, &id, &resp)?;
+        self.log("gui", '<', &String::from_utf8_lossy(&text));
+        out.write_all(&text)?;
+        out.flush()?;
+        Ok(true)
+    }
+
+    /// Returns the commands of all backends, and the renamed ones.
+    fn list_commands(&mut self) -> Response {
+        let mut cmds : Vec<String> = vec![];
+        for i in 0..self.backends.len() {
+            if let Response::Result((_, t)) = self.request(i, "list_commands", "") {
+                for c in t.lines().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+                    if !cmds.iter().any(|x| x == c) { cmds.push(c.to_string()); }
+                }
+            }
+        }
+        for (from, _) in self.rules.renames.iter() {
+            if !cmds.contains(from) { cmds.push(from.clone()); }
+        }
+        Response::Result((None, cmds.join("\n")))
+    }
+
+    /// Forwards the available output of a streaming backend to the GUI.
+    pub fn pump(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        let idx = match &self.stream {
+            Some(st) => st.backend,
+            None     => return Ok(()),
+        };
+
+        let raw = match self.backends[idx].engine.recv_raw() {
+            Ok(raw) => raw,
+            Err(e)  => {
+                let st = self.stream.take().unwrap();
+                let resp = self.failure(idx, e);
+                if st.started { writeln!(out)?; } else { reply(out, &st.gui_id, &resp)?; }
+                out.flush()?;
+                return Ok(());
+            },
+        };
+        if !raw.is_empty() {
+            let who = self.backends[idx].name.clone();
+            self.log(&who, '<', &raw);
+        }
+
+        let st = self.stream.as_mut().unwrap();
+        st.buffer += &raw;
+        while let Some(nl) = st.buffer.find('\n') {
+            let line : String = st.buffer[..nl].trim_end_matches('\r').to_string();
+            st.buffer.drain(..=nl);
+
+            if !st.started {
+                if line.trim().is_empty() { continue; }
+                writeln!(out, "{}", rewrite_id(&line, &st.gui_id))?;
+                st.started = true;
+                continue;
+            }
+
+            if line.trim().is_empty() {
+                writeln!(out)?;
+                out.flush()?;
+
+                let st   = self.stream.take().unwrap();
+                let rest = st.buffer;
+                self.backends[idx].rp.feed(&rest);
+                if let (Some(color), Some(mv)) = (st.relay, st.played) {
+                    // The response was already forwarded.
+                    if let Some(failed) = self.relay(idx, "play", &format!("{} {}", color, mv)) {
+                        self.log("proxy", '!', &failed.text());
+                    }
+                }
+                return Ok(());
+            }
+
+            if let Some(mv) = line.strip_prefix("play ") {
+                st.played = Some(mv.trim().to_string());
+            }
+            writeln!(out, "{}", line)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Interrupts a running stream and forwards its remaining output.
+    fn finish_stream(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        let idx = match &self.stream {
+            Some(st) => st.backend,
+            None     => return Ok(()),
+        };
+
+        let id    = self.send(idx, "protocol_version", "");
+        let start = Instant::now();
+        while self.stream.is_some() {
+            self.pump(out)?;
+            if start.elapsed() > self.rules.timeout {
+                self.stream = None;
+                writeln!(out)?;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let _ = self.wait(idx, id);
+        Ok(())
+    }
+
+    /// Reads the commands of the GUI from `input` until `quit` or the
+    /// end of input and writes the responses to `out`.
+    pub fn run<R>(&mut self, input: R, out: &mut dyn Write) -> Result<(), Error>
+        where R: BufRead + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in input.lines() {
+                if tx.send(line).is_err() { break; }
+            }
+        });
+
+        loop {
+            let line =
+                if self.is_streaming() {
+                    match rx.try_recv() {
+                        Ok(line) => line,
+                        Err(mpsc::TryRecvError::Empty) => {
+                            self.pump(out)?;
+                            std::thread::sleep(Duration::from_millis(5));
+                            continue;
+                        },
+                        Err(mpsc::TryRecvError::Disconnected) => return self.finish_stream(out),
+                    }
+                } else {
+                    match rx.recv() {
+                        Ok(line) => line,
+                        Err(_)   => return Ok(()),
+                    }
+                };
+
+            if !self.handle(&line?, out)? { return Ok(()); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    #[test]
+    fn check_routing_and_streaming() {
+        let a = MockEngine::new(Script::new()
+            .expect("boardsize 9").respond("")
+            .expect("genmove b").respond("D4")
+            .expect("lz-analyze 50").raw("=\ninfo move E5 visits 1\n")
+            .expect("protocol_version").raw("info move E5 visits 2\n\n").respond("2")
+            .expect("quit").respond(""));
+        let b = MockEngine::new(Script::new()
+            .expect("boardsize 9").fail("unacceptable size")
+            .expect("play b D4").respond("")
+            .expect("gg-final_score").respond("B+3")
+            .expect("quit").respond(""));
+
+        let mut rules = Rules::default();
+        rules.routes.push((String::from("final_score"), 1));
+        rules.renames.push((String::from("final_score"), String::from("gg-final_score")));
+        let mut p = Proxy::new(vec![(String::from("a"), a.engine()),
+                                    (String::from("b"), b.engine())], rules);
+
+        let mut out = Vec::new();
+        assert!(p.handle("1 boardsize 9", &mut out).unwrap());
+        assert!(p.handle("genmove b # comment", &mut out).unwrap());
+        assert!(p.handle("5 final_score", &mut out).unwrap());
+        assert!(p.handle("7 lz-analyze 50", &mut out).unwrap());
+        assert!(p.is_streaming());
+        assert!(!p.handle("8 quit", &mut out).unwrap());
+
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "?1 backend b failed: unacceptable size\n\n= D4\n\n=5 B+3\n\n\
+                    =7\ninfo move E5 visits 1\ninfo move E5 visits 2\n\n=8 \n\n");
+        a.assert_done();
+        b.assert_done();
+    }
+
+    #[test]
+    fn check_rejected_relay() {
+        let a = MockEngine::new(Script::new()
+            .expect("genmove b").respond("D4")
+            .expect("place_free_handicap 2").respond("D4 Q16"));
+        let b = MockEngine::new(Script::new()
+            .expect("play b D4").fail("illegal move")
+            .expect("set_free_handicap D4 Q16").respond(""));
+        let mut p = Proxy::new(vec![(String::from("a"), a.engine()),
+                                    (String::from("b"), b.engine())], Rules::default());
+
+        let mut out = Vec::new();
+        assert!(p.handle("1 genmove b", &mut out).unwrap());
+        assert!(p.handle("2 place_free_handicap 2", &mut out).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "?1 backend b failed: illegal move\n\n=2 D4 Q16\n\n");
+        a.assert_done();
+        b.assert_done();
+    }
+}
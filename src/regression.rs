@@ -0,0 +1,423 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+A runner for regression test files in the format used by GNU Go and GoGui.
+
+A regression test file (usually with the extension `.tst`) contains GTP
+commands. A command with a numeric ID, that is followed by an annotation
+line starting with `#?`, is a test:
+
+```text
+loadsgf games/ko1.sgf 12
+10 reg_genmove white
+#? [C3|D4]
+
+20 genmove black
+#? [!PASS]*
+
+30 play white Z99
+#? [?illegal move]
+```
+
+The expected response is a regular expression between the brackets,
+which has to match the complete response text. Alternatives are
+separated by `|`. A leading `!` negates the pattern. A leading `?` means
+the command is expected to fail, and the rest of the pattern is matched
+against the error message. A trailing `*` marks a test which is known to
+fail. Commands without an annotation are sent too, but their responses
+are not checked.
+
+Relative paths in the test file (for `loadsgf`) are resolved by the
+engine, so start it in the directory of the test file.
+
+```no_run
+use std::time::Duration;
+use gtp::controller::Engine;
+use gtp::regression;
+
+let mut ctrl = Engine::new("/usr/bin/gnugo", &["--mode", "gtp"]);
+assert!(ctrl.start().is_ok());
+
+let suite  = regression::Suite::load("ko.tst").unwrap();
+let report = regression::run(&mut ctrl, &suite, Duration::from_secs(60)).unwrap();
+print!("{}", report);
+```
+*/
+
+use std::time::Duration;
+use std::time::Instant;
+
+use regex::Regex;
+
+use super::controller::Engine;
+use super::controller::Error;
+use super::Command;
+use super::Response;
+
+/// The expected response of a test.
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    /// The pattern as written in the test file, without
+    /// the `!`, `?` and `*` markers.
+    pub pattern:        String,
+    regex:              Regex,
+    /// The response must not match the pattern.
+    pub negate:         bool,
+    /// The command is expected to fail.
+    pub error:          bool,
+    /// The test is known to fail.
+    pub expect_failure: bool,
+}
+
+impl Expectation {
+    /// Parses the part of a `#?` annotation line following the `#?`,
+    /// like `[!A1|B2]*`.
+    pub fn parse(s: &str) -> Result<Expectation, String> {
+        let s = s.trim();
+        if !s.starts_with('[') {
+            return Err(format!("expected '[' in annotation '{}'", s));
+        }
+        let close = match s.rfind(']') {
+            Some(i) => i,
+            None    => return Err(format!("missing ']' in annotation '{}'", s)),
+        };
+
+        let mut pattern = &s[1..close];
+        let expect_failure = s[close + 1..].trim_start().starts_with('*');
+
+        let negate = pattern.starts_with('!');
+        if negate { pattern = &pattern[1..]; }
+        let error = pattern.starts_with('?');
+        if error { pattern = &pattern[1..]; }
+        let pattern = pattern.trim();
+
+        let regex = Regex::new(&format!("^(?:{})$", pattern))
+            .map_err(|e| format!("bad pattern '{}': {}", pattern, e))?;
+
+        Ok(Expectation {
+            pattern: pattern.to_string(),
+            regex,
+            negate,
+            error,
+            expect_failure,
+        })
+    }
+
+    /// Checks whether the response fulfills the expectation.
+    pub fn matches(&self, resp: &Response) -> bool {
+        let (is_error, text) = match resp {
+            Response::Error((_, t))  => (true, t),
+            Response::Result((_, t)) => (false, t),
+        };
+        let m = is_error == self.error && self.regex.is_match(text.trim());
+        m != self.negate
+    }
+}
+
+/// A command of a regression test file.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The line number in the test file, counted from 1.
+    pub line:       usize,
+    /// The test number, if the command had an ID.
+    pub id:         Option<u32>,
+    /// The command line without the ID.
+    pub command:    String,
+    /// What the response should look like, if the command is a test.
+    pub expect:     Option<Expectation>,
+}
+
+/// The parsed contents of a regression test file.
+#[derive(Debug, Clone, Default)]
+pub struct Suite {
+    pub name:       String,
+    pub entries:    Vec<Entry>,
+}
+
+impl Suite {
+    /// Parses the text of a regression test file.
+    ///
+    /// ```
+    /// let suite = gtp::regression::Suite::parse("test", "boardsize 9\n1 genmove b\n#? [E5]*\n").unwrap();
+    /// assert_eq!(suite.entries.len(), 2);
+    /// assert_eq!(suite.tests().count(), 1);
+    /// assert!(suite.entries[1].expect.as_ref().unwrap().expect_failure);
+    /// ```
+    pub fn parse(name: &str, text: &str) -> Result<Suite, String> {
+        let mut suite = Suite { name: name.to_string(), entries: Vec::new() };
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if let Some(ann) = line.strip_prefix("#?") {
+                let expect =
+                    Expectation::parse(ann)
+                        .map_err(|e| format!("{}:{}: {}", name, i + 1, e))?;
+                match suite.entries.last_mut() {
+                    Some(e) if e.id.is_some() && e.expect.is_none() => {
+                        e.expect = Some(expect);
+                    },
+                    _ => {
+                        return Err(format!(
+                            "{}:{}: annotation without numbered command",
+                            name, i + 1));
+                    },
+                }
+                continue;
+            }
+
+            let line = match line.find('#') {
+                Some(p) => line[..p].trim(),
+                None    => line,
+            };
+            if line.is_empty() { continue; }
+
+            let mut parts = line.splitn(2, ' ');
+            let first     = parts.next().unwrap_or("");
+            let (id, command) =
+                if let Ok(id) = first.parse::<u32>() {
+                    (Some(id), parts.next().unwrap_or("").trim().to_string())
+                } else {
+                    (None, line.to_string())
+                };
+
+            suite.entries.push(Entry { line: i + 1, id, command, expect: None });
+        }
+
+        Ok(suite)
+    }
+
+    /// Reads and parses a regression test file.
+    pub fn load(path: &str) -> Result<Suite, String> {
+        let text =
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("Couldn't read '{}': {}", path, e))?;
+        Suite::parse(path, &text)
+    }
+
+    /// Returns the entries that are tests.
+    pub fn tests(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| e.expect.is_some())
+    }
+}
+
+/// The outcome of a test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// A test marked as known to fail passed.
+    UnexpectedPass,
+    /// A test not marked as known to fail failed.
+    UnexpectedFail,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Pass           => write!(f, "passed"),
+            Outcome::Fail           => write!(f, "failed"),
+            Outcome::UnexpectedPass => write!(f, "PASSED"),
+            Outcome::UnexpectedFail => write!(f, "FAILED"),
+        }
+    }
+}
+
+/// The result of a single test.
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub line:       usize,
+    pub id:         u32,
+    pub command:    String,
+    pub expected:   String,
+    /// The response, `None` if there was none in time.
+    pub response:   Option<Response>,
+    pub outcome:    Outcome,
+    pub elapsed:    Duration,
+}
+
+/// The results of running a `Suite`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub name:       String,
+    pub results:    Vec<TestResult>,
+    pub elapsed:    Duration,
+}
+
+impl Report {
+    /// Returns the number of tests with the given outcome.
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.results.iter().filter(|r| r.outcome == outcome).count()
+    }
+
+    /// Returns the tests with unexpected outcomes.
+    pub fn unexpected(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter().filter(|r| {
+            r.outcome == Outcome::UnexpectedPass
+            || r.outcome == Outcome::UnexpectedFail
+        })
+    }
+
+    /// Returns true if all tests had the expected outcome.
+    pub fn is_pass(&self) -> bool { self.unexpected().next().is_none() }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for r in self.unexpected() {
+            writeln!(f, "{}:{} {} {} [{}] expected [{}] ({:.2}s)",
+                     self.name, r.id, r.outcome, r.command,
+                     r.response.as_ref().map(|r| r.text()).unwrap_or_else(|| String::from("timeout")),
+                     r.expected,
+                     r.elapsed.as_secs_f64())?;
+        }
+        writeln!(f, "{}: {} passed, {} failed, {} PASSED, {} FAILED ({:.2}s)",
+                 self.name,
+                 self.count(Outcome::Pass),
+                 self.count(Outcome::Fail),
+                 self.count(Outcome::UnexpectedPass),
+                 self.count(Outcome::UnexpectedFail),
+                 self.elapsed.as_secs_f64())
+    }
+}
+
+/// Runs the commands of `suite` on the started `engine` and checks the
+/// responses. `timeout` is the maximum time to wait for each response.
+///
+/// A test timing out fails, and the suite goes on. Returns an error if
+/// the engine could not be communicated with.
+pub fn run(engine: &mut Engine, suite: &Suite, timeout: Duration) -> Result<Report, Error> {
+    let start      = Instant::now();
+    let mut report = Report { name: suite.name.clone(), ..Report::default() };
+
+    for entry in suite.entries.iter() {
+        let t0   = Instant::now();
+        let resp =
+            match engine.send_wait(command(&entry.command), timeout) {
+                Ok(resp)                  => Some(resp),
+                Err(Error::Timeout { .. }) => None,
+                Err(e)                    => return Err(e),
+            };
+
+        if let Some(expect) = &entry.expect {
+            let passed = resp.as_ref().map(|r| expect.matches(r)).unwrap_or(false);
+            let outcome =
+                match (passed, expect.expect_failure) {
+                    (true,  false) => Outcome::Pass,
+                    (true,  true)  => Outcome::UnexpectedPass,
+                    (false, true)  => Outcome::Fail,
+                    (false, false) => Outcome::UnexpectedFail,
+                };
+
+            report.results.push(TestResult {
+                line:       entry.line,
+                id:         entry.id.unwrap_or(0),
+                command:    entry.command.clone(),
+                expected:   expect.pattern.clone(),
+                response:   resp,
+                outcome,
+                elapsed:    t0.elapsed(),
+            });
+        }
+    }
+
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+fn command(line: &str) -> Command {
+    let mut parts = line.splitn(2, ' ');
+    let mut cmd   = Command::new(parts.next().unwrap_or(""));
+    if let Some(args) = parts.next() {
+        cmd.args(|e| e.s(args));
+    }
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    fn res(text: &str) -> Response { Response::Result((None, text.to_string())) }
+    fn err(text: &str) -> Response { Response::Error((None, text.to_string())) }
+
+    #[test]
+    fn check_expectations() {
+        let e = Expectation::parse("[C3|D4]").unwrap();
+        assert!(e.matches(&res("C3")));
+        assert!(e.matches(&res("D4")));
+        assert!(!e.matches(&res("D44")));
+        assert!(!e.matches(&err("C3")));
+        assert!(!e.expect_failure);
+
+        let e = Expectation::parse(" [!PASS]*").unwrap();
+        assert!(e.negate);
+        assert!(e.expect_failure);
+        assert!(e.matches(&res("A1")));
+        assert!(!e.matches(&res("PASS")));
+
+        let e = Expectation::parse("[?illegal move]").unwrap();
+        assert!(e.matches(&err("illegal move")));
+        assert!(!e.matches(&res("illegal move")));
+
+        let e = Expectation::parse("[1 (A1|B2)]").unwrap();
+        assert!(e.matches(&res("1 B2")));
+
+        assert!(Expectation::parse("C3").is_err());
+        assert!(Expectation::parse("[(C3]").is_err());
+    }
+
+    #[test]
+    fn check_suite_parse() {
+        let suite = Suite::parse("t.tst", "\
+            # A comment\n\
+            loadsgf games/ko.sgf 12 # with comment\n\
+            10 reg_genmove white\n\
+            #? [C3|D4]\n\
+            \n\
+            20 genmove black\n").unwrap();
+
+        assert_eq!(suite.entries.len(), 3);
+        assert_eq!(suite.entries[0].command, "loadsgf games/ko.sgf 12");
+        assert_eq!(suite.entries[0].id, None);
+        assert_eq!(suite.entries[1].id, Some(10));
+        assert_eq!(suite.entries[1].line, 3);
+        assert_eq!(suite.entries[1].command, "reg_genmove white");
+        assert!(suite.entries[2].expect.is_none());
+        assert_eq!(suite.tests().count(), 1);
+
+        assert!(Suite::parse("t.tst", "name\n#? [x]\n").is_err());
+    }
+
+    #[test]
+    fn check_run() {
+        let mock = MockEngine::new(Script::new()
+            .expect("boardsize 9").respond("")
+            .expect("genmove b").respond("D4")
+            .expect("genmove w").respond("PASS")
+            .expect("play w Z99").fail("illegal move")
+            .expect("reg_genmove b").delay(Duration::from_millis(300)).respond("C3")
+            .expect("genmove b").respond("E5"));
+        let suite = Suite::parse("t.tst", "\
+            boardsize 9\n\
+            1 genmove b\n#? [C3|D4]\n\
+            2 genmove w\n#? [!PASS]*\n\
+            3 play w Z99\n#? [?illegal move]\n\
+            4 reg_genmove b\n#? [C3]\n\
+            5 genmove b\n#? [E5]\n").unwrap();
+
+        let mut ctrl = mock.engine();
+        let report = run(&mut ctrl, &suite, Duration::from_millis(150)).unwrap();
+        mock.assert_done();
+
+        let outcomes : Vec<Outcome> = report.results.iter().map(|r| r.outcome).collect();
+        assert_eq!(outcomes, vec![Outcome::Pass, Outcome::Fail, Outcome::Pass,
+                                  Outcome::UnexpectedFail, Outcome::Pass]);
+        assert!(report.results[3].response.is_none());
+        assert!(!report.is_pass());
+        assert!(report.to_string().contains("t.tst:4 FAILED reg_genmove b [timeout] expected [C3]"),
+                "{}", report);
+    }
+}
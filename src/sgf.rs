@@ -0,0 +1,296 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Writing and reading of game records in the SGF (Smart Game Format) file
+format. Reading is limited to the root properties written by
+[`GameRecord::to_sgf()`](struct.GameRecord.html#method.to_sgf) and the
+moves of the main line.
+
+```
+use gtp::Color;
+use gtp::sgf::GameRecord;
+
+let mut rec = GameRecord::new(9, 7.0);
+rec.black  = String::from("GNU Go");
+rec.result = String::from("B+R");
+rec.moves.push((Color::B, (3, 7)));
+rec.moves.push((Color::W, (0, 0)));
+assert_eq!(rec.to_sgf(),
+    "(;GM[1]FF[4]CA[UTF-8]AP[gtp-rs]SZ[9]KM[7]PB[GNU Go]PW[]RE[B+R]\n;B[cc];W[])\n");
+```
+*/
+
+use super::Color;
+
+/// A game with its moves and the SGF root properties.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GameRecord {
+    pub size:       usize,
+    pub komi:       f32,
+    /// Name of the black player.
+    pub black:      String,
+    /// Name of the white player.
+    pub white:      String,
+    /// The result in SGF notation, like `B+R`, `W+3.5` or `0`.
+    pub result:     String,
+    /// The date of the game, like `2019-06-14`. Omitted if empty.
+    pub date:       String,
+    /// The comment of the root node. Omitted if empty.
+    pub comment:    String,
+    /// The name of the opening, stored in the `ON` property.
+    /// Omitted if empty.
+    pub opening:    String,
+    /// The moves, with `(0, 0)` for a pass.
+    pub moves:      Vec<(Color, (i32, i32))>,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+impl GameRecord {
+    pub fn new(size: usize, komi: f32) -> GameRecord {
+        GameRecord { size, komi, ..GameRecord::default() }
+    }
+
+    /// Converts a vertex to SGF coordinates, which count rows from the top.
+    fn point(&self, (h, v): (i32, i32)) -> String {
+        if (h, v) == (0, 0) { return String::from(""); }
+        let col = (b'a' + (h - 1) as u8) as char;
+        let row = (b'a' + (self.size as i32 - v) as u8) as char;
+        format!("{}{}", col, row)
+    }
+
+    /// Generates the SGF text of the game.
+    pub fn to_sgf(&self) -> String {
+        let mut out = String::from("(;GM[1]FF[4]CA[UTF-8]AP[gtp-rs]");
+        out += &format!("SZ[{}]KM[{}]", self.size, self.komi);
+        out += &format!("PB[{}]PW[{}]", escape(&self.black), escape(&self.white));
+        out += &format!("RE[{}]", escape(&self.result));
+        if !self.date.is_empty() {
+            out += &format!("DT[{}]", escape(&self.date));
+        }
+        if !self.opening.is_empty() {
+            out += &format!("ON[{}]", escape(&self.opening));
+        }
+        if !self.comment.is_empty() {
+            out += &format!("C[{}]", escape(&self.comment));
+        }
+        out += "\n";
+
+        for (color, vertex) in self.moves.iter() {
+            let c = if *color == Color::B { "B" } else { "W" };
+            out += &format!(";{}[{}]", c, self.point(*vertex));
+        }
+        out += ")\n";
+        out
+    }
+
+    /// Writes the SGF text of the game to a file.
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_sgf())
+    }
+
+    /// Reads the root properties and the moves of the main line of the
+    /// first game in an SGF text. Variations are skipped, setup stones
+    /// (`AB`, `AW`) are not supported.
+    ///
+    /// ```
+    /// let rec = gtp::sgf::GameRecord::parse("(;SZ[9]KM[6.5];B[ee](;W[cc];B[tt])(;W[gg]))").unwrap();
+    /// assert_eq!(rec.size, 9);
+    /// assert_eq!(rec.moves.len(), 3);
+    /// assert_eq!(rec.moves[1], (gtp::Color::W, (3, 7)));
+    /// assert_eq!(rec.moves[2], (gtp::Color::B, (0, 0)));
+    /// ```
+    pub fn parse(text: &str) -> Result<GameRecord, String> {
+        let mut rec = GameRecord::new(19, 0.0);
+        let mut r   = Reader { chars: text.chars().collect(), pos: 0 };
+        r.skip_ws();
+        r.game_tree(true, &mut rec)?;
+        Ok(rec)
+    }
+
+    /// Reads an SGF file, see [`parse()`](#method.parse).
+    pub fn load(path: &std::path::Path) -> Result<GameRecord, String> {
+        let text =
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("Couldn't read '{}': {}", path.display(), e))?;
+        GameRecord::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Converts SGF coordinates to a vertex, the empty point and `tt`
+    /// on boards up to 19x19 are a pass.
+    fn vertex(&self, point: &str) -> Result<(i32, i32), String> {
+        if point.is_empty() || (point == "tt" && self.size <= 19) {
+            return Ok((0, 0));
+        }
+        let b = point.as_bytes();
+        if b.len() != 2 || !b[0].is_ascii_lowercase() || !b[1].is_ascii_lowercase() {
+            return Err(format!("bad point '{}'", point));
+        }
+        let h = (b[0] - b'a') as i32 + 1;
+        let v = self.size as i32 - (b[1] - b'a') as i32;
+        if h > self.size as i32 || v < 1 {
+            return Err(format!("point '{}' outside of the board", point));
+        }
+        Ok((h, v))
+    }
+
+    fn apply(&mut self, ident: &str, values: &[String]) -> Result<(), String> {
+        let value = values.first().map(|v| &v[..]).unwrap_or("");
+        match ident {
+            "SZ" => {
+                self.size = value.trim().parse::<usize>()
+                    .map_err(|_| format!("unsupported board size '{}'", value))?;
+            },
+            "KM" => { self.komi = value.trim().parse::<f32>().unwrap_or(0.0); },
+            "PB" => { self.black   = value.to_string(); },
+            "PW" => { self.white   = value.to_string(); },
+            "RE" => { self.result  = value.to_string(); },
+            "DT" => { self.date    = value.to_string(); },
+            "ON" => { self.opening = value.to_string(); },
+            "B" | "W" => {
+                let color = if ident == "B" { Color::B } else { Color::W };
+                let v     = self.vertex(value.trim())?;
+                self.moves.push((color, v));
+            },
+            "AB" | "AW" | "AE" => {
+                return Err(String::from("setup stones are not supported"));
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+struct Reader {
+    chars:  Vec<char>,
+    pos:    usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> { self.chars.get(self.pos).cloned() }
+
+    fn skip_ws(&mut self) {
+        while self.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!("expected '{}' at offset {}", c, self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Reads a game tree, the properties are only applied to `rec`
+    /// if `main` is set.
+    fn game_tree(&mut self, main: bool, rec: &mut GameRecord) -> Result<(), String> {
+        self.expect('(')?;
+        self.skip_ws();
+        while self.peek() == Some(';') {
+            self.pos += 1;
+            self.node(main, rec)?;
+            self.skip_ws();
+        }
+
+        let mut first = true;
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some('(') => { self.game_tree(main && first, rec)?; first = false; },
+                Some(')') => { self.pos += 1; return Ok(()); },
+                Some(c)   => return Err(format!("unexpected '{}' at offset {}", c, self.pos)),
+                None      => return Err(String::from("unexpected end of SGF")),
+            }
+        }
+    }
+
+    fn node(&mut self, main: bool, rec: &mut GameRecord) -> Result<(), String> {
+        loop {
+            self.skip_ws();
+            let mut ident = String::new();
+            while let Some(c) = self.peek() {
+                if !c.is_ascii_alphabetic() { break; }
+                // Lower case letters are allowed in property names by FF[3].
+                if c.is_ascii_uppercase() { ident.push(c); }
+                self.pos += 1;
+            }
+            if ident.is_empty() { return Ok(()); }
+
+            let mut values = vec![];
+            self.skip_ws();
+            while self.peek() == Some('[') {
+                values.push(self.value()?);
+                self.skip_ws();
+            }
+            if values.is_empty() {
+                return Err(format!("property {} without value", ident));
+            }
+            if main { rec.apply(&ident, &values)?; }
+        }
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        self.expect('[')?;
+        let mut v = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.pos += 1;
+                    if let Some(c) = self.peek() { v.push(c); }
+                },
+                Some(']') => { self.pos += 1; return Ok(v); },
+                Some(c)   => v.push(c),
+                None      => return Err(String::from("unexpected end of SGF")),
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+/// Returns the current date (UTC) in the format of the SGF `DT` property.
+pub fn today() -> String {
+    let secs =
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+    // Conversion of days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z   = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp  = (5 * doy + 2) / 153;
+    let d   = doy - (153 * mp + 2) / 5 + 1;
+    let m   = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y   = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_round_trip() {
+        let mut rec = GameRecord::new(13, 6.5);
+        rec.black   = String::from("Black [1]");
+        rec.result  = String::from("W+0.5");
+        rec.opening = String::from("san-ren-sei");
+        rec.moves   = vec![(Color::B, (1, 13)), (Color::W, (13, 1)), (Color::B, (0, 0))];
+
+        assert_eq!(GameRecord::parse(&rec.to_sgf()), Ok(rec));
+
+        assert!(GameRecord::parse("(;SZ[9];B[zz])").is_err());
+        assert!(GameRecord::parse("(;SZ[9]AB[aa])").is_err());
+        assert!(GameRecord::parse("(;SZ[9];B[aa]").is_err());
+    }
+}
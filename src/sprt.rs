@@ -0,0 +1,218 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Sequential probability ratio test (SPRT) for deciding matches early.
+
+The test compares the hypothesis H0, that the tested engine is `elo0`
+stronger than its opponent, against H1, that it is `elo1` stronger. After
+each game the log-likelihood ratio (LLR) is updated. The test is decided
+when the LLR leaves the bounds given by the error probabilities `alpha`
+(accepting H1 although H0 holds) and `beta` (accepting H0 although H1
+holds).
+
+The LLR is computed with the normal approximation of the score
+distribution, as done by fishtest and cutechess-cli. Half a win and half
+a loss are added to the results as a prior, so the test also decides
+when one engine wins every game.
+
+The results can come from any source, for instance from a
+[`Match`](../arbiter/struct.Match.html) with `MatchConfig::sprt` set:
+
+```
+use gtp::sprt::{Sprt, Status};
+
+let mut sprt = Sprt::new(0.0, 10.0, 0.05, 0.05);
+for _ in 0..500 {
+    sprt.add(1.0);
+    sprt.add(0.5);
+    sprt.add(0.0);
+    sprt.add(1.0);
+}
+assert_eq!(sprt.status(), Status::AcceptH1);
+```
+*/
+
+use super::arbiter::GameResult;
+use super::Color;
+
+/// The decision of the test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// More games are needed.
+    Continue,
+    /// The tested engine is not `elo1` stronger.
+    AcceptH0,
+    /// The tested engine is not only `elo0` stronger.
+    AcceptH1,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Status::Continue => write!(f, "continue"),
+            Status::AcceptH0 => write!(f, "H0 accepted"),
+            Status::AcceptH1 => write!(f, "H1 accepted"),
+        }
+    }
+}
+
+/// The state of a sequential probability ratio test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprt {
+    pub elo0:   f64,
+    pub elo1:   f64,
+    pub alpha:  f64,
+    pub beta:   f64,
+    /// Wins, draws and losses of the tested engine.
+    pub wins:   usize,
+    pub draws:  usize,
+    pub losses: usize,
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10.0_f64.powf(-elo / 400.0))
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Sprt {
+        Sprt { elo0, elo1, alpha, beta, wins: 0, draws: 0, losses: 0 }
+    }
+
+    /// Parses `<elo0>,<elo1>[,<alpha>,<beta>]`,
+    /// `alpha` and `beta` default to 0.05.
+    ///
+    /// ```
+    /// let sprt = gtp::sprt::Sprt::parse("0,5").unwrap();
+    /// assert_eq!((sprt.elo1, sprt.alpha, sprt.beta), (5.0, 0.05, 0.05));
+    /// ```
+    pub fn parse(s: &str) -> Result<Sprt, String> {
+        let v : Result<Vec<f64>, _> = s.split(',').map(|p| p.trim().parse::<f64>()).collect();
+        let v = v.map_err(|_| format!("bad SPRT parameters '{}'", s))?;
+        let sprt =
+            match v.len() {
+                2 => Sprt::new(v[0], v[1], 0.05, 0.05),
+                4 => Sprt::new(v[0], v[1], v[2], v[3]),
+                _ => return Err(format!("expected <elo0>,<elo1>[,<alpha>,<beta>], got '{}'", s)),
+            };
+
+        let p = 0.0..1.0;
+        if sprt.elo0 >= sprt.elo1 || !p.contains(&sprt.alpha) || !p.contains(&sprt.beta)
+           || sprt.alpha == 0.0 || sprt.beta == 0.0
+        {
+            return Err(format!("bad SPRT parameters '{}'", s));
+        }
+        Ok(sprt)
+    }
+
+    /// Adds a game with the given score of the tested engine:
+    /// 1 for a win, 0.5 for a draw and 0 for a loss.
+    pub fn add(&mut self, score: f64) {
+        if score > 0.75      { self.wins   += 1; }
+        else if score < 0.25 { self.losses += 1; }
+        else                 { self.draws  += 1; }
+    }
+
+    /// Adds a game result, with `tested` being the color of the tested
+    /// engine. Returns false if the result is unknown and was ignored.
+    pub fn add_result(&mut self, result: &GameResult, tested: Color) -> bool {
+        match result {
+            GameResult::Win(c, _) => self.add(if *c == tested { 1.0 } else { 0.0 }),
+            GameResult::Draw      => self.add(0.5),
+            GameResult::Unknown   => return false,
+        }
+        true
+    }
+
+    /// Returns the number of games.
+    pub fn games(&self) -> usize { self.wins + self.draws + self.losses }
+
+    /// Returns the lower and upper bound of the LLR.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(),
+         ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// Returns the log-likelihood ratio. It is 0 without games.
+    pub fn llr(&self) -> f64 {
+        if self.games() == 0 { return 0.0; }
+
+        // The prior of half a win and half a loss keeps
+        // the variance above 0 for one-sided results:
+        let wins   = self.wins   as f64 + 0.5;
+        let losses = self.losses as f64 + 0.5;
+        let n      = self.games() as f64 + 1.0;
+
+        let (w, d, l) = (wins / n, self.draws as f64 / n, losses / n);
+        let score = w + d / 2.0;
+        let var   =   w * (1.0 - score).powi(2)
+                    + d * (0.5 - score).powi(2)
+                    + l * score.powi(2);
+
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        n * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * var)
+    }
+
+    /// Returns the decision of the test.
+    pub fn status(&self) -> Status {
+        let llr = self.llr();
+        let (lower, upper) = self.bounds();
+        if llr >= upper      { Status::AcceptH1 }
+        else if llr <= lower { Status::AcceptH0 }
+        else                 { Status::Continue }
+    }
+}
+
+impl std::fmt::Display for Sprt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (lower, upper) = self.bounds();
+        write!(f, "SPRT elo0={} elo1={}: LLR {:.2} [{:.2}, {:.2}] W/D/L {}/{}/{}: {}",
+               self.elo0, self.elo1, self.llr(), lower, upper,
+               self.wins, self.draws, self.losses, self.status())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbiter::WinReason;
+
+    #[test]
+    fn check_llr() {
+        let mut s = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        let (lower, upper) = s.bounds();
+        assert!((lower + 2.944).abs() < 1e-3);
+        assert!((upper - 2.944).abs() < 1e-3);
+
+        assert_eq!(s.llr(), 0.0);
+        s.add(1.0);
+        assert!(s.llr() > 0.0);
+        assert_eq!(s.status(), Status::Continue);
+
+        // Even results favor H0.
+        for _ in 0..5000 { s.add(1.0); s.add(0.0); }
+        assert!(s.llr() < 0.0);
+        assert_eq!(s.status(), Status::AcceptH0);
+
+        let mut s = Sprt::new(0.0, 5.0, 0.05, 0.05);
+        assert!(s.add_result(&GameResult::Win(Color::W, WinReason::Resign), Color::W));
+        assert!(s.add_result(&GameResult::Win(Color::W, WinReason::Resign), Color::B));
+        assert!(s.add_result(&GameResult::Draw, Color::B));
+        assert!(!s.add_result(&GameResult::Unknown, Color::B));
+        assert_eq!((s.wins, s.draws, s.losses), (1, 1, 1));
+
+        // One-sided results are decided, too.
+        let mut s = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        while s.status() == Status::Continue && s.games() < 100 { s.add(1.0); }
+        assert_eq!(s.status(), Status::AcceptH1);
+        assert!(s.games() < 20);
+
+        let mut s = Sprt::new(0.0, 10.0, 0.05, 0.05);
+        while s.status() == Status::Continue && s.games() < 100 { s.add(0.0); }
+        assert_eq!(s.status(), Status::AcceptH0);
+
+        assert!(Sprt::parse("5,0").is_err());
+        assert!(Sprt::parse("0,5,0.05").is_err());
+        assert!(Sprt::parse("0,5,0,0.05").is_err());
+    }
+}
@@ -0,0 +1,375 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Supervision of an engine, restarting it when it crashes.
+
+A [`Supervisor`](struct.Supervisor.html) sends commands like
+[`Engine::send_wait()`](../controller/struct.Engine.html#method.send_wait).
+When the engine process disappears, it records a
+[`CrashReport`](struct.CrashReport.html) with the exit status and the last
+lines the engine wrote to stderr, starts a new process after a backoff
+delay and restores the game: the board size, komi and the moves played
+so far. Then the command is sent again. After too many restarts the
+supervisor gives up.
+
+The game is tracked from the successful responses to `boardsize`,
+`clear_board`, `komi`, `play`, `genmove`, `undo`, the handicap commands
+and `loadsgf`.
+
+```no_run
+use std::time::Duration;
+use gtp::Command;
+use gtp::supervisor::{RestartPolicy, Supervisor};
+
+let mut sup = Supervisor::new("leelaz", &["--gtp", "-w", "best.gz"], RestartPolicy::new()).unwrap();
+let t = Duration::from_secs(60);
+sup.send_wait(Command::cmd("boardsize", |e| e.i(19)), t).unwrap();
+let mv = sup.send_wait(Command::cmd("genmove", |e| e.b()), t).unwrap();
+println!("{} after {} crashes", mv.text(), sup.crashes().len());
+for crash in sup.crashes() {
+    println!("{}", crash);
+}
+```
+*/
+
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use super::controller;
+use super::controller::Engine;
+use super::Command;
+use super::Response;
+
+/// When and how to restart a crashed engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestartPolicy {
+    /// How often the engine is restarted, before giving up.
+    pub max_restarts:   usize,
+    /// The delay before the first restart, doubled for each further one.
+    pub backoff:        Duration,
+    /// The maximum delay before a restart.
+    pub max_backoff:    Duration,
+    /// Whether the game is restored on the new process.
+    pub restore:        bool,
+    /// Whether the command the engine crashed on is sent again.
+    pub retry:          bool,
+    /// How many lines of stderr are kept in the crash report.
+    pub stderr_lines:   usize,
+    /// The time to wait for the responses while restoring the game.
+    pub timeout:        Duration,
+}
+
+impl RestartPolicy {
+    /// Returns a policy of up to 3 restarts, starting with a backoff of
+    /// half a second, restoring the game and retrying the command.
+    pub fn new() -> RestartPolicy {
+        RestartPolicy {
+            max_restarts:   3,
+            backoff:        Duration::from_millis(500),
+            max_backoff:    Duration::from_secs(30),
+            restore:        true,
+            retry:          true,
+            stderr_lines:   20,
+            timeout:        Duration::from_secs(60),
+        }
+    }
+
+    /// Returns the delay before the restart with the given number,
+    /// counted from 0.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// let p = gtp::supervisor::RestartPolicy::new();
+    /// assert_eq!(p.delay(0), Duration::from_millis(500));
+    /// assert_eq!(p.delay(2), Duration::from_secs(2));
+    /// assert_eq!(p.delay(10), Duration::from_secs(30));
+    /// ```
+    pub fn delay(&self, restart: usize) -> Duration {
+        let factor = 1u32.checked_shl(restart as u32).unwrap_or(u32::MAX);
+        self.backoff.checked_mul(factor).unwrap_or(self.max_backoff).min(self.max_backoff)
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self { RestartPolicy::new() }
+}
+
+/// What is known about a crash.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    /// The command the engine crashed on.
+    pub command:    String,
+    /// The exit status, `None` if the engine has no process.
+    pub status:     Option<ExitStatus>,
+    /// The last lines written to stderr.
+    pub stderr:     Vec<String>,
+}
+
+impl std::fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "engine crashed on '{}'", self.command)?;
+        if let Some(status) = self.status {
+            write!(f, " ({})", status)?;
+        }
+        for line in self.stderr.iter() {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error as returned by the [`Supervisor`](struct.Supervisor.html).
+#[derive(Debug)]
+pub enum Error {
+    /// Communicating with the engine failed, without a crash,
+    /// for instance a timeout.
+    Engine(controller::Error),
+    /// The engine crashed and was not restarted, because of the
+    /// `max_restarts` of the policy.
+    GaveUp(CrashReport),
+    /// The engine crashed, was restarted, and the command was not sent
+    /// again, because `retry` of the policy is off.
+    Crashed(CrashReport),
+    /// Starting the engine failed.
+    Start(controller::Error),
+    /// Restoring the game failed on the command.
+    Restore(String, String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Engine(e)         => write!(f, "engine failed: {:?}", e),
+            Error::GaveUp(report)    => write!(f, "giving up, {}", report),
+            Error::Crashed(report)   => write!(f, "{}", report),
+            Error::Start(e)          => write!(f, "couldn't start engine: {:?}", e),
+            Error::Restore(cmd, msg) => write!(f, "couldn't restore game, '{}' failed: {}", cmd, msg),
+        }
+    }
+}
+
+/// The game, as far as needed to restore it.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Game {
+    size:   Option<String>,
+    komi:   Option<String>,
+    /// The commands playing the moves, like `play b D4`.
+    moves:  Vec<String>,
+}
+
+impl Game {
+    /// Updates the game after the successful command `name args`.
+    fn track(&mut self, name: &str, args: &str, response: &str) {
+        let response = response.trim();
+        match name {
+            "boardsize"   => { self.size = Some(args.to_string()); self.moves.clear(); },
+            "clear_board" => self.moves.clear(),
+            "komi"        => self.komi = Some(args.to_string()),
+            "play" | "fixed_handicap" | "set_free_handicap" =>
+                self.moves.push(format!("{} {}", name, args)),
+            "loadsgf" => {
+                self.moves.clear();
+                self.moves.push(format!("{} {}", name, args));
+            },
+            "genmove" if !response.eq_ignore_ascii_case("resign") =>
+                self.moves.push(format!("play {} {}", args, response)),
+            "place_free_handicap" =>
+                self.moves.push(format!("set_free_handicap {}", response)),
+            "undo" => { self.moves.pop(); },
+            _ => (),
+        }
+    }
+
+    /// Returns the commands restoring the game on a fresh engine.
+    fn commands(&self) -> Vec<String> {
+        let mut cmds = vec![];
+        if let Some(size) = &self.size { cmds.push(format!("boardsize {}", size)); }
+        cmds.push(String::from("clear_board"));
+        if let Some(komi) = &self.komi { cmds.push(format!("komi {}", komi)); }
+        cmds.extend(self.moves.iter().cloned());
+        cmds
+    }
+}
+
+fn command(line: &str) -> Command {
+    let mut parts = line.splitn(2, ' ');
+    let mut cmd   = Command::new(parts.next().unwrap_or(""));
+    if let Some(args) = parts.next() {
+        cmd.args(|e| e.s(args));
+    }
+    cmd
+}
+
+/// Starts the engines for a [`Supervisor`](struct.Supervisor.html).
+pub type Factory = Box<dyn FnMut() -> Result<Engine, controller::Error> + Send>;
+
+/// Restarts a crashing engine, see the [module documentation](index.html).
+pub struct Supervisor {
+    engine:     Engine,
+    factory:    Factory,
+    policy:     RestartPolicy,
+    game:       Game,
+    crashes:    Vec<CrashReport>,
+}
+
+impl Supervisor {
+    /// Starts the engine binary with the arguments.
+    pub fn new(cmd: &str, args: &[&str], policy: RestartPolicy) -> Result<Supervisor, Error> {
+        let cmd  = cmd.to_string();
+        let args : Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        Supervisor::with_factory(Box::new(move || {
+            let args : Vec<&str> = args.iter().map(|s| &s[..]).collect();
+            let mut engine = Engine::new(&cmd, &args);
+            engine.start()?;
+            Ok(engine)
+        }), policy)
+    }
+
+    /// Uses `factory` for starting the engine, initially and after each
+    /// crash. The engines must be started already.
+    pub fn with_factory(mut factory: Factory, policy: RestartPolicy) -> Result<Supervisor, Error> {
+        let engine = factory().map_err(Error::Start)?;
+        Ok(Supervisor { engine, factory, policy, game: Game::default(), crashes: vec![] })
+    }
+
+    /// Returns the engine. Commands sent directly to it aren't
+    /// tracked for restoring the game.
+    pub fn engine(&mut self) -> &mut Engine { &mut self.engine }
+
+    /// Returns the reports of all crashes so far.
+    pub fn crashes(&self) -> &[CrashReport] { &self.crashes }
+
+    /// Sends a command and waits up to `timeout` for the response,
+    /// restarting the engine if it crashes.
+    pub fn send_wait(&mut self, cmd: Command, timeout: Duration) -> Result<Response, Error> {
+        let line = cmd.to_string().trim().to_string();
+        let mut parts = line.splitn(2, ' ');
+        let name = parts.next().unwrap_or("").to_string();
+        let args = parts.next().unwrap_or("").to_string();
+
+        let mut cmd = Some(cmd);
+        loop {
+            let c = cmd.take().unwrap_or_else(|| command(&line));
+            match self.engine.send_wait(c, timeout) {
+                Ok(resp) => {
+                    if let Response::Result((_, text)) = &resp {
+                        self.game.track(&name, &args, text);
+                    }
+                    return Ok(resp);
+                },
+                Err(controller::Error::ProcessError(_)) => {
+                    let report = self.restart(&line)?;
+                    if !self.policy.retry { return Err(Error::Crashed(report)); }
+                },
+                Err(e) => return Err(Error::Engine(e)),
+            }
+        }
+    }
+
+    /// Records the crash, starts a new engine and restores the game.
+    fn restart(&mut self, line: &str) -> Result<CrashReport, Error> {
+        let stderr = self.engine.stderr();
+        let lines  : Vec<&str> = stderr.lines().collect();
+        let skip   = lines.len().saturating_sub(self.policy.stderr_lines);
+        let report = CrashReport {
+            command: line.to_string(),
+            status:  self.engine.stop(Duration::from_millis(100)).ok().flatten(),
+            stderr:  lines[skip..].iter().map(|l| l.to_string()).collect(),
+        };
+
+        let restarts = self.crashes.len();
+        self.crashes.push(report.clone());
+        if restarts >= self.policy.max_restarts {
+            return Err(Error::GaveUp(report));
+        }
+
+        std::thread::sleep(self.policy.delay(restarts));
+        self.engine = (self.factory)().map_err(Error::Start)?;
+
+        if self.policy.restore {
+            for line in self.game.commands() {
+                match self.engine.send_wait(command(&line), self.policy.timeout) {
+                    Ok(Response::Result(_))       => (),
+                    Ok(Response::Error((_, msg))) => return Err(Error::Restore(line, msg)),
+                    Err(e) => return Err(Error::Restore(line, format!("{:?}", e))),
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Stops the engine, see
+    /// [`Engine::stop()`](../controller/struct.Engine.html#method.stop).
+    pub fn stop(&mut self, grace: Duration) -> Result<Option<ExitStatus>, Error> {
+        self.engine.stop(grace).map_err(Error::Engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+
+    #[test]
+    fn check_restart() {
+        let mocks = [
+            MockEngine::new(Script::new()
+                .expect("boardsize 9").respond("")
+                .expect("komi 6.5").respond("")
+                .expect("play b E5").respond("")
+                .expect("genmove w").respond("C3")
+                .expect("undo").respond("")
+                .expect("genmove w").respond("G7")
+                .expect("genmove b").stderr("loading\nSegmentation fault\n").crash()),
+            MockEngine::new(Script::new()
+                .expect("boardsize 9").respond("")
+                .expect("clear_board").respond("")
+                .expect("komi 6.5").respond("")
+                .expect("play b E5").respond("")
+                .expect("play w G7").respond("")
+                .expect("genmove b").crash()),
+            MockEngine::new(Script::new()
+                .expect("boardsize 9").respond("")
+                .expect("clear_board").respond("")
+                .expect("komi 6.5").respond("")
+                .expect("play b E5").respond("")
+                .expect("play w G7").respond("")
+                .expect("genmove b").respond("C7")),
+        ];
+
+        let engines : Vec<Engine> = mocks.iter().map(|m| m.engine()).collect();
+        let mut engines = engines.into_iter();
+        let mut policy  = RestartPolicy::new();
+        policy.backoff      = Duration::from_millis(0);
+        policy.stderr_lines = 1;
+
+        let mut sup = Supervisor::with_factory(
+            Box::new(move || engines.next().ok_or(controller::Error::NoHandle)),
+            policy.clone()).unwrap();
+
+        let t = Duration::from_secs(1);
+        sup.send_wait(Command::cmd("boardsize", |e| e.i(9)), t).unwrap();
+        sup.send_wait(Command::cmd("komi", |e| e.f(6.5)), t).unwrap();
+        sup.send_wait(Command::cmd("play", |e| e.mv_b((5, 5))), t).unwrap();
+        sup.send_wait(Command::cmd("genmove", |e| e.w()), t).unwrap();
+        sup.send_wait(Command::new("undo"), t).unwrap();
+        sup.send_wait(Command::cmd("genmove", |e| e.w()), t).unwrap();
+        let resp = sup.send_wait(Command::cmd("genmove", |e| e.b()), t).unwrap();
+        assert_eq!(resp.text(), "C7");
+
+        assert_eq!(sup.crashes().len(), 2);
+        assert_eq!(sup.crashes()[0].command, "genmove b");
+        assert_eq!(sup.crashes()[0].stderr, vec!["Segmentation fault"]);
+        assert_eq!(sup.crashes()[0].to_string(), "engine crashed on 'genmove b'\n  Segmentation fault");
+        for m in mocks.iter() { m.assert_done(); }
+
+        let mock = MockEngine::new(Script::new().expect("name").crash());
+        let mut engines = vec![mock.engine()].into_iter();
+        policy.max_restarts = 0;
+        let mut sup = Supervisor::with_factory(
+            Box::new(move || engines.next().ok_or(controller::Error::NoHandle)),
+            policy).unwrap();
+        assert!(matches!(sup.send_wait(Command::new("name"), t), Err(Error::GaveUp(_))));
+    }
+}
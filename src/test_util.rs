@@ -0,0 +1,34 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+//! Helpers shared by the unit tests.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A fresh directory in the temporary directory of the system,
+/// removed with its contents when dropped.
+pub struct TempDir {
+    path:   PathBuf,
+}
+
+impl TempDir {
+    /// Creates the directory `gtp-<name>-<process id>`.
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("gtp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Returns the path of a file in the directory.
+    pub fn join(&self, file: &str) -> PathBuf { self.path.join(file) }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
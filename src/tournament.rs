@@ -0,0 +1,681 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Round-robin and gauntlet tournaments between many engines, with ratings.
+
+The games of a tournament are scheduled in rounds. In a round-robin
+tournament every participant plays every other one in each round, in a
+gauntlet the first participant plays all others. The colors of a pairing
+swap from round to round.
+
+The games are played by a configurable number of worker threads with
+[`arbiter::play_game()`](../arbiter/fn.play_game.html). Each worker keeps
+the engines it has started for the following games, and restarts them
+after a forfeit or a loss on time. Engines are stopped with `quit`, and
+killed if they don't exit within the command timeout of the game.
+
+Finished games are appended to the results file, one line per game:
+
+```text
+# gtp-rs tournament results
+<game number>\t<black>\t<white>\t<result>\t<moves>\t<seconds>\t<note>
+```
+
+If the results file already exists, its games are not played again, so an
+interrupted tournament resumes where it stopped.
+
+The ratings are maximum likelihood Elo ratings in the style of BayesElo,
+see [`ratings()`](fn.ratings.html).
+
+```no_run
+use gtp::arbiter::EngineSpec;
+use gtp::tournament::{Participant, Tournament, TournamentConfig};
+
+let mut cfg = TournamentConfig::new(vec![
+    Participant::parse("weak=gnugo --mode gtp --level 1").unwrap(),
+    Participant::parse("strong=gnugo --mode gtp --level 10").unwrap(),
+    Participant::parse("default=gnugo --mode gtp").unwrap(),
+]);
+cfg.workers = 4;
+cfg.results = Some("results.txt".into());
+
+let mut t = Tournament::new(cfg).unwrap();
+t.run(|game| println!("{}", game)).unwrap();
+print!("{}", t.standings());
+```
+*/
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::arbiter;
+use super::arbiter::EngineSpec;
+use super::arbiter::GameConfig;
+use super::arbiter::GameResult;
+use super::arbiter::Players;
+use super::arbiter::WinReason;
+use super::controller::Engine;
+use super::Color;
+
+/// An engine taking part in a tournament.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participant {
+    /// The name used in the results file and the standings, must not
+    /// contain whitespace.
+    pub name:   String,
+    pub spec:   EngineSpec,
+}
+
+impl Participant {
+    /// Parses `<name>=<command line>`.
+    ///
+    /// ```
+    /// let p = gtp::tournament::Participant::parse("gg1=gnugo --mode gtp --level 1").unwrap();
+    /// assert_eq!(p.name, "gg1");
+    /// assert_eq!(p.spec.args, vec!["--mode", "gtp", "--level", "1"]);
+    /// ```
+    pub fn parse(s: &str) -> Result<Participant, String> {
+        let eq = match s.find('=') {
+            Some(eq) => eq,
+            None     => return Err(format!("expected <name>=<command> in '{}'", s)),
+        };
+        let name = s[..eq].trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("bad participant name '{}'", name));
+        }
+        Ok(Participant { name: name.to_string(), spec: EngineSpec::parse(&s[eq + 1..])? })
+    }
+}
+
+/// The pairing scheme of a tournament.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Every participant plays every other one.
+    RoundRobin,
+    /// The first participant plays all others.
+    Gauntlet,
+}
+
+/// Settings of a tournament.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentConfig {
+    pub participants:   Vec<Participant>,
+    pub kind:           Kind,
+    /// The number of rounds, each pairing plays one game per round.
+    pub rounds:         usize,
+    /// The number of games played at the same time.
+    pub workers:        usize,
+    pub game:           GameConfig,
+    /// Engine used for scoring the games, each worker starts its own.
+    pub referee:        Option<EngineSpec>,
+    /// The file the results are written to and resumed from.
+    pub results:        Option<PathBuf>,
+    /// Directory for the SGF files, named `game-<number>.sgf`.
+    pub sgf_dir:        Option<PathBuf>,
+}
+
+impl TournamentConfig {
+    /// Creates the config of a round-robin tournament with 2 rounds
+    /// and one worker.
+    pub fn new(participants: Vec<Participant>) -> TournamentConfig {
+        TournamentConfig {
+            participants,
+            kind:       Kind::RoundRobin,
+            rounds:     2,
+            workers:    1,
+            game:       GameConfig::default(),
+            referee:    None,
+            results:    None,
+            sgf_dir:    None,
+        }
+    }
+}
+
+/// A scheduled game, with the participants as indices
+/// into `TournamentConfig::participants`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pairing {
+    /// Counted from 1.
+    pub number: usize,
+    pub black:  usize,
+    pub white:  usize,
+}
+
+/// Creates the pairings of a tournament with `n` participants.
+///
+/// ```
+/// use gtp::tournament::{schedule, Kind};
+/// let games = schedule(Kind::Gauntlet, 3, 2);
+/// let pairs : Vec<(usize, usize)> = games.iter().map(|p| (p.black, p.white)).collect();
+/// assert_eq!(pairs, vec![(0, 1), (0, 2), (1, 0), (2, 0)]);
+/// ```
+pub fn schedule(kind: Kind, n: usize, rounds: usize) -> Vec<Pairing> {
+    let mut pairs = vec![];
+    match kind {
+        Kind::RoundRobin => {
+            for i in 0..n {
+                for j in (i + 1)..n { pairs.push((i, j)); }
+            }
+        },
+        Kind::Gauntlet => {
+            for j in 1..n { pairs.push((0, j)); }
+        },
+    }
+
+    let mut games = vec![];
+    for round in 0..rounds {
+        for (a, b) in pairs.iter() {
+            let (black, white) = if round % 2 == 0 { (*a, *b) } else { (*b, *a) };
+            games.push(Pairing { number: games.len() + 1, black, white });
+        }
+    }
+    games
+}
+
+/// A finished game of a tournament.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TournamentGame {
+    pub pairing:    Pairing,
+    pub black:      String,
+    pub white:      String,
+    pub result:     GameResult,
+    pub moves:      usize,
+    pub elapsed:    Duration,
+    pub note:       String,
+}
+
+impl TournamentGame {
+    /// Formats the game as a line of the results file.
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{:.1}\t{}",
+                self.pairing.number, self.black, self.white, self.result,
+                self.moves, self.elapsed.as_secs_f64(),
+                self.note.replace(['\t', '\n'], " "))
+    }
+
+    /// Parses a line of the results file.
+    pub fn parse_line(line: &str, participants: &[Participant]) -> Result<TournamentGame, String> {
+        let f : Vec<&str> = line.splitn(7, '\t').collect();
+        if f.len() < 6 {
+            return Err(format!("bad results line '{}'", line));
+        }
+
+        let index = |name: &str| {
+            participants.iter().position(|p| p.name == name)
+                .ok_or_else(|| format!("unknown participant '{}'", name))
+        };
+        let number  = f[0].parse::<usize>().map_err(|_| format!("bad game number '{}'", f[0]))?;
+        let result  = GameResult::parse(f[3]);
+        let moves   = f[4].parse::<usize>().map_err(|_| format!("bad move count '{}'", f[4]))?;
+        let secs    = f[5].parse::<f64>().map_err(|_| format!("bad time '{}'", f[5]))?;
+
+        Ok(TournamentGame {
+            pairing:    Pairing { number, black: index(f[1])?, white: index(f[2])? },
+            black:      f[1].to_string(),
+            white:      f[2].to_string(),
+            result:     if f[3] == "?" { GameResult::Unknown } else {
+                            result.ok_or_else(|| format!("bad result '{}'", f[3]))?
+                        },
+            moves,
+            elapsed:    Duration::from_secs_f64(secs.max(0.0)),
+            note:       f.get(6).unwrap_or(&"").to_string(),
+        })
+    }
+
+    /// Returns the points of the black player: 1 for a win, 0.5 for
+    /// a draw and `None` if the result is unknown.
+    pub fn black_score(&self) -> Option<f64> {
+        match self.result {
+            GameResult::Win(Color::B, _) => Some(1.0),
+            GameResult::Win(Color::W, _) => Some(0.0),
+            GameResult::Draw             => Some(0.5),
+            GameResult::Unknown          => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TournamentGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:>4} {:<16} {:<16} {:<7} {:>5} {:>8.1}s",
+               self.pairing.number, self.black, self.white,
+               self.result.to_string(), self.moves,
+               self.elapsed.as_secs_f64())?;
+        if !self.note.is_empty() { write!(f, " {}", self.note)?; }
+        Ok(())
+    }
+}
+
+/// The rating of a participant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rating {
+    pub name:   String,
+    /// The Elo rating, the average of all participants is 0.
+    pub elo:    f64,
+    /// Half the width of the approximate 95% confidence interval.
+    pub error:  f64,
+    pub games:  usize,
+    /// Points scored, 1 per win and 0.5 per draw.
+    pub points: f64,
+}
+
+/// The ratings of all participants, best first.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Standings {
+    pub ratings:    Vec<Rating>,
+}
+
+impl std::fmt::Display for Standings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{:>4} {:<16} {:>6} {:>6} {:>6} {:>7}",
+                 "Rank", "Name", "Elo", "+/-", "Games", "Score")?;
+        for (i, r) in self.ratings.iter().enumerate() {
+            let score = if r.games > 0 { 100.0 * r.points / r.games as f64 } else { 0.0 };
+            writeln!(f, "{:>4} {:<16} {:>6.0} {:>6.0} {:>6} {:>6.1}%",
+                     i + 1, r.name, r.elo, r.error, r.games, score)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of virtual draws added between participants that played
+/// each other, like the prior of BayesElo. Keeps the ratings finite
+/// if a participant won or lost all games.
+const PRIOR_DRAWS : f64 = 2.0;
+
+const ELO_PER_NEPER : f64 = 400.0 / std::f64::consts::LN_10;
+
+/// Computes maximum likelihood Elo ratings of `n` participants from games
+/// given as `(black, white, points of black)`, with the participants as
+/// indices into `names`.
+///
+/// ```
+/// let games = vec![(0, 1, 1.0), (1, 0, 0.0), (0, 1, 0.5), (1, 0, 1.0)];
+/// let s = gtp::tournament::ratings(&["a", "b"], &games);
+/// assert_eq!(s.ratings[0].name, "a");
+/// assert!(s.ratings[0].elo > 0.0 && s.ratings[1].elo < 0.0);
+/// ```
+pub fn ratings<S: AsRef<str>>(names: &[S], games: &[(usize, usize, f64)]) -> Standings {
+    let n = names.len();
+    let mut count   = vec![vec![0.0; n]; n];
+    let mut points  = vec![0.0; n];
+    let mut played  = vec![0; n];
+
+    for (b, w, score) in games.iter() {
+        count[*b][*w] += 1.0;
+        count[*w][*b] += 1.0;
+        points[*b] += score;
+        points[*w] += 1.0 - score;
+        played[*b] += 1;
+        played[*w] += 1;
+    }
+
+    let mut wins = points.clone();
+    for (i, row) in count.iter_mut().enumerate() {
+        for (j, c) in row.iter_mut().enumerate() {
+            if i != j && *c > 0.0 {
+                *c      += PRIOR_DRAWS;
+                wins[i] += PRIOR_DRAWS / 2.0;
+            }
+        }
+    }
+
+    // Minorization-maximization for the Bradley-Terry model,
+    // see Hunter (2004), "MM algorithms for generalized Bradley-Terry models".
+    let mut gamma = vec![1.0; n];
+    for _ in 0..10000 {
+        let mut max_change : f64 = 0.0;
+        for i in 0..n {
+            let denom : f64 =
+                (0..n).filter(|j| *j != i)
+                      .map(|j| count[i][j] / (gamma[i] + gamma[j]))
+                      .sum();
+            if denom <= 0.0 { continue; }
+            let g = wins[i] / denom;
+            max_change = max_change.max((g.ln() - gamma[i].ln()).abs());
+            gamma[i] = g;
+        }
+        if max_change < 1e-9 { break; }
+    }
+
+    let logs : Vec<f64> = gamma.iter().map(|g| g.ln()).collect();
+    let mean = logs.iter().sum::<f64>() / n.max(1) as f64;
+
+    let mut standings = Standings::default();
+    for i in 0..n {
+        let info : f64 =
+            (0..n).filter(|j| *j != i).map(|j| {
+                let p = gamma[i] / (gamma[i] + gamma[j]);
+                count[i][j] * p * (1.0 - p)
+            }).sum();
+        let error = if info > 0.0 { 1.96 * ELO_PER_NEPER / info.sqrt() } else { f64::INFINITY };
+
+        standings.ratings.push(Rating {
+            name:   names[i].as_ref().to_string(),
+            elo:    (logs[i] - mean) * ELO_PER_NEPER,
+            error,
+            games:  played[i],
+            points: points[i],
+        });
+    }
+    standings.ratings.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap_or(std::cmp::Ordering::Equal));
+    standings
+}
+
+/// Errors that stop a tournament.
+#[derive(Debug)]
+pub enum Error {
+    /// A game could not be played.
+    Game(Pairing, arbiter::Error),
+    /// The results file could not be read or written.
+    Io(std::io::Error),
+    /// The results file is broken or doesn't match the tournament.
+    BadResults(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Game(p, e)     => write!(f, "game {}: {}", p.number, e),
+            Error::Io(e)          => write!(f, "results file: {}", e),
+            Error::BadResults(s)  => write!(f, "results file: {}", s),
+        }
+    }
+}
+
+/// A tournament with its finished games.
+pub struct Tournament {
+    cfg:        TournamentConfig,
+    schedule:   Vec<Pairing>,
+    games:      Vec<TournamentGame>,
+}
+
+const RESULTS_HEADER : &str = "# gtp-rs tournament results";
+
+impl Tournament {
+    /// Creates the tournament and reads the games of the results file,
+    /// if it exists.
+    pub fn new(cfg: TournamentConfig) -> Result<Tournament, Error> {
+        let schedule = schedule(cfg.kind, cfg.participants.len(), cfg.rounds);
+        let mut t = Tournament { cfg, schedule, games: Vec::new() };
+
+        let path = match &t.cfg.results {
+            Some(path) if path.exists() => path.clone(),
+            _ => return Ok(t),
+        };
+        let text = std::fs::read_to_string(&path).map_err(Error::Io)?;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') { continue; }
+
+            let game =
+                TournamentGame::parse_line(line, &t.cfg.participants)
+                    .map_err(|e| Error::BadResults(format!("line {}: {}", i + 1, e)))?;
+            if !t.schedule.contains(&game.pairing) {
+                return Err(Error::BadResults(format!(
+                    "line {}: game {} doesn't match the tournament",
+                    i + 1, game.pairing.number)));
+            }
+            if t.is_played(game.pairing.number) {
+                return Err(Error::BadResults(format!(
+                    "line {}: game {} is listed twice", i + 1, game.pairing.number)));
+            }
+            t.games.push(game);
+        }
+        Ok(t)
+    }
+
+    fn is_played(&self, number: usize) -> bool {
+        self.games.iter().any(|g| g.pairing.number == number)
+    }
+
+    /// Returns the finished games, in the order they finished.
+    pub fn games(&self) -> &[TournamentGame] { &self.games }
+
+    /// Returns the games that were not played yet.
+    pub fn pending(&self) -> Vec<Pairing> {
+        self.schedule.iter().filter(|p| !self.is_played(p.number)).cloned().collect()
+    }
+
+    /// Computes the ratings from the finished games,
+    /// games with unknown result are ignored.
+    pub fn standings(&self) -> Standings {
+        let names : Vec<&str> = self.cfg.participants.iter().map(|p| &p.name[..]).collect();
+        let games : Vec<(usize, usize, f64)> =
+            self.games.iter()
+                .filter_map(|g| Some((g.pairing.black, g.pairing.white, g.black_score()?)))
+                .collect();
+        ratings(&names, &games)
+    }
+
+    fn record(&mut self, game: TournamentGame) -> Result<(), Error> {
+        if let Some(path) = &self.cfg.results {
+            let is_new = !path.exists();
+            let mut f =
+                std::fs::OpenOptions::new()
+                    .create(true).append(true).open(path)
+                    .map_err(Error::Io)?;
+            if is_new { writeln!(f, "{}", RESULTS_HEADER).map_err(Error::Io)?; }
+            writeln!(f, "{}", game.to_line()).map_err(Error::Io)?;
+        }
+        self.games.push(game);
+        Ok(())
+    }
+
+    /// Plays the pending games and calls `on_game` after each game.
+    pub fn run<F>(&mut self, on_game: F) -> Result<(), Error>
+        where F: FnMut(&TournamentGame)
+    {
+        self.run_with(|spec| spec.start(), on_game)
+    }
+
+    /// Like `run()`, but starts the engines with `start`, which gets the
+    /// spec of a participant or the referee.
+    ///
+    /// If a game can't be played, the workers stop after their current
+    /// game and the first error is returned.
+    pub fn run_with<S, F>(&mut self, start: S, mut on_game: F) -> Result<(), Error>
+        where S: Fn(&EngineSpec) -> Result<Engine, arbiter::Error> + Sync,
+              F: FnMut(&TournamentGame)
+    {
+        let cfg   = self.cfg.clone();
+        let queue = Mutex::new(self.pending().into_iter().collect::<VecDeque<_>>());
+        let stop  = AtomicBool::new(false);
+        let (tx, rx) = mpsc::channel();
+        let mut error = None;
+
+        std::thread::scope(|s| {
+            for _ in 0..cfg.workers.max(1) {
+                let tx = tx.clone();
+                let (cfg, queue, stop, start) = (&cfg, &queue, &stop, &start);
+                s.spawn(move || worker(cfg, queue, stop, start, tx));
+            }
+            drop(tx);
+
+            for res in rx {
+                let res = res.and_then(|game| {
+                    self.record(game.clone())?;
+                    on_game(&game);
+                    Ok(())
+                });
+                if let Err(e) = res {
+                    stop.store(true, Ordering::SeqCst);
+                    error.get_or_insert(e);
+                }
+            }
+        });
+
+        match error {
+            Some(e) => Err(e),
+            None    => Ok(()),
+        }
+    }
+}
+
+fn worker<S>(cfg: &TournamentConfig, queue: &Mutex<VecDeque<Pairing>>, stop: &AtomicBool,
+             start: &S, tx: mpsc::Sender<Result<TournamentGame, Error>>)
+    where S: Fn(&EngineSpec) -> Result<Engine, arbiter::Error>
+{
+    let mut engines = HashMap::new();
+    let mut referee = None;
+
+    while !stop.load(Ordering::SeqCst) {
+        let pairing = match queue.lock().unwrap().pop_front() {
+            Some(p) => p,
+            None    => break,
+        };
+
+        let res =
+            play(cfg, &pairing, &mut engines, &mut referee, start)
+                .map_err(|e| Error::Game(pairing, e));
+        let failed = res.is_err();
+        if tx.send(res).is_err() || failed { break; }
+    }
+
+    let grace = cfg.game.command_timeout;
+    for (_, mut e) in engines.drain() { let _ = e.stop(grace); }
+    if let Some(mut r) = referee { let _ = r.stop(grace); }
+}
+
+fn play<S>(cfg: &TournamentConfig, pairing: &Pairing, engines: &mut HashMap<usize, Engine>,
+           referee: &mut Option<Engine>, start: &S) -> Result<TournamentGame, arbiter::Error>
+    where S: Fn(&EngineSpec) -> Result<Engine, arbiter::Error>
+{
+    for idx in [pairing.black, pairing.white].iter() {
+        if !engines.contains_key(idx) {
+            engines.insert(*idx, start(&cfg.participants[*idx].spec)?);
+        }
+    }
+    if let (Some(spec), None) = (&cfg.referee, &referee) {
+        *referee = Some(start(spec)?);
+    }
+
+    let names = (&cfg.participants[pairing.black].name, &cfg.participants[pairing.white].name);
+    let mut black = engines.remove(&pairing.black).unwrap();
+    let mut white = engines.remove(&pairing.white).unwrap();
+
+    let mut players = Players {
+        black:   (names.0, &mut black),
+        white:   (names.1, &mut white),
+        referee: referee.as_mut().map(|r| ("referee", r)),
+    };
+    let mut game = arbiter::play_game(&mut players, &cfg.game)?;
+
+    // After a forfeit or a loss on time the engines may be in any
+    // state, or still thinking. They are restarted for the next game.
+    if let GameResult::Win(_, WinReason::Forfeit) | GameResult::Win(_, WinReason::Time) = game.result {
+        let _ = black.stop(cfg.game.command_timeout);
+        let _ = white.stop(cfg.game.command_timeout);
+    } else {
+        engines.insert(pairing.black, black);
+        engines.insert(pairing.white, white);
+    }
+
+    game.record.black = names.0.clone();
+    game.record.white = names.1.clone();
+    if let Some(dir) = &cfg.sgf_dir {
+        let path = dir.join(format!("game-{}.sgf", pairing.number));
+        game.record.save(&path).map_err(arbiter::Error::Io)?;
+    }
+
+    Ok(TournamentGame {
+        pairing:    *pairing,
+        black:      names.0.clone(),
+        white:      names.1.clone(),
+        result:     game.result,
+        moves:      game.record.moves.len(),
+        elapsed:    game.elapsed,
+        note:       game.note,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockEngine, Script};
+    use crate::test_util::TempDir;
+
+    fn setup(s: Script) -> Script {
+        s.expect("boardsize 9").respond("")
+         .expect("clear_board").respond("")
+         .expect("komi 7").respond("")
+    }
+
+    #[test]
+    fn check_run_and_resume() {
+        // "a" wins all games but the third one, which it loses on time.
+        // It wins the second one by "b" sending garbage. After the forfeit
+        // and the loss on time both engines are restarted.
+        let a = setup(Script::new()).expect("genmove b").respond("E5");
+        let a = setup(a).expect("quit").respond("");
+        let a = setup(a).expect("genmove b").delay(Duration::from_millis(300)).respond("A1")
+                        .expect("quit").respond("");
+        let a = setup(a).expect("quit").respond("");
+        let b = setup(Script::new()).expect("play b E5").respond("")
+                                    .expect("genmove w").respond("resign");
+        let b = setup(b).expect("genmove b").respond("Z99").expect("quit").respond("");
+        let b = setup(b).expect("quit").respond("");
+        let b = setup(b).expect("genmove b").respond("resign").expect("quit").respond("");
+        let (a, b) = (MockEngine::new(a), MockEngine::new(b));
+
+        let dir  = TempDir::new("tournament");
+        let path = dir.join("results.txt");
+
+        let mut cfg = TournamentConfig::new(vec![
+            Participant::parse("a=a").unwrap(),
+            Participant::parse("b=b").unwrap(),
+        ]);
+        cfg.rounds  = 4;
+        cfg.results = Some(path.clone());
+        cfg.game    = GameConfig {
+            size:               9,
+            komi:               7.0,
+            move_timeout:       Duration::from_millis(200),
+            command_timeout:    Duration::from_millis(200),
+            ..GameConfig::default()
+        };
+
+        let mut t = Tournament::new(cfg.clone()).unwrap();
+        assert_eq!(t.pending().len(), 4);
+        let res = t.run_with(|spec| Ok(if spec.cmd == "a" { a.engine() } else { b.engine() }), |_| ());
+        assert!(res.is_ok());
+        assert_eq!(t.games().len(), 4);
+        assert_eq!(t.games()[0].result.to_string(), "B+R");
+        assert_eq!(t.games()[1].result.to_string(), "W+F");
+        assert_eq!(t.games()[2].result.to_string(), "W+T");
+        assert_eq!(t.games()[3].result.to_string(), "W+R");
+
+        let s = t.standings();
+        assert_eq!(s.ratings[0].name, "a");
+        assert_eq!(s.ratings[0].points, 3.0);
+        a.assert_done();
+        b.assert_done();
+
+        let t = Tournament::new(cfg.clone()).unwrap();
+        assert_eq!(t.games().len(), 4);
+        assert!(t.pending().is_empty());
+        assert_eq!(t.standings(), s);
+
+        cfg.participants[1].name = String::from("c");
+        assert!(Tournament::new(cfg).is_err());
+    }
+
+    #[test]
+    fn check_ratings() {
+        let games = vec![(0, 1, 1.0); 30];
+        let s = ratings(&["a", "b"], &games);
+        assert!(s.ratings[0].elo > 200.0);
+        assert!((s.ratings[0].elo + s.ratings[1].elo).abs() < 1e-6);
+        assert!(s.ratings[0].error > 0.0 && s.ratings[0].error.is_finite());
+
+        // An equal record gives equal ratings.
+        let s = ratings(&["a", "b", "c"], &[(0, 1, 1.0), (1, 2, 1.0), (2, 0, 1.0)]);
+        for r in s.ratings.iter() { assert!(r.elo.abs() < 1e-6); }
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright (c) 2019 Weird Constructor <weirdconstructor@gmail.com>
+// This is a part of gtp-rs. See README.md and COPYING for details.
+
+/*!
+Recording of GTP sessions and replaying them as a fake engine.
+
+A [`Recorder`](struct.Recorder.html) set with
+[`Engine::set_transcript()`](../controller/struct.Engine.html#method.set_transcript)
+logs everything sent to the engine and every chunk of output received from
+it. A transcript is a text file with a header line followed by one line
+per event:
+
+```text
+# gtp-rs transcript
+0.000000 > 1 name\n
+0.001234 < =1 GNU Go\n\n
+0.001240 ! loading patterns...\n
+```
+
+The first field is the time in seconds since the recording started, from
+a monotonic clock. The second field is the direction: `>` for text sent
+to the engine, `<` for its stdout and `!` for its stderr. The rest of the
+line, after a single space, is the text, with `\`, newline, carriage return
+and tab escaped as `\\`, `\n`, `\r` and `\t`.
+
+A [`Replay`](struct.Replay.html) is a
+[`Transport`](../detached_command/trait.Transport.html) playing back a
+transcript: each time the controller sends something, the output that was
+recorded after the corresponding send is returned. This way a session with
+a misbehaving engine can be reproduced without the engine:
+
+```no_run
+use std::time::Duration;
+use gtp::Command;
+use gtp::controller::Engine;
+use gtp::transcript::{Recorder, Replay};
+
+let mut engine = Engine::new("gnugo", &["--mode", "gtp"]);
+engine.start().unwrap();
+engine.set_transcript(Some(Recorder::create("session.txt").unwrap()));
+engine.send_wait(Command::new("name"), Duration::from_secs(1)).unwrap();
+
+let replay = Replay::load("session.txt").unwrap();
+let mut fake = Engine::with_transport(Box::new(replay));
+let resp = fake.send_wait(Command::new("name"), Duration::from_secs(1)).unwrap();
+assert_eq!(resp.text(), "GNU Go");
+```
+*/
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::detached_command::Error;
+use super::detached_command::Transport;
+
+/// The header line of a transcript file.
+pub const HEADER : &str = "# gtp-rs transcript";
+
+/// The direction of a transcript entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// Sent to the engine.
+    Sent,
+    /// Received on stdout.
+    Stdout,
+    /// Received on stderr.
+    Stderr,
+}
+
+impl Direction {
+    fn symbol(self) -> char {
+        match self {
+            Direction::Sent   => '>',
+            Direction::Stdout => '<',
+            Direction::Stderr => '!',
+        }
+    }
+}
+
+/// An event of a transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The time since the recording started.
+    pub time:   Duration,
+    pub dir:    Direction,
+    pub text:   String,
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c    => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut out   = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' { out.push(c); continue; }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('n')  => out.push('\n'),
+            Some('r')  => out.push('\r'),
+            Some('t')  => out.push('\t'),
+            Some(c)    => return Err(format!("bad escape '\\{}'", c)),
+            None       => return Err(String::from("escape at end of line")),
+        }
+    }
+    Ok(out)
+}
+
+impl Entry {
+    /// Formats the entry as a transcript line, without the newline.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use gtp::transcript::{Direction, Entry};
+    /// let e = Entry { time: Duration::from_millis(1500), dir: Direction::Sent,
+    ///                 text: String::from("1 name\n") };
+    /// assert_eq!(e.to_line(), "1.500000 > 1 name\\n");
+    /// assert_eq!(Entry::parse_line(&e.to_line()).unwrap(), e);
+    /// ```
+    pub fn to_line(&self) -> String {
+        format!("{}.{:06} {} {}",
+                self.time.as_secs(), self.time.subsec_micros(),
+                self.dir.symbol(), escape(&self.text))
+    }
+
+    /// Parses a transcript line.
+    pub fn parse_line(line: &str) -> Result<Entry, String> {
+        let mut parts = line.splitn(3, ' ');
+        let time = parts.next().unwrap_or("");
+        let dir  = parts.next().unwrap_or("");
+        let text = parts.next().ok_or_else(|| format!("bad transcript line '{}'", line))?;
+
+        let time =
+            time.parse::<f64>().ok()
+                .filter(|t| *t >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("bad time '{}'", time))?;
+        let dir =
+            match dir {
+                ">" => Direction::Sent,
+                "<" => Direction::Stdout,
+                "!" => Direction::Stderr,
+                _   => return Err(format!("bad direction '{}'", dir)),
+            };
+        Ok(Entry { time, dir, text: unescape(text)? })
+    }
+}
+
+/// Parses a transcript. Empty lines and lines starting with `#` are
+/// ignored.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') { continue; }
+        entries.push(Entry::parse_line(line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+    }
+    Ok(entries)
+}
+
+/// Reads a transcript file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>, String> {
+    let path = path.as_ref();
+    let text =
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read '{}': {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Writes transcript entries, see the [module documentation](index.html).
+pub struct Recorder {
+    out:    Box<dyn Write + Send>,
+    start:  Instant,
+}
+
+impl Recorder {
+    /// Starts a transcript written to `out`. The times are relative to now.
+    pub fn new(mut out: Box<dyn Write + Send>) -> Recorder {
+        let _ = writeln!(out, "{}", HEADER);
+        Recorder { out, start: Instant::now() }
+    }
+
+    /// Starts a transcript written to a new file.
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Recorder> {
+        let file = std::fs::File::create(path)?;
+        Ok(Recorder::new(Box::new(std::io::BufWriter::new(file))))
+    }
+
+    /// Records an event. Errors writing the transcript are ignored,
+    /// they shouldn't disturb the session.
+    pub fn record(&mut self, dir: Direction, text: &str) {
+        if text.is_empty() { return; }
+        let entry = Entry { time: self.start.elapsed(), dir, text: text.to_string() };
+        let _ = writeln!(self.out, "{}", entry.to_line());
+        let _ = self.out.flush();
+    }
+}
+
+/// A [`Transport`](../detached_command/trait.Transport.html) that plays a
+/// transcript back, see the [module documentation](index.html).
+pub struct Replay {
+    entries:    VecDeque<Entry>,
+    /// Output that is due at the given instant.
+    queue:      VecDeque<(Instant, Entry)>,
+    stdout:     Vec<String>,
+    stderr:     Vec<String>,
+    realtime:   bool,
+    strict:     bool,
+    failed:     bool,
+}
+
+impl Replay {
+    /// Creates a replay of the entries. The output recorded before the
+    /// first send is available right away.
+    pub fn new(entries: Vec<Entry>) -> Replay {
+        let mut replay = Replay {
+            entries:  entries.into(),
+            queue:    VecDeque::new(),
+            stdout:   vec![],
+            stderr:   vec![],
+            realtime: false,
+            strict:   false,
+            failed:   false,
+        };
+        replay.release(Instant::now(), Duration::from_secs(0));
+        replay
+    }
+
+    /// Creates a replay of a transcript file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, String> {
+        Ok(Replay::new(load(path)?))
+    }
+
+    /// With `realtime` the output is delayed like it was recorded,
+    /// otherwise it is available right after the send.
+    pub fn set_realtime(&mut self, realtime: bool) { self.realtime = realtime; }
+
+    /// With `strict` the replay disconnects when the controller sends
+    /// something else than what was recorded. Otherwise the recorded
+    /// output is replayed regardless.
+    pub fn set_strict(&mut self, strict: bool) { self.strict = strict; }
+
+    /// Returns true if all entries were replayed.
+    pub fn is_done(&self) -> bool { self.entries.is_empty() && self.queue.is_empty() }
+
+    /// Queues the output up to the next send. `since` is the recorded
+    /// time the output is relative to.
+    fn release(&mut self, now: Instant, since: Duration) {
+        while let Some(e) = self.entries.front() {
+            if e.dir == Direction::Sent { break; }
+            let e   = self.entries.pop_front().unwrap();
+            let due = if self.realtime { now + e.time.saturating_sub(since) } else { now };
+            self.queue.push_back((due, e));
+        }
+    }
+}
+
+impl Transport for Replay {
+    fn send(&mut self, buffer: Vec<u8>) {
+        let sent = String::from_utf8_lossy(&buffer).to_string();
+        let now  = Instant::now();
+
+        let expected = match self.entries.pop_front() {
+            Some(e) => e,
+            None    => { if self.strict { self.failed = true; } return; },
+        };
+        if self.strict && expected.text != sent {
+            self.failed = true;
+            return;
+        }
+        self.release(now, expected.time);
+    }
+
+    fn poll(&mut self) -> Result<(), Error> {
+        if self.failed { return Err(Error::Disconnected); }
+
+        let now = Instant::now();
+        while let Some((due, _)) = self.queue.front() {
+            if *due > now { break; }
+            let e = self.queue.pop_front().unwrap().1;
+            match e.dir {
+                Direction::Stderr => self.stderr.push(e.text),
+                _                 => self.stdout.push(e.text),
+            }
+        }
+        Ok(())
+    }
+
+    fn stdout_available(&self) -> bool { !self.stdout.is_empty() }
+    fn stderr_available(&self) -> bool { !self.stderr.is_empty() }
+
+    fn recv_stdout(&mut self) -> String {
+        let ret = self.stdout.join("");
+        self.stdout.clear();
+        ret
+    }
+
+    fn recv_stderr(&mut self) -> String {
+        let ret = self.stderr.join("");
+        self.stderr.clear();
+        ret
+    }
+
+    fn shutdown(&mut self) {
+        self.failed = true;
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::Command;
+    use crate::controller::Engine;
+    use crate::mock::{MockEngine, Script};
+
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn check_record_replay() {
+        let mock = MockEngine::new(Script::new()
+            .expect("name").stderr("hello\\world\n").respond("GNU Go")
+            .expect("genmove b").respond("D4"));
+        let buf = Arc::new(Mutex::new(vec![]));
+
+        let mut engine = mock.engine();
+        engine.set_transcript(Some(Recorder::new(Box::new(Shared(buf.clone())))));
+        let t = Duration::from_secs(1);
+        assert_eq!(engine.send_wait(Command::new("name"), t).unwrap().text(), "GNU Go");
+        assert_eq!(engine.send_wait(Command::cmd("genmove", |e| e.b()), t).unwrap().text(), "D4");
+        mock.assert_done();
+
+        let text = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        assert!(text.starts_with("# gtp-rs transcript\n"));
+        assert!(text.contains(" ! hello\\\\world\\n\n"), "{}", text);
+        let entries = parse(&text).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].text, "1 name\n");
+        assert_eq!(entries[2].text, "=1 GNU Go\n\n");
+
+        let mut replay = Engine::with_transport(Box::new(Replay::new(entries.clone())));
+        assert_eq!(replay.send_wait(Command::new("name"), t).unwrap().text(), "GNU Go");
+        assert_eq!(replay.stderr(), "hello\\world\n");
+        assert_eq!(replay.send_wait(Command::cmd("genmove", |e| e.b()), t).unwrap().text(), "D4");
+
+        let mut r = Replay::new(entries);
+        r.set_strict(true);
+        let mut replay = Engine::with_transport(Box::new(r));
+        assert!(replay.send_wait(Command::new("version"), t).is_err());
+
+        assert!(parse("0.1 ? x").is_err());
+        assert!(parse("0.1 > x\\q").is_err());
+    }
+}